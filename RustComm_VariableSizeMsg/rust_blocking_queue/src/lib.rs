@@ -18,6 +18,7 @@
 #![allow(dead_code)]
 use std::sync::*;
 use std::collections::*;
+use std::time::{Duration, Instant};
 
 #[derive(Debug)]
 /// Thread-safe queue that blocks de_q on empty
@@ -53,15 +54,44 @@ impl<T> BlockingQueue<T> {
     /// - same for condition variable
     pub fn de_q(&self) -> T {
         let mut lq = self.q.lock().unwrap();
-        while lq.len() == 0 {
+        while lq.is_empty() {
             lq = self.cv.wait(lq).unwrap();
         }
         lq.pop_front().unwrap()
     }
+    /// pop element from front of queue if there is one
+    /// - never blocks
+    pub fn try_de_q(&self) -> Option<T> {
+        self.q.lock().unwrap().pop_front()
+    }
+    /// pop element from front of queue, waiting at most timeout
+    /// - returns None if queue is still empty at timeout
+    pub fn de_q_timeout(&self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut lq = self.q.lock().unwrap();
+        while lq.is_empty() {
+            let wait = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    deadline - now
+                }
+                None => timeout,
+            };
+            lq = self.cv.wait_timeout(lq, wait).unwrap().0;
+        }
+        lq.pop_front()
+    }
     /// return number of elements in queue
     pub fn len(&self) -> usize {
         self.q.lock().unwrap().len()
     }
+    /// return true if queue holds no elements
+    pub fn is_empty(&self) -> bool {
+        self.q.lock().unwrap().is_empty()
+    }
 }
 
 #[cfg(test)]
@@ -85,4 +115,26 @@ mod tests {
         assert_eq!(bq.de_q(), 3.5);
         assert_eq!(bq.len(), 0);
     }
+    #[test]
+    fn bq_try_de_queue() {
+        let bq = BlockingQueue::<f64>::new();
+        assert_eq!(bq.try_de_q(), None);
+        bq.en_q(3.5);
+        assert_eq!(bq.try_de_q(), Some(3.5));
+        assert!(bq.is_empty());
+    }
+    #[test]
+    fn bq_de_queue_timeout() {
+        let bq = Arc::new(BlockingQueue::<f64>::new());
+        let start = Instant::now();
+        assert_eq!(bq.de_q_timeout(Duration::from_millis(50)), None);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        let bq2 = Arc::clone(&bq);
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            bq2.en_q(3.5);
+        });
+        assert_eq!(bq.de_q_timeout(Duration::from_secs(5)), Some(3.5));
+        handle.join().unwrap();
+    }
 }
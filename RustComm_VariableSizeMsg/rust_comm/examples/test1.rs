@@ -9,12 +9,13 @@
    - start Connector component
    - send a few messages, ,observe replies
    - send END message to exit client handler
-   - send QUIT message to shut down Listener
+   - shut down Listener with its ListenerHandle
 */
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
 use std::io::prelude::*;
+use std::time::Duration;
 
 /*-- component library rust_blocking_queue --*/
 use rust_message::*;
@@ -34,7 +35,7 @@ fn main() {
     print!("\n  -- test1: rust_comm\n  -- variable size msgs, buffered\n");
     
     let addr = "127.0.0.1:8080";
-    let mut lsnr = Listener::<P,Log>::new(8);
+    let mut lsnr = Listener::<P,M,Log>::new(8);
     let rslt = lsnr.start(addr);
     if rslt.is_err() {
         return;
@@ -42,10 +43,9 @@ fn main() {
     let handle = rslt.unwrap();
     
     let rslt = Connector::<P,M,Log>::new(addr);
-    if rslt.is_ok() {
+    if let Ok(conn) = rslt {
         print!("\n  connected to: {:?}",addr);
         let _ = std::io::stdout().flush();
-        let conn = rslt.unwrap();
         let mut msg = Message::create_msg_str_fit("message #1");
         msg.set_type(MessageType::FLUSH as u8);
         print!("\n  main posting msg: {:?}", msg.get_content_str().unwrap());
@@ -65,7 +65,7 @@ fn main() {
         print!("\n  main received msg: {:?}",msg.get_content_str().unwrap());
 
         /*-- shut down connector --*/
-        let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE);
+        let mut msg = Message::new(HEADER_SIZE);
         msg.set_type(MessageType::END as u8);
         print!("\n  main posting {:?} msg", "END");
         conn.post_message(msg);
@@ -76,7 +76,7 @@ fn main() {
 
 
     /*-- shut down listener --*/
-    lsnr.stop();
-    let _ = handle.join();
+    let report = handle.shutdown(Duration::from_secs(1));
+    print!("\n  listener shut down: {:?}", report);
     println!();
 }
\ No newline at end of file
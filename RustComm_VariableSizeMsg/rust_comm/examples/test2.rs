@@ -9,12 +9,13 @@
    - start Connector component
    - send a few messages, ,observe replies
    - send END message to exit client handler
-   - send QUIT message to shut down Listener
+   - shut down Listener with its ListenerHandle
 */
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
 use std::io::prelude::*;
+use std::time::Duration;
 
 /*-- component library rust_blocking_queue --*/
 use rust_message::*;
@@ -37,7 +38,7 @@ fn start_client(
         for i in 0..n {
             /*-- used to test error handling --*/
             if sd && i == n-1 {
-                let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE);
+                let mut msg = Message::new(HEADER_SIZE);
                 msg.set_type(MessageType::QUIT as u8);
                 conn.post_message(msg);
                 return;
@@ -52,7 +53,7 @@ fn start_client(
             let msg = conn.get_message();
             print!("\n  received msg: {:?}", msg.get_content_str().unwrap());
         }
-        let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE);
+        let mut msg = Message::new(HEADER_SIZE);
         msg.set_type(MessageType::END as u8);
         print!("\n  posting END message");
         conn.post_message(msg);
@@ -65,7 +66,7 @@ fn main() {
     print!("\n  -- test2: rust_comm\n  -- variable size msgs, buffered\n");
     
     let addr = "127.0.0.1:8080";
-    let mut lsnr = Listener::<P,Log>::new(8);
+    let mut lsnr = Listener::<P,M,Log>::new(8);
     let rslt = lsnr.start(addr);
     if rslt.is_err() {
         print!("\n  can't start listener on {:?}", addr);
@@ -82,7 +83,7 @@ fn main() {
     let _ = h3.join();
 
     /*-- shut down listener --*/
-    lsnr.stop();
-    let _ = handle.join();
+    let report = handle.shutdown(Duration::from_secs(1));
+    print!("\n  listener shut down: {:?}", report);
     println!();
 }
\ No newline at end of file
@@ -13,12 +13,13 @@
    - send a fixed number of messages
    - send END message to exit client handler
    - eval elapsed time
-   - send QUIT message to shut down Listener
+   - shut down Listener with its ListenerHandle
 */
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
 use std::io::prelude::*;
+use std::time::Duration;
 use std::net::{Shutdown, TcpStream};
 use std::sync::Arc;
 use std::{thread, time};
@@ -138,7 +139,7 @@ fn client_no_wait_for_reply<L: Logger>(
             );
             sconn1.post_message(msg.clone());
         }
-        let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE);
+        let mut msg = Message::new(HEADER_SIZE);
         msg.set_type(MessageType::END as u8);
         sconn1.post_message(msg);
     });
@@ -173,12 +174,12 @@ fn main() {
 
     let nt: u8 = 8;
     let addr = "127.0.0.1:8080";
-    let mut lsnr = Listener::<P,Log>::new(nt);
+    let mut lsnr = Listener::<P,M,Log>::new(nt);
     let rslt = lsnr.start(addr);
     if rslt.is_err() {
         return;
     }
-    let _handle = rslt.unwrap();
+    let lsnr_handle = rslt.unwrap();
 
     let h1 = client_wait_for_reply::<L>(
         addr, "test3 - wait for reply", 1000, 65536
@@ -221,6 +222,6 @@ fn main() {
     println!();
     
     /*-- shut down listener --*/
-    lsnr.stop();
-    let _ = _handle.join();
+    let report = lsnr_handle.shutdown(Duration::from_secs(1));
+    print!("\n  listener shut down: {:?}", report);
 }
\ No newline at end of file
@@ -17,12 +17,13 @@
        - send a fixed number of messages
        - send END message to exit client handler
    - eval elapsed time
-   - send QUIT message to shut down Listener
+   - shut down Listener with its ListenerHandle
 */
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
 use std::io::prelude::*;
+use std::time::Duration;
 use std::net::{Shutdown, TcpStream};
 use std::sync::Arc;
 use std::{thread, time};
@@ -199,17 +200,17 @@ fn main() {
     let nt: u8 = 8;
     let addr = "127.0.0.1:8080";
     print!("\n  num thrdpool thrds: {:?}",nt);
-    let mut lsnr = Listener::<P,Log>::new(nt);
+    let mut lsnr = Listener::<P,M,Log>::new(nt);
     let rslt = lsnr.start(addr);
     if rslt.is_err() {
         return;
     }
-    let _handle = rslt.unwrap();
+    let lsnr_handle = rslt.unwrap();
 
     multiple_clients(16, addr, "test4", 1000, 4096);
     println!();
 
     /*-- shut down listener --*/
-    lsnr.stop();
-    let _ = _handle.join();
+    let report = lsnr_handle.shutdown(Duration::from_secs(1));
+    print!("\n  listener shut down: {:?}", report);
 }
\ No newline at end of file
@@ -0,0 +1,91 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::test5.rs - Test file transfer                //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Demo:
+   - start Listener component with a file directory
+   - start Connector component
+   - send a file, interleaving an ordinary message
+   - observe progress reports and the echoed message
+   - verify stored file matches original
+   - send END message to exit client handler
+   - shut down Listener with its ListenerHandle
+*/
+#![allow(unused_imports)]
+#![allow(dead_code)]
+
+use std::io::prelude::*;
+use std::time::Duration;
+
+use rust_message::*;
+use rust_traits::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+type Log = MuteLog;
+type M = Message;
+type P = CommProcessing<Log>;
+
+fn main() {
+
+    print!("\n  -- test5: rust_comm\n  -- file transfer\n");
+
+    let src = std::path::Path::new(file!());
+    let dir = std::env::temp_dir().join("rust_comm_test5");
+    let _ = std::fs::create_dir_all(&dir);
+
+    let addr = "127.0.0.1:8080";
+    let mut lsnr = Listener::<P,M,Log>::new(8);
+    lsnr.set_file_dir(&dir);
+    let rslt = lsnr.start(addr);
+    if rslt.is_err() {
+        return;
+    }
+    let handle = rslt.unwrap();
+
+    let rslt = Connector::<P,M,Log>::new(addr);
+    if let Ok(conn) = rslt {
+        print!("\n  sending file {:?} to {:?}", src, dir);
+        match conn.send_file(src) {
+            Ok(transfer) => {
+                /*-- ordinary traffic shares the connection --*/
+                let mut msg = Message::create_msg_str_fit("sent during transfer");
+                msg.set_type(MessageType::FLUSH as u8);
+                conn.post_message(msg);
+                loop {
+                    let status = transfer.next_status();
+                    print!("\n  status: {:?}", status);
+                    if status.is_done() {
+                        break;
+                    }
+                }
+                let msg = conn.get_message();
+                print!("\n  main received msg: {:?}", msg.get_content_str().unwrap());
+                let stored = std::fs::read(dir.join(transfer.name()));
+                let original = std::fs::read(src);
+                if stored.is_ok() && stored.ok() == original.ok() {
+                    print!("\n  stored file matches original");
+                }
+                else {
+                    print!("\n  stored file does not match original");
+                }
+            }
+            Err(err) => print!("\n  can't send {:?}: {}", src, err),
+        }
+
+        /*-- shut down connector --*/
+        let mut msg = Message::new(HEADER_SIZE);
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+    }
+    else {
+        print!("\n  connection to {:?} failed", addr);
+    }
+
+    /*-- shut down listener --*/
+    let report = handle.shutdown(Duration::from_secs(1));
+    print!("\n  listener shut down: {:?}", report);
+    println!();
+}
@@ -0,0 +1,91 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::test6.rs - Test Comm peer endpoints          //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Demo:
+   - start three Comm peers, each listening on its own port
+   - each peer posts a greeting to the other two
+   - each peer, on its own thread, replies to every
+     greeting it receives, using the address returned
+     with the greeting
+   - stop all peers
+*/
+#![allow(unused_imports)]
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use rust_message::*;
+use rust_traits::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+type Log = MuteLog;
+type M = Message;
+type P = CommProcessing<Log>;
+
+fn flush_msg(text: &str) -> Message {
+    let mut msg = Message::create_msg_str_fit(text);
+    msg.set_type(MessageType::FLUSH as u8);
+    msg
+}
+
+fn main() {
+
+    print!("\n  -- test6: rust_comm\n  -- Comm peers\n");
+
+    let addrs = ["127.0.0.1:8081", "127.0.0.1:8082", "127.0.0.1:8083"];
+    let mut peers = Vec::<Comm<P,M,Log>>::new();
+    let mut handles = Vec::new();
+    for addr in &addrs {
+        let mut comm = Comm::<P,M,Log>::new();
+        match comm.start(addr) {
+            Ok(handle) => handles.push(handle),
+            Err(err) => {
+                print!("\n  can't start peer on {:?}: {}", addr, err);
+                return;
+            }
+        }
+        peers.push(comm);
+    }
+
+    /*-- every peer greets the others --*/
+    for (i, comm) in peers.iter().enumerate() {
+        for (j, dest) in addrs.iter().enumerate() {
+            if i != j {
+                let text = format!("greeting from {}", addrs[i]);
+                let _ = comm.post_message(dest, flush_msg(&text));
+            }
+        }
+    }
+    /*-- each peer receives two greetings and two replies --*/
+    let peers: Vec<Arc<Comm<P,M,Log>>> = peers.into_iter().map(Arc::new).collect();
+    let mut workers = Vec::new();
+    for (i, comm) in peers.iter().enumerate() {
+        let comm = Arc::clone(comm);
+        let me = addrs[i];
+        workers.push(std::thread::spawn(move || {
+            for _ in 0..4 {
+                let (from, msg) = comm.get_message();
+                let text = msg.get_content_str().unwrap_or("").to_string();
+                print!("\n  {} received {:?} from {}", me, text, from);
+                if text.starts_with("greeting") {
+                    let reply = format!("reply from {}", me);
+                    let _ = comm.post_message(from, flush_msg(&reply));
+                }
+            }
+        }));
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+    for comm in &peers {
+        comm.stop();
+    }
+    for handle in handles {
+        let _ = handle.join();
+    }
+    println!();
+}
@@ -0,0 +1,465 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::async_comm.rs - tokio Connector and Listener //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   With the async feature, AsyncConnector<P,M,L> and
+   AsyncListener<P,M,L> run on a tokio runtime instead of
+   dedicated threads:
+   - an AsyncConnector is two tasks, one sending and one
+     receiving, rather than two OS threads
+   - an AsyncListener handles each client in its own task,
+     so idle connections hold no thread
+
+   P supplies framing and processing with AsyncSndr,
+   AsyncRcvr, and AsyncProcess.  CommProcessing frames
+   messages exactly as it does for Sndr and Rcvr, so async
+   and blocking endpoints interoperate: an AsyncConnector
+   can talk to a Listener, and a Connector to an
+   AsyncListener.
+
+   Behavior follows the blocking types: messages are
+   numbered when posted, AsyncListener sets each reply's
+   correlation id to its request's id, SHUTDOWN is answered
+   with END, PING with PONG, and shutdown drains then
+   forces connections, returning the same ShutdownReport.
+   set_max_msg_size limits received messages, and an
+   unreadable frame is answered with PROTOCOL_ERROR before
+   closing, see frame_limit.rs.
+
+   Not yet async:
+   - AsyncConnector has no request or ReplyTicket, callers
+     match replies by get_correlation_id
+   - AsyncListener refuses file transfers
+   - neither sends heartbeats nor drops idle connections,
+     tokio::time::timeout serves instead
+   - a corrupted message always closes the connection, see
+     checksum.rs
+*/
+
+use crate::*;
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::atomic::AtomicUsize;
+use tokio::io::{AsyncWriteExt, BufReader as AsyncBufReader, BufWriter as AsyncBufWriter};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener as AsyncTcpListener, TcpStream as AsyncTcpStream};
+use tokio::sync::{mpsc, Mutex as AsyncMutex, Notify};
+use tokio::task::{AbortHandle, JoinHandle as TaskHandle};
+use tokio::time::Instant;
+
+/*---------------------------------------------------------
+  AsyncConnector<P,M,L> - connects to a Listener or
+  AsyncListener
+*/
+#[derive(Debug)]
+pub struct AsyncConnector<P,M,L> {
+    snd: mpsc::UnboundedSender<M>,
+    rcv: AsyncMutex<mpsc::UnboundedReceiver<M>>,
+    next_id: AtomicU64,
+    max_msg_size: Arc<AtomicUsize>,
+    peer: SocketAddr,
+    local: SocketAddr,
+    _t: PhantomData<(P, L)>,
+}
+impl<P,M,L> AsyncConnector<P,M,L> where
+    M: Msg + Clone + Send + Sync + Default + 'static,
+    P: AsyncSndr<M> + AsyncRcvr<M> + 'static,
+    L: Logger + 'static
+{
+    /*-- connect and spawn send and receive tasks --*/
+    pub async fn new<A: tokio::net::ToSocketAddrs>(addr: A) -> CommResult<AsyncConnector<P,M,L>> {
+        let strm = AsyncTcpStream::connect(addr).await.map_err(CommError::Connect)?;
+        let peer = strm.peer_addr()?;
+        let local = strm.local_addr()?;
+        L::write(&format!("\n--connected to {}--", peer));
+        let (rd, wr) = strm.into_split();
+        let (snd, mut outbox) = mpsc::unbounded_channel::<M>();
+        let (inbox, rcv) = mpsc::unbounded_channel::<M>();
+
+        /*-- send task ends after sending END, PROTOCOL_ERROR, or a failed send --*/
+        tokio::spawn(async move {
+            let mut writer = AsyncBufWriter::new(wr);
+            while let Some(msg) = outbox.recv().await {
+                let is_end = msg.get_type() == MessageType::END as u8
+                    || msg.get_type() == MessageType::PROTOCOL_ERROR as u8;
+                if P::async_send_message(&msg, &mut writer).await.is_err() || is_end {
+                    break;
+                }
+            }
+            let _ = writer.flush().await;
+            L::write("\n--terminating connector send task--");
+        });
+        /*-- receive task ends when the connection does --*/
+        let max_msg_size = Arc::new(AtomicUsize::new(DEFAULT_MAX_MSG_SIZE));
+        let max = Arc::clone(&max_msg_size);
+        let reply_q = snd.clone();
+        tokio::spawn(async move {
+            let mut reader = AsyncBufReader::new(rd);
+            loop {
+                let msg = match P::async_recv_message_max(&mut reader, max.load(Ordering::Relaxed)).await {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        if let Some(reply) = protocol_error::<M>(&e) {
+                            let _ = reply_q.send(reply);
+                        }
+                        break;
+                    }
+                };
+                if msg.get_type() == MessageType::PING as u8 {
+                    let _ = reply_q.send(pong_msg());
+                    continue;
+                }
+                if msg.get_type() == MessageType::PONG as u8 {
+                    continue;
+                }
+                /*-- listener is shutting down, end after queued msgs --*/
+                if msg.get_type() == MessageType::SHUTDOWN as u8 {
+                    let mut end = M::new(HEADER_SIZE);
+                    end.set_type(MessageType::END as u8);
+                    let _ = reply_q.send(end);
+                }
+                if inbox.send(msg).is_err() {
+                    break;
+                }
+            }
+            L::write("\n--terminating connector receive task--");
+        });
+        Ok(AsyncConnector {
+            snd,
+            rcv: AsyncMutex::new(rcv),
+            next_id: AtomicU64::new(1),
+            max_msg_size,
+            peer,
+            local,
+            _t: PhantomData,
+        })
+    }
+    /*-- msg is given the next message id unless it has one --*/
+    pub fn post_message(&self, mut msg: M) {
+        if msg.get_msg_id() == 0 {
+            msg.set_msg_id(self.next_id.fetch_add(1, Ordering::Relaxed));
+        }
+        let _ = self.snd.send(msg);
+    }
+    /*-- next received msg, PeerClosed once the connection has ended --*/
+    pub async fn get_message(&self) -> CommResult<M> {
+        self.rcv.lock().await.recv().await.ok_or(CommError::PeerClosed)
+    }
+    /*-- largest message body accepted from the listener, see frame_limit.rs --*/
+    pub fn set_max_msg_size(&self, max: usize) {
+        self.max_msg_size.store(max, Ordering::Relaxed);
+    }
+    pub fn get_max_msg_size(&self) -> usize {
+        self.max_msg_size.load(Ordering::Relaxed)
+    }
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local
+    }
+}
+
+/*-- writer shared by a client task and shutdown --*/
+type AsyncSharedWriter = Arc<AsyncMutex<AsyncBufWriter<OwnedWriteHalf>>>;
+
+/*---------------------------------------------------------
+  AsyncConnections - registry of an AsyncListener's open
+  connections, the async counterpart of Connections
+  - abort is None until the client task is spawned
+*/
+#[derive(Debug, Default)]
+struct AsyncConnections {
+    map: Mutex<HashMap<u64, (AsyncSharedWriter, Option<AbortHandle>)>>,
+    emptied: Notify,
+    next_id: AtomicU64,
+}
+impl AsyncConnections {
+    fn add(&self, writer: AsyncSharedWriter) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.map.lock().unwrap().insert(id, (writer, None));
+        id
+    }
+    fn set_abort(&self, id: u64, abort: AbortHandle) {
+        if let Some(entry) = self.map.lock().unwrap().get_mut(&id) {
+            entry.1 = Some(abort);
+        }
+    }
+    fn remove(&self, id: u64) {
+        let mut map = self.map.lock().unwrap();
+        map.remove(&id);
+        if map.is_empty() {
+            self.emptied.notify_waiters();
+        }
+    }
+    fn len(&self) -> usize {
+        self.map.lock().unwrap().len()
+    }
+    fn writers(&self) -> Vec<AsyncSharedWriter> {
+        self.map.lock().unwrap().values().map(|(w, _)| Arc::clone(w)).collect()
+    }
+    /*-- wait until no connections are open or deadline, if any, passes --*/
+    async fn wait_empty(&self, deadline: Option<Instant>) -> bool {
+        loop {
+            let emptied = self.emptied.notified();
+            if self.len() == 0 {
+                return true;
+            }
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, emptied).await.is_err() {
+                        return self.len() == 0;
+                    }
+                }
+                None => emptied.await,
+            }
+        }
+    }
+    /*-- abort every client task, returning their count --*/
+    fn abort_all(&self) -> usize {
+        let mut map = self.map.lock().unwrap();
+        for (_, abort) in map.values() {
+            if let Some(abort) = abort {
+                abort.abort();
+            }
+        }
+        let forced = map.len();
+        map.clear();
+        forced
+    }
+}
+
+/*---------------------------------------------------------
+  AsyncListener<P,M,L>
+  - each client connection is handled in its own task
+    with its own clone of the processor, p
+*/
+#[derive(Debug)]
+pub struct AsyncListener<P,M,L> {
+    p: P,
+    local: Option<SocketAddr>,
+    max_msg_size: usize,
+    _t: PhantomData<(M, L)>,
+}
+impl<P,M,L> AsyncListener<P,M,L> where
+    M: Msg + Clone + Send + Sync + Default + 'static,
+    P: Debug + Clone + Send + Sync + Default
+        + AsyncSndr<M> + AsyncRcvr<M> + AsyncProcess<M> + 'static,
+    L: Logger + 'static
+{
+    pub fn new() -> AsyncListener<P,M,L> {
+        AsyncListener::with_processor(P::default())
+    }
+    /*-- p is cloned for each client connection --*/
+    pub fn with_processor(p: P) -> AsyncListener<P,M,L> {
+        AsyncListener { p, local: None, max_msg_size: DEFAULT_MAX_MSG_SIZE, _t: PhantomData }
+    }
+    /*-- largest message body accepted from clients, see frame_limit.rs, takes effect at start --*/
+    pub fn set_max_msg_size(&mut self, max: usize) {
+        self.max_msg_size = max;
+    }
+    pub fn get_max_msg_size(&self) -> usize {
+        self.max_msg_size
+    }
+    /*-- address bound by start, shows the port chosen for port 0 --*/
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local
+    }
+    /*-- binds as Listener::start does, then spawns accept task --*/
+    pub async fn start<A: tokio::net::ToSocketAddrs>(
+        &mut self, addr: A
+    ) -> CommResult<AsyncListenerHandle<P,M,L>> {
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host(addr).await
+            .map_err(CommError::Bind)?
+            .collect();
+        L::write(&format!("\n--starting async listener on {:?}--", addrs));
+        let tcpl = bind_listener(&addrs).map_err(CommError::Bind)?;
+        self.local = Some(tcpl.local_addr()?);
+        tcpl.set_nonblocking(true)?;
+        let tcpl = AsyncTcpListener::from_std(tcpl)?;
+        let conns = Arc::new(AsyncConnections::default());
+        let stop = Arc::new(Notify::new());
+        let accept = tokio::spawn(accept_loop::<P,M,L>(
+            tcpl, self.p.clone(), self.max_msg_size, Arc::clone(&conns), Arc::clone(&stop)
+        ));
+        Ok(AsyncListenerHandle { stop, conns, accept: Some(accept), _t: PhantomData })
+    }
+}
+impl<P,M,L> Default for AsyncListener<P,M,L> where
+    M: Msg + Clone + Send + Sync + Default + 'static,
+    P: Debug + Clone + Send + Sync + Default
+        + AsyncSndr<M> + AsyncRcvr<M> + AsyncProcess<M> + 'static,
+    L: Logger + 'static
+{
+    fn default() -> AsyncListener<P,M,L> {
+        AsyncListener::new()
+    }
+}
+/*-- accept until stopped, spawning a task per client --*/
+async fn accept_loop<P,M,L>(
+    tcpl: AsyncTcpListener, proc: P, max: usize, conns: Arc<AsyncConnections>, stop: Arc<Notify>
+)
+where
+    M: Msg + Clone + Send + Sync + Default + 'static,
+    P: Clone + AsyncSndr<M> + AsyncRcvr<M> + AsyncProcess<M> + 'static,
+    L: Logger + 'static
+{
+    loop {
+        let (strm, peer) = tokio::select! {
+            _ = stop.notified() => break,
+            rslt = tcpl.accept() => match rslt {
+                Ok(accepted) => accepted,
+                Err(_) => continue,
+            },
+        };
+        let (rd, wr) = strm.into_split();
+        let writer = Arc::new(AsyncMutex::new(AsyncBufWriter::new(wr)));
+        let id = conns.add(Arc::clone(&writer));
+        let task_conns = Arc::clone(&conns);
+        let proc = proc.clone();
+        let task = tokio::spawn(async move {
+            let rslt = handle_client_async::<P,M,L>(rd, &writer, proc, peer, max).await;
+            if let Err(e) = rslt {
+                L::write(&format!("\n  client handler failed: {}", e));
+            }
+            task_conns.remove(id);
+        });
+        conns.set_abort(id, task.abort_handle());
+    }
+    L::write("\n--terminating async listener accept task--");
+}
+/*---------------------------------------------------------
+  Handle client messages, as handle_client does
+  - process with proc.async_process_message, then send
+    replies, correlated with msg, under the writer lock
+  - file transfer messages are refused
+  - a frame that can't be read is answered with
+    PROTOCOL_ERROR, then the connection closes
+*/
+async fn handle_client_async<P,M,L>(
+    rd: OwnedReadHalf, writer: &AsyncMutex<AsyncBufWriter<OwnedWriteHalf>>,
+    mut proc: P, peer: SocketAddr, max: usize
+) -> CommResult<CloseReason>
+where
+    M: Msg + Clone + Send + Sync + Default,
+    P: AsyncSndr<M> + AsyncRcvr<M> + AsyncProcess<M>,
+    L: Logger
+{
+    let mut reader = AsyncBufReader::new(rd);
+    let mut files = FileReceiver::new(None);
+    let mut ctx = ConnContext::new(Some(peer));
+    let reason = loop {
+        let msg: M = match P::async_recv_message_max(&mut reader, max).await {
+            Ok(msg) => msg,
+            Err(CommError::PeerClosed) => break CloseReason::Dropped,
+            Err(e) => {
+                if let Some(reply) = protocol_error::<M>(&e) {
+                    let mut buf_writer = writer.lock().await;
+                    let _ = P::async_send_message(&reply, &mut *buf_writer).await;
+                    let _ = buf_writer.flush().await;
+                }
+                return Err(e);
+            }
+        };
+        ctx.msg_count += 1;
+        if msg.get_type() == MessageType::END as u8 {
+            break CloseReason::End;
+        }
+        else if msg.get_type() == MessageType::QUIT as u8 {
+            break CloseReason::Quit;
+        }
+        else if is_file_msg(msg.get_type()) {
+            if let Some(reply) = files.handle(&msg) {
+                let _ = P::async_send_message(&reply, &mut *writer.lock().await).await;
+            }
+            continue;
+        }
+        else if msg.get_type() == MessageType::PING as u8 {
+            let _ = P::async_send_message(&pong_msg(), &mut *writer.lock().await).await;
+            continue;
+        }
+        else if msg.get_type() == MessageType::PONG as u8 {
+            continue;
+        }
+        let req_id = msg.get_msg_id();
+        let checked = msg.get_flags() & FLAG_CHECKSUM;
+        let replies = proc.async_process_message(msg, &mut ctx).await;
+        let mut buf_writer = writer.lock().await;
+        for mut reply in replies {
+            if reply.get_correlation_id() == 0 {
+                reply.set_correlation_id(req_id);
+            }
+            reply.set_flags(reply.get_flags() | checked);
+            let _ = P::async_send_message(&reply, &mut *buf_writer).await;
+        }
+    };
+    let _ = writer.lock().await.flush().await;
+    L::write("\n  terminating client task");
+    Ok(reason)
+}
+
+/*---------------------------------------------------------
+  AsyncListenerHandle<P,M,L> - returned by
+  AsyncListener::start
+*/
+#[derive(Debug)]
+pub struct AsyncListenerHandle<P,M,L> {
+    stop: Arc<Notify>,
+    conns: Arc<AsyncConnections>,
+    accept: Option<TaskHandle<()>>,
+    _t: PhantomData<(P, M, L)>,
+}
+impl<P,M,L> AsyncListenerHandle<P,M,L> where
+    M: Msg + Clone + Send + Sync + Default + 'static,
+    P: AsyncSndr<M> + 'static,
+    L: Logger
+{
+    /*-- number of currently open client connections --*/
+    pub fn connections(&self) -> usize {
+        self.conns.len()
+    }
+    /*-----------------------------------------------------
+      Stop accepting, send SHUTDOWN to every client, wait
+      up to timeout for them to close, and abort the rest,
+      as ListenerHandle::shutdown does
+      - each SHUTDOWN is sent by its own task, given up at
+        the deadline, so a client that has stopped reading
+        can't hold up shutdown
+    */
+    pub async fn shutdown(mut self, timeout: Duration) -> ShutdownReport {
+        L::write("\n--shutting down async listener--");
+        /*-- None, for a timeout too long to represent, waits for every client --*/
+        let deadline = Instant::now().checked_add(timeout);
+        self.stop.notify_one();
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.await;
+        }
+        /*-- no new connections now, so this count is final --*/
+        let open = self.conns.len();
+        let mut msg = M::new(HEADER_SIZE);
+        msg.set_type(MessageType::SHUTDOWN as u8);
+        for writer in self.conns.writers() {
+            let msg = msg.clone();
+            tokio::spawn(async move {
+                let send = async {
+                    let _ = P::async_send_message(&msg, &mut *writer.lock().await).await;
+                };
+                match deadline {
+                    Some(deadline) => {
+                        let _ = tokio::time::timeout_at(deadline, send).await;
+                    }
+                    None => send.await,
+                }
+            });
+        }
+        let forced = if self.conns.wait_empty(deadline).await {
+            0
+        }
+        else {
+            self.conns.abort_all()
+        };
+        ShutdownReport { drained: open - forced, forced }
+    }
+}
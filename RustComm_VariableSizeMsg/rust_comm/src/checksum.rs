@@ -0,0 +1,44 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::checksum.rs - corrupted message handling     //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Messages with FLAG_CHECKSUM set are sent with a CRC-32C
+   of their header and body, and receivers report a
+   mismatch as CommError::ChecksumMismatch, see
+   rust_comm_processing.
+   - Connector::set_checksums sets FLAG_CHECKSUM on every
+     message the Connector sends
+   - Listeners checksum their replies to checksummed
+     messages
+   - a ChecksumPolicy, set with set_checksum_policy on
+     Connector, Listener, and Comm, decides what a
+     mismatch does to the connection it arrived on
+
+   AsyncConnector and AsyncListener check checksums too,
+   but always close.
+*/
+
+use rust_traits::*;
+
+/*---------------------------------------------------------
+  ChecksumPolicy - what a corrupted message does
+  - Close ends the connection, as any receive failure
+    does, the default
+  - Drop discards the message and keeps receiving; the
+    frame's length was read before the mismatch was found,
+    so if the length was what got corrupted, the next
+    receive fails and closes the connection anyway
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumPolicy {
+    #[default]
+    Close,
+    Drop,
+}
+impl ChecksumPolicy {
+    /*-- true if failed receive e is a message to skip --*/
+    pub(crate) fn skips(self, e: &CommError) -> bool {
+        self == ChecksumPolicy::Drop && matches!(e, CommError::ChecksumMismatch { .. })
+    }
+}
@@ -0,0 +1,294 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::comm.rs - peer to peer endpoint              //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Comm<P,M,L> composes the two halves of RustComm into a
+   single peer endpoint:
+   - like Listener it accepts connections on its own
+     listening address
+   - like Connector it opens connections to other peers
+   - every connection, accepted or opened, gets a send
+     queue and thread, and a receive thread
+
+   All received messages go to one inbox, tagged with the
+   address of the connection they arrived on.  Posting a
+   message to that address sends it back over the same
+   connection, so replies reach the right peer.
+
+   Unlike Listener, Comm does no processing of its own.
+   P supplies only message framing, via Sndr and Rcvr.
+
+   Receive settings are shared by every connection:
+   - set_max_msg_size refuses larger messages, answering
+     with PROTOCOL_ERROR, as Listener does, see
+     frame_limit.rs
+   - set_checksum_policy decides what a corrupted message
+     does, see checksum.rs
+   Comm doesn't send checksums or heartbeats.
+*/
+
+use rust_traits::*;
+use rust_message::*;
+use rust_blocking_queue::*;
+
+use crate::{accept_loop, bind_listener, ChecksumPolicy, Tcp};
+use crate::frame_limit::*;
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::{BufReader, BufWriter, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, atomic::AtomicBool, atomic::AtomicUsize, atomic::Ordering};
+use std::thread::JoinHandle;
+
+type PeerMap<M> = Arc<Mutex<HashMap<SocketAddr, Arc<BlockingQueue<M>>>>>;
+
+/*-- receive settings, read by each connection for every message --*/
+#[derive(Debug)]
+struct Settings {
+    max_msg_size: AtomicUsize,
+    checksum_policy: Mutex<ChecksumPolicy>,
+}
+impl Settings {
+    fn new() -> Settings {
+        Settings {
+            max_msg_size: AtomicUsize::new(DEFAULT_MAX_MSG_SIZE),
+            checksum_policy: Mutex::new(ChecksumPolicy::default()),
+        }
+    }
+}
+
+/*---------------------------------------------------------
+  Comm<P,M,L> - accepts and initiates connections
+*/
+#[derive(Debug)]
+pub struct Comm<P,M,L> where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
+    L: Logger + Debug + Copy + Clone + Default
+{
+    inbox: Arc<BlockingQueue<(SocketAddr, M)>>,
+    peers: PeerMap<M>,
+    run: Arc<AtomicBool>,
+    local: Option<SocketAddr>,
+    settings: Arc<Settings>,
+    _p: P,
+    log: L,
+}
+impl<P,M,L> Default for Comm<P,M,L> where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M> + 'static,
+    L: Logger + Debug + Copy + Clone + Default + 'static
+{
+    fn default() -> Comm<P,M,L> {
+        Comm::new()
+    }
+}
+impl<P,M,L> Comm<P,M,L> where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M> + 'static,
+    L: Logger + Debug + Copy + Clone + Default + 'static
+{
+    pub fn new() -> Comm<P,M,L> {
+        Comm {
+            inbox: Arc::new(BlockingQueue::new()),
+            peers: Arc::new(Mutex::new(HashMap::new())),
+            run: Arc::new(AtomicBool::new(true)),
+            local: None,
+            settings: Arc::new(Settings::new()),
+            _p: P::default(),
+            log: L::default(),
+        }
+    }
+    /*-- bind to addr and accept peer connections on dedicated thread --*/
+    pub fn start<A: ToSocketAddrs>(&mut self, addr: A) -> CommResult<JoinHandle<()>> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs()
+            .map_err(CommError::Bind)?
+            .collect();
+        L::write(&format!("\n--starting comm on {:?}--", addrs));
+        let tcpl = bind_listener(&addrs).map_err(CommError::Bind)?;
+        tcpl.set_nonblocking(true)?;
+        self.local = Some(tcpl.local_addr()?);
+        let run = Arc::clone(&self.run);
+        let peers = Arc::clone(&self.peers);
+        let inbox = Arc::clone(&self.inbox);
+        let settings = Arc::clone(&self.settings);
+        let handle = std::thread::spawn(move || {
+            accept_loop::<Tcp,_>(&tcpl, &run, |strm, peer| {
+                L::write(&format!("\n--comm accepted {:?}--", peer));
+                let _ = attach::<P,M,L>(strm, peer, &peers, &inbox, &settings);
+            });
+            L::write("\n--terminating comm accept thread--");
+        });
+        Ok(handle)
+    }
+    /*-- address this Comm is listening on, once started --*/
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local
+    }
+    /*-- largest message body accepted from any peer, see frame_limit.rs --*/
+    pub fn set_max_msg_size(&self, max: usize) {
+        self.settings.max_msg_size.store(max, Ordering::Relaxed);
+    }
+    pub fn get_max_msg_size(&self) -> usize {
+        self.settings.max_msg_size.load(Ordering::Relaxed)
+    }
+    /*-- what a corrupted message from a peer does, see checksum.rs --*/
+    pub fn set_checksum_policy(&self, policy: ChecksumPolicy) {
+        *self.settings.checksum_policy.lock().unwrap() = policy;
+    }
+    /*-- open connection to another peer, returning its address --*/
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> CommResult<SocketAddr> {
+        let strm = TcpStream::connect(addr).map_err(CommError::Connect)?;
+        let peer = strm.peer_addr()?;
+        L::write(&format!("\n--comm connected to {:?}--", peer));
+        attach::<P,M,L>(strm, peer, &self.peers, &self.inbox, &self.settings)?;
+        Ok(peer)
+    }
+    /*-----------------------------------------------------
+      Enqueue msg for dest
+      - dest may be an address returned by get_message,
+        in which case msg goes back over that connection
+      - if there is no connection to dest, one is opened
+    */
+    pub fn post_message<A: ToSocketAddrs>(&self, dest: A, msg: M) -> CommResult<()> {
+        let addrs: Vec<SocketAddr> = dest.to_socket_addrs()
+            .map_err(CommError::Connect)?
+            .collect();
+        let found = {
+            let peers = self.peers.lock().unwrap();
+            addrs.iter().find_map(|a| peers.get(a).cloned())
+        };
+        let sq = match found {
+            Some(sq) => sq,
+            None => {
+                let peer = self.connect(&addrs[..])?;
+                let peers = self.peers.lock().unwrap();
+                peers.get(&peer).cloned().ok_or(CommError::PeerClosed)?
+            }
+        };
+        sq.en_q(msg);
+        Ok(())
+    }
+    /*-- blocks until a message arrives from any peer --*/
+    pub fn get_message(&self) -> (SocketAddr, M) {
+        self.inbox.de_q()
+    }
+    pub fn has_msg(&self) -> bool {
+        !self.inbox.is_empty()
+    }
+    /*-- addresses of currently connected peers --*/
+    pub fn peers(&self) -> Vec<SocketAddr> {
+        self.peers.lock().unwrap().keys().cloned().collect()
+    }
+    /*-- send END to every peer and stop accepting --*/
+    pub fn stop(&self) {
+        self.run.store(false, Ordering::Relaxed);
+        for sq in self.peers.lock().unwrap().values() {
+            sq.en_q(end_msg::<M>());
+        }
+    }
+}
+fn end_msg<M: Msg>() -> M {
+    let mut msg = M::new(HEADER_SIZE);
+    msg.set_type(MessageType::END as u8);
+    msg
+}
+/*---------------------------------------------------------
+  Start send and receive threads for a connection and
+  register its send queue under peer
+  - receive thread tags each message with peer and
+    enQs it in inbox
+  - END from the peer, or a receive error, closes the
+    connection: the peer is unregistered and END is
+    sent back, after PROTOCOL_ERROR if the peer's frame
+    couldn't be read
+*/
+fn attach<P,M,L>(
+    strm: TcpStream, peer: SocketAddr,
+    peers: &PeerMap<M>, inbox: &Arc<BlockingQueue<(SocketAddr, M)>>,
+    settings: &Arc<Settings>
+) -> CommResult<Arc<BlockingQueue<M>>>
+where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Sndr<M> + Rcvr<M> + 'static,
+    L: Logger + 'static
+{
+    let mut buf_writer = BufWriter::new(strm.try_clone()?);
+    let mut buf_reader = BufReader::new(strm.try_clone()?);
+    let sq = Arc::new(BlockingQueue::<M>::new());
+    peers.lock().unwrap().insert(peer, Arc::clone(&sq));
+
+    let ssq = Arc::clone(&sq);
+    let _ = std::thread::spawn(move || {
+        send_loop::<P,M,L,_>(&ssq, &mut buf_writer);
+        let _ = strm.shutdown(Shutdown::Both);
+    });
+    let rsq = Arc::clone(&sq);
+    let rpeers = Arc::clone(peers);
+    let rinbox = Arc::clone(inbox);
+    let rsettings = Arc::clone(settings);
+    let _ = std::thread::spawn(move || {
+        loop {
+            let max = rsettings.max_msg_size.load(Ordering::Relaxed);
+            let rslt: CommResult<M> = P::buf_recv_message_max(&mut buf_reader, max);
+            match rslt {
+                Ok(msg) if msg.get_type() != MessageType::END as u8 => {
+                    rinbox.en_q((peer, msg));
+                }
+                Err(e) if rsettings.checksum_policy.lock().unwrap().skips(&e) => {
+                    L::write(&format!("\n  dropped message: {}", e));
+                }
+                Err(e) => {
+                    if let Some(refusal) = protocol_error::<M>(&e) {
+                        rsq.en_q(refusal);
+                    }
+                    break;
+                }
+                _ => break,
+            }
+        }
+        L::write(&format!("\n--comm closing connection to {:?}--", peer));
+        {
+            /*-- don't remove a newer connection to same peer --*/
+            let mut map = rpeers.lock().unwrap();
+            if map.get(&peer).map(|q| Arc::ptr_eq(q, &rsq)) == Some(true) {
+                map.remove(&peer);
+            }
+        }
+        rsq.en_q(end_msg::<M>());
+    });
+    Ok(sq)
+}
+/*---------------------------------------------------------
+  Send loop of each connection's send thread
+  - deQ and send messages until sending END or a
+    send fails
+*/
+fn send_loop<P,M,L,W>(sq: &BlockingQueue<M>, buf_writer: &mut BufWriter<W>)
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M>,
+    L: Logger,
+    W: Write
+{
+    loop {
+        L::write("\n  -- dequing send msg --");
+        let msg = sq.de_q();
+        L::write("\n  sending msg");
+        let msg_type = msg.get_type();
+        let rslt = P::buf_send_message(&msg, buf_writer);
+        if rslt.is_err() {
+            // may cause panic if io doesn't complete before 
+            // thread shuts down
+            // print!("\n  msg send error");
+            break;
+        }
+        L::write("\n  -- send successful --");
+        if msg_type == MessageType::END as u8 {
+            L::write("\n--terminating send thread--");
+            break;
+        }
+    }
+}
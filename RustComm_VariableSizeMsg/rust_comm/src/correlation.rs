@@ -0,0 +1,142 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::correlation.rs - request/reply matching      //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Connector::request posts a message and returns a
+   ReplyTicket for its replies:
+   - each posted message gets a message id, unique for
+     its Connector
+   - handle_client sets each reply's correlation id to the
+     message id of the request it answers
+   - Connector's receive thread hands replies with a
+     registered correlation id to that ticket, in whatever
+     order they arrive, and everything else to get_message
+
+   A ticket collects every reply to its request, so
+   processors that reply more than once can be waited on
+   repeatedly.  Dropping the ticket unregisters it, after
+   which late replies go to get_message.
+*/
+
+use rust_traits::*;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/*-- replies received for one ticket, closed when connection ends --*/
+#[derive(Debug)]
+struct Slot<M> {
+    replies: Mutex<(VecDeque<M>, bool)>,
+    cv: Condvar,
+}
+
+/*---------------------------------------------------------
+  Tickets<M> - open tickets of one Connector
+  - None once the connection has ended
+*/
+#[derive(Debug)]
+pub(crate) struct Tickets<M> {
+    open: Mutex<Option<HashMap<u64, Arc<Slot<M>>>>>,
+}
+impl<M> Tickets<M> {
+    pub(crate) fn new() -> Tickets<M> {
+        Tickets { open: Mutex::new(Some(HashMap::new())) }
+    }
+    pub(crate) fn register(tickets: &Arc<Tickets<M>>, id: u64) -> ReplyTicket<M> {
+        let mut open = tickets.open.lock().unwrap();
+        let slot = Arc::new(Slot {
+            replies: Mutex::new((VecDeque::new(), open.is_none())),
+            cv: Condvar::new(),
+        });
+        if let Some(map) = open.as_mut() {
+            map.insert(id, Arc::clone(&slot));
+        }
+        ReplyTicket { id, slot, tickets: Arc::clone(tickets) }
+    }
+    /*-- give msg to its ticket, or return it if there is none --*/
+    pub(crate) fn deliver(&self, msg: M) -> Option<M>
+    where M: Msg
+    {
+        let id = msg.get_correlation_id();
+        if id == 0 {
+            return Some(msg);
+        }
+        match self.open.lock().unwrap().as_ref().and_then(|map| map.get(&id)) {
+            Some(slot) => {
+                slot.replies.lock().unwrap().0.push_back(msg);
+                slot.cv.notify_all();
+                None
+            }
+            None => Some(msg),
+        }
+    }
+    /*-- no more replies will arrive --*/
+    pub(crate) fn close_all(&self) {
+        if let Some(map) = self.open.lock().unwrap().take() {
+            for slot in map.values() {
+                slot.replies.lock().unwrap().1 = true;
+                slot.cv.notify_all();
+            }
+        }
+    }
+}
+
+/*---------------------------------------------------------
+  ReplyTicket<M> - replies to one request
+*/
+#[derive(Debug)]
+pub struct ReplyTicket<M> {
+    id: u64,
+    slot: Arc<Slot<M>>,
+    tickets: Arc<Tickets<M>>,
+}
+impl<M> ReplyTicket<M> {
+    /*-- message id of the request --*/
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    /*-- blocks until next reply, PeerClosed if none can arrive --*/
+    pub fn wait(&self) -> CommResult<M> {
+        let mut replies = self.slot.replies.lock().unwrap();
+        loop {
+            if let Some(msg) = replies.0.pop_front() {
+                return Ok(msg);
+            }
+            if replies.1 {
+                return Err(CommError::PeerClosed);
+            }
+            replies = self.slot.cv.wait(replies).unwrap();
+        }
+    }
+    /*-- as wait, but Timeout if no reply within timeout --*/
+    pub fn wait_timeout(&self, timeout: Duration) -> CommResult<M> {
+        /*-- a timeout too long to represent never expires --*/
+        let deadline = match Instant::now().checked_add(timeout) {
+            Some(deadline) => deadline,
+            None => return self.wait(),
+        };
+        let mut replies = self.slot.replies.lock().unwrap();
+        loop {
+            if let Some(msg) = replies.0.pop_front() {
+                return Ok(msg);
+            }
+            if replies.1 {
+                return Err(CommError::PeerClosed);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(CommError::Timeout);
+            }
+            replies = self.slot.cv.wait_timeout(replies, deadline - now).unwrap().0;
+        }
+    }
+}
+impl<M> Drop for ReplyTicket<M> {
+    fn drop(&mut self) {
+        if let Some(map) = self.tickets.open.lock().unwrap().as_mut() {
+            map.remove(&self.id);
+        }
+    }
+}
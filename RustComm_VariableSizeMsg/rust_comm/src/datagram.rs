@@ -0,0 +1,156 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::datagram.rs - Message frames over UDP        //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Datagram<P,M,L> sends and receives messages over a
+   UdpSocket, one message per datagram, for small fire and
+   forget messages where loss is acceptable but connection
+   overhead and head-of-line blocking are not.
+   - messages are framed with P::encode and P::decode, so
+     they have the same header and MessageType as messages
+     sent by Connector
+   - a message larger than max_datagram, by default the
+     largest UDP payload, is not sent, and a received
+     datagram that isn't exactly one message is a Framing
+     error
+   - there are no replies, acknowledgements, or retries
+
+   After set_sequenced, a Datagram numbers the messages it
+   sends to each destination 1, 2, 3, ..., in the message
+   id field, and counts, for each source, the messages it
+   receives, the gaps in their numbers, lost, and those
+   that arrive after a later one, reordered.  A reordered
+   message was first counted as lost, so it is taken off
+   lost.  A duplicate counts as reordered.
+*/
+
+use crate::*;
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::net::UdpSocket;
+
+/*-- largest UDP payload over IPv4 --*/
+pub const MAX_DATAGRAM: usize = 65_507;
+
+/*---------------------------------------------------------
+  SequenceStats - what a sequenced Datagram knows about
+  messages from one source
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SequenceStats {
+    pub received: u64,
+    pub lost: u64,
+    pub reordered: u64,
+    /*-- highest sequence number received --*/
+    pub highest: u64,
+}
+impl SequenceStats {
+    fn record(&mut self, seq: u64) {
+        self.received += 1;
+        if seq > self.highest {
+            self.lost += seq - self.highest - 1;
+            self.highest = seq;
+        }
+        else {
+            self.reordered += 1;
+            self.lost = self.lost.saturating_sub(1);
+        }
+    }
+}
+
+/*---------------------------------------------------------
+  Datagram<P,M,L> - UDP message endpoint
+*/
+#[derive(Debug)]
+pub struct Datagram<P,M,L> {
+    sock: UdpSocket,
+    max: usize,
+    sequenced: bool,
+    next_seq: Mutex<HashMap<SocketAddr, u64>>,
+    stats: Mutex<HashMap<SocketAddr, SequenceStats>>,
+    _t: PhantomData<(P, M, L)>,
+}
+impl<P,M,L> Datagram<P,M,L>
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M> + Rcvr<M>,
+    L: Logger
+{
+    /*-- bind to addr, port 0 for any port --*/
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> CommResult<Datagram<P,M,L>> {
+        let sock = UdpSocket::bind(addr).map_err(CommError::Bind)?;
+        Ok(Datagram {
+            sock,
+            max: MAX_DATAGRAM,
+            sequenced: false,
+            next_seq: Mutex::new(HashMap::new()),
+            stats: Mutex::new(HashMap::new()),
+            _t: PhantomData,
+        })
+    }
+    pub fn local_addr(&self) -> CommResult<SocketAddr> {
+        Ok(self.sock.local_addr()?)
+    }
+    /*-- number sent messages and count received ones, see SequenceStats --*/
+    pub fn set_sequenced(&mut self, sequenced: bool) {
+        self.sequenced = sequenced;
+    }
+    /*-- largest message sent or received, e.g., 1472 to avoid IP fragmentation --*/
+    pub fn set_max_datagram(&mut self, max: usize) {
+        self.max = max.min(MAX_DATAGRAM);
+    }
+    pub fn get_max_datagram(&self) -> usize {
+        self.max
+    }
+    /*-- None blocks recv_from until a datagram arrives --*/
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> CommResult<()> {
+        Ok(self.sock.set_read_timeout(timeout)?)
+    }
+    /*-- send msg to dest in one datagram --*/
+    pub fn send_to<A: ToSocketAddrs>(&self, mut msg: M, dest: A) -> CommResult<()> {
+        let dest = dest.to_socket_addrs()?.next().ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidInput, "no destination address"
+        ))?;
+        /*-- an oversize message doesn't use up a sequence number --*/
+        let mut next = self.next_seq.lock().unwrap();
+        let seq = next.get(&dest).copied().unwrap_or(0) + 1;
+        if self.sequenced {
+            msg.set_msg_id(seq);
+        }
+        let frame = P::encode(&msg);
+        if frame.len() > self.max {
+            return Err(CommError::OversizeFrame { size: frame.len(), max: self.max });
+        }
+        if self.sequenced {
+            next.insert(dest, seq);
+        }
+        drop(next);
+        L::write(&format!("\n  sending datagram to {}", dest));
+        self.sock.send_to(&frame, dest)?;
+        Ok(())
+    }
+    /*-- next message and its source, Timeout after the read timeout --*/
+    pub fn recv_from(&self) -> CommResult<(M, SocketAddr)> {
+        let mut buf = vec![0u8; MAX_DATAGRAM + 1];
+        let (n, src) = self.sock.recv_from(&mut buf)?;
+        if n > self.max {
+            return Err(CommError::OversizeFrame { size: n, max: self.max });
+        }
+        let msg = match P::decode(&buf[..n])? {
+            Some((msg, used)) if used == n => msg,
+            _ => return Err(CommError::Framing(format!(
+                "datagram of {} bytes from {} is not one message", n, src
+            ))),
+        };
+        if self.sequenced {
+            self.stats.lock().unwrap().entry(src).or_default().record(msg.get_msg_id());
+        }
+        Ok((msg, src))
+    }
+    /*-- sequence counts for messages from src, if any were received --*/
+    pub fn stats(&self, src: SocketAddr) -> Option<SequenceStats> {
+        self.stats.lock().unwrap().get(&src).copied()
+    }
+}
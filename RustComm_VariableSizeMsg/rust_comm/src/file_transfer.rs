@@ -0,0 +1,647 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::file_transfer.rs - chunked file transfer     //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   A file is sent as a sequence of messages that share the
+   connection with ordinary traffic:
+
+     FILE_BEGIN  id | size | hash | name
+     FILE_CHUNK  id | bytes            (repeated)
+     FILE_END    id
+
+   All integers are u64, big endian.  The Listener answers
+   with FILE_ACK (id | bytes received) for every BEGIN and
+   CHUNK, echoes FILE_END (id | size) once the file has been
+   verified, and sends FILE_ERROR (id | text) if anything
+   goes wrong, including a file of the same name already in
+   its directory, which is never replaced.  A Connector
+   that fails while reading its file sends FILE_ERROR so
+   the Listener discards the partial file.
+
+   The sender reads a chunk only while fewer than
+   FILE_WINDOW chunks are unacknowledged, so a large file
+   is never held in memory, and stops once the transfer
+   has failed.  Connector holds back a posted END until
+   every file it is sending has been posted.
+
+   Defined Types:
+   - FileTransfer - sender's handle for one transfer
+   - FileStatus   - progress reported back to the sender
+   - FileReceiver - per connection reassembly, Listener side
+*/
+
+use rust_traits::*;
+use rust_message::*;
+use rust_blocking_queue::*;
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Write, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/*-- bytes of file content carried by each FILE_CHUNK --*/
+pub const FILE_CHUNK_SIZE:usize = 32 * 1024;
+/*-- FILE_CHUNKs a sender posts ahead of the Listener's FILE_ACKs --*/
+pub const FILE_WINDOW:usize = 8;
+
+const ID_SIZE:usize = 8;
+
+/*---------------------------------------------------------
+  FNV-1a 64 bit hash, used to verify transferred files
+*/
+#[derive(Debug, Copy, Clone)]
+pub struct FileHasher {
+    state: u64,
+}
+impl Default for FileHasher {
+    fn default() -> FileHasher {
+        FileHasher::new()
+    }
+}
+impl FileHasher {
+    pub fn new() -> FileHasher {
+        FileHasher { state: 0xcbf2_9ce4_8422_2325 }
+    }
+    pub fn update(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.state ^= *b as u64;
+            self.state = self.state.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    pub fn finish(&self) -> u64 {
+        self.state
+    }
+}
+/*-- hash of file contents --*/
+pub fn file_hash(path: &Path) -> std::io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = FileHasher::new();
+    let mut buf = vec![0u8; FILE_CHUNK_SIZE];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finish())
+}
+/*-- is mt one of the file transfer message types? --*/
+pub fn is_file_msg(mt: u8) -> bool {
+    mt == MessageType::FILE_BEGIN as u8
+        || mt == MessageType::FILE_CHUNK as u8
+        || mt == MessageType::FILE_END as u8
+        || mt == MessageType::FILE_ACK as u8
+        || mt == MessageType::FILE_ERROR as u8
+}
+/*---------------------------------------------------------
+  Message construction and parsing helpers
+*/
+fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    let slice = bytes.get(offset..offset + 8)?;
+    let mut dst = [0u8; 8];
+    dst.clone_from_slice(slice);
+    Some(u64::from_be_bytes(dst))
+}
+fn file_msg<M: Msg>(mt: MessageType, id: u64, rest: &[&[u8]]) -> M {
+    let mut body = id.to_be_bytes().to_vec();
+    for part in rest {
+        body.extend_from_slice(part);
+    }
+    let mut msg = M::new(HEADER_SIZE + body.len());
+    msg.set_type(mt as u8);
+    msg.set_content_bytes(&body);
+    msg
+}
+/*-- transfer id carried by every file message --*/
+pub fn file_msg_id<M: Msg>(msg: &M) -> Option<u64> {
+    u64_at(msg.get_content_bytes(), 0)
+}
+pub fn file_begin_msg<M: Msg>(id: u64, size: u64, hash: u64, name: &str) -> M {
+    file_msg(
+        MessageType::FILE_BEGIN, id,
+        &[&size.to_be_bytes(), &hash.to_be_bytes(), name.as_bytes()]
+    )
+}
+pub fn file_chunk_msg<M: Msg>(id: u64, bytes: &[u8]) -> M {
+    file_msg(MessageType::FILE_CHUNK, id, &[bytes])
+}
+pub fn file_end_msg<M: Msg>(id: u64, size: u64) -> M {
+    file_msg(MessageType::FILE_END, id, &[&size.to_be_bytes()])
+}
+pub fn file_ack_msg<M: Msg>(id: u64, received: u64) -> M {
+    file_msg(MessageType::FILE_ACK, id, &[&received.to_be_bytes()])
+}
+pub fn file_error_msg<M: Msg>(id: u64, err: &str) -> M {
+    file_msg(MessageType::FILE_ERROR, id, &[err.as_bytes()])
+}
+/*---------------------------------------------------------
+  FileStatus - reported to the sender of a file
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileStatus {
+    /*-- Listener has received and stored this many bytes --*/
+    Progress { received: u64, total: u64 },
+    /*-- file stored and verified by Listener --*/
+    Complete { size: u64 },
+    /*-- Listener refused or couldn't store the file, text is its reason --*/
+    Refused(String),
+    /*-- sender couldn't read the file, text describes why --*/
+    Failed(String),
+    /*-- connection ended before the transfer did --*/
+    Closed,
+}
+impl FileStatus {
+    pub fn is_done(&self) -> bool {
+        !matches!(self, FileStatus::Progress { .. })
+    }
+}
+/*---------------------------------------------------------
+  FileTransfer - returned by Connector::send_file
+  - status updates arrive in order on an internal queue
+*/
+#[derive(Debug)]
+pub struct FileTransfer {
+    id: u64,
+    name: String,
+    size: u64,
+    status: Arc<BlockingQueue<FileStatus>>,
+}
+impl FileTransfer {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+    /*-- blocks until next status update arrives --*/
+    pub fn next_status(&self) -> FileStatus {
+        self.status.de_q()
+    }
+    /*-----------------------------------------------------
+      Blocks until transfer completes or fails
+      - Refused if the Listener refused the file,
+        PeerClosed if the connection ended first, Io if
+        the file couldn't be read
+    */
+    pub fn wait(&self) -> CommResult<u64> {
+        loop {
+            match self.next_status() {
+                FileStatus::Progress { .. } => continue,
+                FileStatus::Complete { size } => return Ok(size),
+                FileStatus::Refused(why) => return Err(CommError::Refused(why)),
+                FileStatus::Failed(why) => return Err(CommError::Io(std::io::Error::other(why))),
+                FileStatus::Closed => return Err(CommError::PeerClosed),
+            }
+        }
+    }
+}
+/*---------------------------------------------------------
+  FileTransfers - Connector's table of active transfers
+  - the receive thread routes file replies here instead
+    of queuing them for get_message
+  - acks open the window post_file waits on, and a
+    retired transfer closes it
+  - counts files still being posted, so END can wait
+    for them
+*/
+type StatusQueue = Arc<BlockingQueue<FileStatus>>;
+
+#[derive(Debug)]
+struct Active {
+    size: u64,
+    acked: u64,
+    status: StatusQueue,
+}
+
+#[derive(Debug, Default)]
+pub struct FileTransfers {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, Active>>,
+    /*-- an ack arrived or a transfer was retired --*/
+    changed: Condvar,
+    posting: Mutex<usize>,
+    posted: Condvar,
+}
+impl FileTransfers {
+    pub fn new() -> FileTransfers {
+        FileTransfers::default()
+    }
+    /*-- register new transfer of size bytes --*/
+    pub fn begin(&self, name: &str, size: u64) -> FileTransfer {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let status = Arc::new(BlockingQueue::<FileStatus>::new());
+        let entry = Active { size, acked: 0, status: Arc::clone(&status) };
+        self.active.lock().unwrap().insert(id, entry);
+        FileTransfer { id, name: name.to_string(), size, status }
+    }
+    /*-- report status to sender, retiring transfer when done --*/
+    pub fn report(&self, id: u64, status: FileStatus) {
+        let mut active = self.active.lock().unwrap();
+        if let Some(entry) = active.get_mut(&id) {
+            let done = status.is_done();
+            if let FileStatus::Progress { received, .. } = status {
+                entry.acked = received;
+            }
+            entry.status.en_q(status);
+            if done {
+                active.remove(&id);
+            }
+            self.changed.notify_all();
+        }
+    }
+    /*-- end every active transfer when the connection is lost --*/
+    pub fn close_all(&self) {
+        let ids: Vec<u64> = self.active.lock().unwrap().keys().cloned().collect();
+        for id in ids {
+            self.report(id, FileStatus::Closed);
+        }
+    }
+    /*-----------------------------------------------------
+      Blocks until fewer than FILE_WINDOW chunks of the
+      sent bytes are unacknowledged
+      - false if the transfer has been retired
+    */
+    pub fn wait_window(&self, id: u64, sent: u64) -> bool {
+        let window = (FILE_WINDOW * FILE_CHUNK_SIZE) as u64;
+        let mut active = self.active.lock().unwrap();
+        loop {
+            let acked = match active.get(&id) {
+                Some(entry) => entry.acked,
+                None => return false,
+            };
+            if sent.saturating_sub(acked) < window {
+                return true;
+            }
+            active = self.changed.wait(active).unwrap();
+        }
+    }
+    /*-- a file's messages are being posted, until done_posting --*/
+    pub fn start_posting(&self) {
+        *self.posting.lock().unwrap() += 1;
+    }
+    pub fn done_posting(&self) {
+        *self.posting.lock().unwrap() -= 1;
+        self.posted.notify_all();
+    }
+    pub fn is_posting(&self) -> bool {
+        *self.posting.lock().unwrap() > 0
+    }
+    /*-- blocks until no file is being posted --*/
+    pub fn wait_posted(&self) {
+        let mut posting = self.posting.lock().unwrap();
+        while *posting > 0 {
+            posting = self.posted.wait(posting).unwrap();
+        }
+    }
+    /*-- returns true if msg was a file reply and was consumed --*/
+    pub fn route<M: Msg>(&self, msg: &M) -> bool {
+        let mt = msg.get_type();
+        if mt != MessageType::FILE_ACK as u8
+            && mt != MessageType::FILE_END as u8
+            && mt != MessageType::FILE_ERROR as u8
+        {
+            return false;
+        }
+        let id = match file_msg_id(msg) {
+            Some(id) => id,
+            None => return true,  // malformed file reply, discard
+        };
+        let body = msg.get_content_bytes();
+        let total = match self.active.lock().unwrap().get(&id) {
+            Some(entry) => entry.size,
+            None => return true,  // not ours or already retired
+        };
+        let status =
+            if mt == MessageType::FILE_ACK as u8 {
+                let received = u64_at(body, ID_SIZE).unwrap_or(0);
+                FileStatus::Progress { received, total }
+            }
+            else if mt == MessageType::FILE_END as u8 {
+                let size = u64_at(body, ID_SIZE).unwrap_or(total);
+                FileStatus::Complete { size }
+            }
+            else {
+                let text = String::from_utf8_lossy(&body[ID_SIZE..]);
+                FileStatus::Refused(text.to_string())
+            };
+        self.report(id, status);
+        true
+    }
+}
+/*---------------------------------------------------------
+  Reads path and posts FILE_CHUNKs and FILE_END, after the
+  sender has posted FILE_BEGIN
+  - post is called for every message, normally en_q on the
+    Connector's send queue, so chunks interleave with
+    other traffic
+  - each chunk waits for the window, see wait_window, and
+    nothing more is posted once the transfer is retired
+*/
+pub fn post_file<M, F>(
+    transfers: &FileTransfers, id: u64, path: &Path, post: F
+)
+where M: Msg, F: Fn(M)
+{
+    let rslt = (|| -> std::io::Result<Option<u64>> {
+        let mut file = File::open(path)?;
+        let mut buf = vec![0u8; FILE_CHUNK_SIZE];
+        let mut sent = 0u64;
+        loop {
+            if !transfers.wait_window(id, sent) {
+                return Ok(None);
+            }
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            post(file_chunk_msg::<M>(id, &buf[..n]));
+            sent += n as u64;
+        }
+        Ok(Some(sent))
+    })();
+    match rslt {
+        Ok(Some(sent)) => post(file_end_msg::<M>(id, sent)),
+        Ok(None) => {}
+        Err(err) => {
+            let text = format!("sender failed reading file: {}", err);
+            post(file_error_msg::<M>(id, &text));
+            transfers.report(id, FileStatus::Failed(text));
+        }
+    }
+}
+/*---------------------------------------------------------
+  FileReceiver - reassembles incoming files for one
+  connection into a target directory
+  - contents are written to a temporary file which is
+    linked into dir once size and hash have been verified
+  - a file already in dir is never replaced, the transfer
+    fails at FILE_BEGIN, or at FILE_END if the file
+    appeared meanwhile, e.g., from another connection.
+    Linking, unlike renaming, fails if the name exists.
+*/
+static TEMP_COUNT: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug)]
+struct Incoming {
+    name: String,
+    size: u64,
+    hash: u64,
+    received: u64,
+    hasher: FileHasher,
+    temp: PathBuf,
+    file: BufWriter<File>,
+}
+
+#[derive(Debug)]
+pub struct FileReceiver {
+    dir: Option<PathBuf>,
+    incoming: HashMap<u64, Incoming>,
+}
+impl FileReceiver {
+    /*-- dir None means file transfer is refused --*/
+    pub fn new(dir: Option<PathBuf>) -> FileReceiver {
+        FileReceiver {
+            dir,
+            incoming: HashMap::new(),
+        }
+    }
+    /*-- handle one file message, returning reply, if any --*/
+    pub fn handle<M: Msg>(&mut self, msg: &M) -> Option<M> {
+        let id = match file_msg_id(msg) {
+            Some(id) => id,
+            None => return Some(file_error_msg(0, "malformed file message")),
+        };
+        let mt = msg.get_type();
+        let body = msg.get_content_bytes();
+        let rslt =
+            if mt == MessageType::FILE_BEGIN as u8 {
+                self.begin(id, body).map(|_| Some(file_ack_msg(id, 0)))
+            }
+            else if mt == MessageType::FILE_CHUNK as u8 {
+                self.chunk(id, &body[ID_SIZE..])
+                    .map(|received| Some(file_ack_msg(id, received)))
+            }
+            else if mt == MessageType::FILE_END as u8 {
+                self.end(id).map(|size| Some(file_end_msg(id, size)))
+            }
+            else if mt == MessageType::FILE_ERROR as u8 {
+                /*-- sender gave up, nothing to reply --*/
+                self.discard(id);
+                Ok(None)
+            }
+            else {
+                Err(format!("unexpected file message {}", msg.type_display()))
+            };
+        match rslt {
+            Ok(reply) => reply,
+            Err(err) => {
+                self.discard(id);
+                Some(file_error_msg(id, &err))
+            }
+        }
+    }
+    fn begin(&mut self, id: u64, body: &[u8]) -> Result<(), String> {
+        let dir = self.dir.as_ref()
+            .ok_or_else(|| "file transfer not enabled on listener".to_string())?;
+        let size = u64_at(body, ID_SIZE).ok_or("malformed FILE_BEGIN")?;
+        let hash = u64_at(body, 2*ID_SIZE).ok_or("malformed FILE_BEGIN")?;
+        let raw = String::from_utf8_lossy(&body[3*ID_SIZE..]).to_string();
+        /*-- keep only final path component, never write outside dir --*/
+        let name = Path::new(&raw).file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| format!("invalid file name {:?}", raw))?
+            .to_string();
+        if self.incoming.contains_key(&id) {
+            return Err(format!("duplicate transfer id {}", id));
+        }
+        if dir.join(&name).exists() {
+            return Err(format!("{} already exists", name));
+        }
+        let count = TEMP_COUNT.fetch_add(1, Ordering::Relaxed);
+        let temp = dir.join(format!(".{}.{}.part", name, count));
+        let file = File::create(&temp)
+            .map_err(|e| format!("can't create {:?}: {}", temp, e))?;
+        self.incoming.insert(id, Incoming {
+            name, size, hash, received: 0,
+            hasher: FileHasher::new(),
+            temp,
+            file: BufWriter::new(file),
+        });
+        Ok(())
+    }
+    fn chunk(&mut self, id: u64, bytes: &[u8]) -> Result<u64, String> {
+        let inc = self.incoming.get_mut(&id)
+            .ok_or_else(|| format!("unknown transfer id {}", id))?;
+        if inc.received + bytes.len() as u64 > inc.size {
+            return Err(format!("{} exceeds announced size {}", inc.name, inc.size));
+        }
+        inc.file.write_all(bytes)
+            .map_err(|e| format!("write to {} failed: {}", inc.name, e))?;
+        inc.hasher.update(bytes);
+        inc.received += bytes.len() as u64;
+        Ok(inc.received)
+    }
+    fn end(&mut self, id: u64) -> Result<u64, String> {
+        let mut inc = self.incoming.remove(&id)
+            .ok_or_else(|| format!("unknown transfer id {}", id))?;
+        let rslt = (|| {
+            inc.file.flush()
+                .map_err(|e| format!("write to {} failed: {}", inc.name, e))?;
+            if inc.received != inc.size {
+                return Err(format!(
+                    "{}: received {} of {} bytes", inc.name, inc.received, inc.size
+                ));
+            }
+            if inc.hasher.finish() != inc.hash {
+                return Err(format!("{}: checksum mismatch", inc.name));
+            }
+            let target = self.dir.as_ref().unwrap().join(&inc.name);  // begin checked dir
+            fs::hard_link(&inc.temp, target).map_err(|e| match e.kind() {
+                std::io::ErrorKind::AlreadyExists => format!("{} already exists", inc.name),
+                _ => format!("can't store {}: {}", inc.name, e),
+            })?;
+            Ok(inc.size)
+        })();
+        drop(inc.file);
+        let _ = fs::remove_file(&inc.temp);
+        rslt
+    }
+    fn discard(&mut self, id: u64) {
+        if let Some(inc) = self.incoming.remove(&id) {
+            drop(inc.file);
+            let _ = fs::remove_file(&inc.temp);
+        }
+    }
+}
+impl Drop for FileReceiver {
+    /*-- connection closed, abandon unfinished transfers --*/
+    fn drop(&mut self) {
+        let ids: Vec<u64> = self.incoming.keys().cloned().collect();
+        for id in ids {
+            self.discard(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("rust_comm_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    fn hash_of(bytes: &[u8]) -> u64 {
+        let mut hasher = FileHasher::new();
+        hasher.update(bytes);
+        hasher.finish()
+    }
+    #[test]
+    fn receiver_reassembles_chunks() {
+        let dir = test_dir("reassemble");
+        let content = b"first chunk, second chunk".to_vec();
+        let mut rcvr = FileReceiver::new(Some(dir.clone()));
+        let begin: Message =
+            file_begin_msg(1, content.len() as u64, hash_of(&content), "a.txt");
+        let reply = rcvr.handle(&begin).unwrap();
+        assert_eq!(reply.get_type(), MessageType::FILE_ACK as u8);
+        for part in content.chunks(12) {
+            let reply = rcvr.handle(&file_chunk_msg::<Message>(1, part)).unwrap();
+            assert_eq!(reply.get_type(), MessageType::FILE_ACK as u8);
+        }
+        let reply = rcvr.handle(&file_end_msg::<Message>(1, 0)).unwrap();
+        assert_eq!(reply.get_type(), MessageType::FILE_END as u8);
+        assert_eq!(fs::read(dir.join("a.txt")).unwrap(), content);
+        let _ = fs::remove_dir_all(&dir);
+    }
+    #[test]
+    fn receiver_rejects_bad_hash() {
+        let dir = test_dir("bad_hash");
+        let mut rcvr = FileReceiver::new(Some(dir.clone()));
+        let _ = rcvr.handle(&file_begin_msg::<Message>(7, 3, 0, "b.txt"));
+        let _ = rcvr.handle(&file_chunk_msg::<Message>(7, b"abc"));
+        let reply = rcvr.handle(&file_end_msg::<Message>(7, 3)).unwrap();
+        assert_eq!(reply.get_type(), MessageType::FILE_ERROR as u8);
+        assert!(!dir.join("b.txt").exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+    #[test]
+    fn receiver_strips_directories_from_name() {
+        let dir = test_dir("strip");
+        let mut rcvr = FileReceiver::new(Some(dir.clone()));
+        let _ = rcvr.handle(&file_begin_msg::<Message>(2, 1, hash_of(b"x"), "../../c.txt"));
+        let _ = rcvr.handle(&file_chunk_msg::<Message>(2, b"x"));
+        let _ = rcvr.handle(&file_end_msg::<Message>(2, 1));
+        assert!(dir.join("c.txt").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+    #[test]
+    fn receiver_never_replaces_files() {
+        let dir = test_dir("replace");
+        fs::write(dir.join("e.txt"), b"keep").unwrap();
+        let mut rcvr = FileReceiver::new(Some(dir.clone()));
+        let reply = rcvr.handle(&file_begin_msg::<Message>(3, 1, hash_of(b"x"), "e.txt")).unwrap();
+        assert_eq!(reply.get_type(), MessageType::FILE_ERROR as u8);
+
+        /*-- appears while the transfer is under way --*/
+        let _ = rcvr.handle(&file_begin_msg::<Message>(4, 1, hash_of(b"x"), "f.txt"));
+        let _ = rcvr.handle(&file_chunk_msg::<Message>(4, b"x"));
+        fs::write(dir.join("f.txt"), b"keep").unwrap();
+        let reply = rcvr.handle(&file_end_msg::<Message>(4, 1)).unwrap();
+        assert_eq!(reply.get_type(), MessageType::FILE_ERROR as u8);
+        assert_eq!(fs::read(dir.join("e.txt")).unwrap(), b"keep");
+        assert_eq!(fs::read(dir.join("f.txt")).unwrap(), b"keep");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+    #[test]
+    fn acks_open_the_window() {
+        let files = Arc::new(FileTransfers::new());
+        let transfer = files.begin("g.txt", 1 << 30);
+        let id = transfer.id();
+        let window = (FILE_WINDOW * FILE_CHUNK_SIZE) as u64;
+        assert!(files.wait_window(id, window - 1));
+        let waiter = {
+            let files = Arc::clone(&files);
+            std::thread::spawn(move || files.wait_window(id, window + 10))
+        };
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!waiter.is_finished());
+        assert!(files.route(&file_ack_msg::<Message>(id, 11)));
+        assert!(waiter.join().unwrap());
+
+        /*-- a failed transfer closes it --*/
+        let waiter = {
+            let files = Arc::clone(&files);
+            std::thread::spawn(move || files.wait_window(id, 2 * window))
+        };
+        files.close_all();
+        assert!(!waiter.join().unwrap());
+        assert!(matches!(transfer.wait(), Err(CommError::PeerClosed)));
+    }
+    #[test]
+    fn transfers_route_replies() {
+        let files = FileTransfers::new();
+        let transfer = files.begin("d.txt", 10);
+        let id = transfer.id();
+        assert!(files.route(&file_ack_msg::<Message>(id, 4)));
+        assert!(files.route(&file_end_msg::<Message>(id, 10)));
+        assert!(!files.route(&Message::create_msg_str_fit("not a file msg")));
+        assert_eq!(
+            transfer.next_status(), 
+            FileStatus::Progress { received: 4, total: 10 }
+        );
+        assert_eq!(transfer.wait().unwrap(), 10);
+    }
+}
@@ -0,0 +1,39 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::frame_limit.rs - maximum message size        //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   A message header says how large its body is, and a
+   receiver allocates that much before reading the body, so
+   one hostile or corrupted header could make a Listener try
+   to allocate exabytes.  Rcvr implementations refuse bodies
+   larger than a maximum with CommError::OversizeFrame,
+   before allocating:
+   - Listener::set_max_msg_size,
+     Connector::set_max_msg_size,
+     Comm::set_max_msg_size, and their async counterparts
+     set the maximum, DEFAULT_MAX_MSG_SIZE, from
+     rust_traits, by default
+   - a frame that can't be read, too large, with a bad
+     prefix or version, or malformed, is answered with
+     PROTOCOL_ERROR, its body the error's text, then the
+     connection is closed, since the stream can't be
+     resynchronized after it
+*/
+
+use crate::*;
+
+/*-- PROTOCOL_ERROR telling the peer about e, None if e isn't the peer's bad frame --*/
+pub(crate) fn protocol_error<M: Msg>(e: &CommError) -> Option<M> {
+    match e {
+        CommError::OversizeFrame { .. } | CommError::BadMagic(_)
+        | CommError::UnsupportedVersion(_) | CommError::Framing(_) => {
+            let text = e.to_string();
+            let mut msg = M::new(HEADER_SIZE + text.len());
+            msg.set_type(MessageType::PROTOCOL_ERROR as u8);
+            msg.set_content_str(&text);
+            Some(msg)
+        }
+        _ => None,
+    }
+}
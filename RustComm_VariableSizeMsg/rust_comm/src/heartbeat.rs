@@ -0,0 +1,293 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::heartbeat.rs - idle connection detection     //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   A peer that vanishes without closing its connection,
+   e.g., a pulled cable or suspended VM, leaves reads
+   blocked forever.  A Heartbeat bounds that:
+   - after interval with nothing heard from the peer, send
+     it PING, and every Connector and Listener answers PING
+     with PONG, so a live peer is heard from again
+   - after idle_timeout with nothing heard, drop the
+     connection
+
+   Connector::set_heartbeat runs a thread that watches the
+   Connector's connection.  A dropped connection is
+   Disconnected, and is reconnected if the Connector has a
+   ReconnectPolicy.  Listener::set_heartbeat runs a thread
+   that watches every client connection, and
+   Listener::set_frame_timeout drops clients that take too
+   long to send a whole message, so a client trickling
+   bytes can't hold a connection open.  Dropped clients
+   are reported to observers as CloseReason::TimedOut.
+
+   The watching threads drop connections by shutting them
+   down, as ListenerHandle::shutdown does, which ends
+   blocked reads.  Time a Listener spends processing a
+   client's message doesn't count against the client.
+
+   Defined Types:
+   - Heartbeat - PING interval and idle timeout
+*/
+
+use crate::*;
+
+use std::time::Instant;
+
+/*---------------------------------------------------------
+  Heartbeat - how long a connection may be quiet
+  - an interval of idle_timeout or more never pings
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub idle_timeout: Duration,
+}
+impl Default for Heartbeat {
+    fn default() -> Heartbeat {
+        Heartbeat::new(Duration::from_secs(15))
+    }
+}
+impl Heartbeat {
+    /*-- ping after interval, drop after three intervals --*/
+    pub fn new(interval: Duration) -> Heartbeat {
+        Heartbeat { interval, idle_timeout: interval.saturating_mul(3) }
+    }
+}
+/*-- how often a watching thread checks, a fraction of the shortest wait --*/
+fn tick(heartbeat: Option<&Heartbeat>, frame_timeout: Option<Duration>) -> Duration {
+    let shortest = heartbeat.map(|hb| hb.interval.min(hb.idle_timeout))
+        .into_iter()
+        .chain(frame_timeout)
+        .min()
+        .unwrap_or(Duration::from_secs(1));
+    (shortest / 4).max(Duration::from_millis(5))
+}
+
+pub(crate) fn ping_msg<M: Msg>() -> M {
+    let mut msg = M::new(HEADER_SIZE);
+    msg.set_type(MessageType::PING as u8);
+    msg
+}
+pub(crate) fn pong_msg<M: Msg>() -> M {
+    let mut msg = M::new(HEADER_SIZE);
+    msg.set_type(MessageType::PONG as u8);
+    msg
+}
+
+/*-- what a watching thread does with a connection --*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Verdict {
+    Fine,
+    Ping,
+    Expired,
+}
+
+/*---------------------------------------------------------
+  Activity - when a connection was last heard from
+  - frame is when an incomplete message started arriving
+  - a busy connection is being processed, so isn't
+    expected to be heard from
+*/
+#[derive(Debug)]
+struct ActivityState {
+    heard: Instant,
+    frame: Option<Instant>,
+    pinged: bool,
+    busy: bool,
+}
+#[derive(Debug)]
+pub(crate) struct Activity {
+    state: Mutex<ActivityState>,
+}
+impl Default for Activity {
+    fn default() -> Activity {
+        Activity {
+            state: Mutex::new(ActivityState {
+                heard: Instant::now(),
+                frame: None,
+                pinged: false,
+                busy: false,
+            }),
+        }
+    }
+}
+impl Activity {
+    pub(crate) fn new() -> Activity {
+        Activity::default()
+    }
+    /*-- bytes arrived, starting a message if none was incomplete --*/
+    pub(crate) fn heard(&self) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        state.heard = now;
+        state.frame.get_or_insert(now);
+        state.pinged = false;
+    }
+    /*-- whole messages were taken, partial if one is still incomplete --*/
+    pub(crate) fn framed(&self, partial: bool) {
+        self.state.lock().unwrap().frame = partial.then(Instant::now);
+    }
+    /*-- a message is being processed --*/
+    pub(crate) fn busy(&self) {
+        self.state.lock().unwrap().busy = true;
+    }
+    /*-- ready for the next message, waits are measured from now --*/
+    pub(crate) fn ready(&self, partial: bool) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        state.busy = false;
+        state.heard = now;
+        state.pinged = false;
+        state.frame = partial.then_some(now);
+    }
+    /*-- Ping is returned once for each quiet spell --*/
+    pub(crate) fn check(
+        &self, heartbeat: Option<&Heartbeat>, frame_timeout: Option<Duration>
+    ) -> Verdict {
+        let mut state = self.state.lock().unwrap();
+        if state.busy {
+            return Verdict::Fine;
+        }
+        if let (Some(timeout), Some(start)) = (frame_timeout, state.frame) {
+            if start.elapsed() >= timeout {
+                return Verdict::Expired;
+            }
+        }
+        if let Some(hb) = heartbeat {
+            let quiet = state.heard.elapsed();
+            if quiet >= hb.idle_timeout {
+                return Verdict::Expired;
+            }
+            if quiet >= hb.interval && !state.pinged {
+                state.pinged = true;
+                return Verdict::Ping;
+            }
+        }
+        Verdict::Fine
+    }
+}
+
+/*---------------------------------------------------------
+  Watched<R> - reader that records when bytes arrive
+*/
+#[derive(Debug)]
+pub(crate) struct Watched<R> {
+    inner: R,
+    activity: Arc<Activity>,
+}
+impl<R: Read> Watched<R> {
+    pub(crate) fn new(inner: R, activity: Arc<Activity>) -> Watched<R> {
+        Watched { inner, activity }
+    }
+}
+impl<R: Read> Read for Watched<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.activity.heard();
+        }
+        Ok(n)
+    }
+}
+
+/*---------------------------------------------------------
+  Listener watching thread
+  - pings quiet clients and drops expired ones, until
+    run is cleared by ListenerHandle::shutdown
+*/
+pub(crate) fn watch_clients<P,M,L>(
+    conns: Arc<Connections>, run: Arc<AtomicBool>,
+    heartbeat: Option<Heartbeat>, frame_timeout: Option<Duration>
+)
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M>,
+    L: Logger + 'static
+{
+    let tick = tick(heartbeat.as_ref(), frame_timeout);
+    let ping = P::encode(&ping_msg::<M>());
+    let _ = thread::spawn(move || {
+        while run.load(Ordering::Relaxed) {
+            thread::sleep(tick);
+            for (id, verdict) in conns.check(heartbeat.as_ref(), frame_timeout) {
+                match verdict {
+                    Verdict::Ping => {
+                        conns.push(id, ping.clone());
+                    }
+                    Verdict::Expired => {
+                        L::write(&format!("\n--client {} timed out--", id));
+                    }
+                    Verdict::Fine => {}
+                }
+            }
+        }
+    });
+}
+
+/*---------------------------------------------------------
+  Connector watching thread
+  - pings a quiet Listener and drops the connection when
+    it expires, until the Connector has no more connections
+*/
+pub(crate) fn watch_link<M,L,T>(sq: Arc<BlockingQueue<M>>, link: Arc<Link<T>>)
+where
+    M: Msg + Clone + Send + Default + 'static,
+    L: Logger,
+    T: Transport
+{
+    while let Some(hb) = link.heartbeat() {
+        thread::sleep(tick(Some(&hb), None));
+        if link.state() != ConnectionState::Connected {
+            continue;
+        }
+        match link.activity().check(Some(&hb), None) {
+            Verdict::Ping => sq.en_q(ping_msg()),
+            Verdict::Expired => {
+                L::write("\n--connection timed out--");
+                link.lost(link.generation());
+            }
+            Verdict::Fine => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quiet_activity_is_pinged_once_then_expires() {
+        let hb = Heartbeat {
+            interval: Duration::from_millis(20), idle_timeout: Duration::from_millis(60)
+        };
+        let activity = Activity::new();
+        assert_eq!(activity.check(Some(&hb), None), Verdict::Fine);
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(activity.check(Some(&hb), None), Verdict::Ping);
+        assert_eq!(activity.check(Some(&hb), None), Verdict::Fine);
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(activity.check(Some(&hb), None), Verdict::Expired);
+
+        /*-- busy connections aren't expected to be heard from --*/
+        activity.busy();
+        assert_eq!(activity.check(Some(&hb), None), Verdict::Fine);
+        activity.ready(false);
+        assert_eq!(activity.check(Some(&hb), None), Verdict::Fine);
+    }
+    #[test]
+    fn incomplete_message_expires_at_frame_timeout() {
+        let timeout = Some(Duration::from_millis(30));
+        let activity = Activity::new();
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(activity.check(None, timeout), Verdict::Fine);
+        activity.heard();
+        activity.framed(false);
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(activity.check(None, timeout), Verdict::Fine);
+        activity.heard();
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(activity.check(None, timeout), Verdict::Expired);
+    }
+}
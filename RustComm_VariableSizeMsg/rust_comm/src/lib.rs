@@ -7,14 +7,68 @@
    Variable msg size, buffered transfer
 
    Defined Types:
-   - Listener<P,L>
-   - Connector<P,M,L>
+   - Listener<P,M,L,T>
+   - Connector<P,M,L,T>
+   - Comm<P,M,L>, see comm.rs
      - P is a processing type supporting application needs
      - L is a log type which is expected to be either
        VerboseLog or MuteLog
      - M is a message type
+     - T is a Transport, Tcp by default, see transport.rs
+       and Memory, an in-process Transport for tests, see
+       memory.rs
    P processes messages and its code must work with that
    of the Message type.
+
+   Connector::send_file and the Listener's file directory
+   support chunked file transfer, see file_transfer.rs.
+
+   Listener::start returns a ListenerHandle used to shut
+   the Listener down gracefully, see shutdown.rs.
+
+   Connector::with_reconnect reconnects with backoff after
+   a lost connection, see reconnect.rs.
+
+   Listener::set_observer registers a ConnectionObserver
+   for client connection events, see observer.rs.
+
+   Listener::set_reactor multiplexes clients on a few
+   non-blocking I/O threads, so the pool size doesn't cap
+   the number of clients, see reactor.rs.
+
+   Connector::request returns a ReplyTicket that receives
+   replies to its message, see correlation.rs.
+   Connector::send_and_wait waits for the first of them,
+   and try_get_message and get_message_timeout receive
+   without blocking indefinitely.
+
+   Listeners forward PUBLISH messages to connections that
+   SUBSCRIBE to their topics, see pubsub.rs.
+
+   Listener::send_to and broadcast send to connected
+   clients at any time, see push.rs.
+
+   Connector::set_heartbeat and Listener::set_heartbeat
+   ping quiet peers and drop connections that stay idle,
+   see heartbeat.rs.
+
+   Connector::set_checksums sends messages with checksums,
+   and set_checksum_policy on Connector and Listener says
+   whether a corrupted message closes its connection, see
+   checksum.rs.
+
+   Connector::set_max_msg_size and Listener::set_max_msg_size
+   refuse larger messages before allocating for them,
+   answering with PROTOCOL_ERROR, see frame_limit.rs.
+
+   Datagram sends and receives messages over UDP, see
+   datagram.rs.
+
+   The async feature adds AsyncConnector and AsyncListener,
+   tokio based and wire compatible, see async_comm.rs.
+
+   The tls feature adds Connector::with_tls and
+   Listener::set_tls, encrypting with rustls, see tls.rs.
    
    Traits used by these types are defined in rust_traits.
 */
@@ -22,6 +76,45 @@
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
+mod transport;
+pub use transport::*;
+mod memory;
+pub use memory::*;
+mod file_transfer;
+pub use file_transfer::*;
+mod comm;
+pub use comm::*;
+mod shutdown;
+pub use shutdown::*;
+mod reconnect;
+pub use reconnect::*;
+mod observer;
+pub use observer::*;
+mod correlation;
+pub use correlation::*;
+mod reactor;
+pub use reactor::REACTOR_OUTPUT_LIMIT;
+mod pubsub;
+pub use pubsub::*;
+mod push;
+pub use push::*;
+mod heartbeat;
+pub use heartbeat::*;
+mod checksum;
+pub use checksum::*;
+mod frame_limit;
+use frame_limit::*;
+mod datagram;
+pub use datagram::*;
+#[cfg(feature = "async")]
+mod async_comm;
+#[cfg(feature = "async")]
+pub use async_comm::*;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "tls")]
+pub use tls::*;
+
 /*-- rust_comm facilities --*/
 use rust_traits::*;
 use rust_message::*;
@@ -32,119 +125,303 @@ use rust_thread_pool::*;
 
 /*-- std library facilities --*/
 use std::fmt::*;
-use std::sync::{Arc, atomic::AtomicBool, atomic::Ordering};
-use std::net::{TcpStream, TcpListener, Shutdown};
-use std::io::{Result, BufReader, BufWriter, stdout, Write};
+use std::sync::{Arc, Mutex, atomic::AtomicBool, atomic::AtomicU64, atomic::Ordering};
+use std::net::{TcpStream, TcpListener, Shutdown, SocketAddr, ToSocketAddrs};
+use std::io::{BufReader, BufWriter, Write};
 use std::io::prelude::*;
 use std::thread;
 use std::thread::{JoinHandle};
-
-type L = MuteLog;
-type M = Message;
-type P = CommProcessing<L>;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use socket2::{Domain, Protocol, Socket, Type};
 
 /*---------------------------------------------------------
-  Connector<P,M,L> - attempts to connect to Listener<P,L>
+  Connector<P,M,L,T> - attempts to connect to
+  Listener<P,M,L,T>
+  - with_reconnect reconnects after a lost connection,
+    see reconnect.rs
 */
 #[derive(Debug)]
-pub struct Connector<P,M,L> where 
+pub struct Connector<P,M,L,T = Tcp> where 
     M: Msg + Clone + Send + Default,
     P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>, 
-    L: Logger + Debug + Copy + Clone + Default
+    L: Logger + Debug + Copy + Clone + Default,
+    T: Transport
 {
     snd_queue: Arc<BlockingQueue<M>>,
     rcv_queue: Arc<BlockingQueue<M>>,
+    files: Arc<FileTransfers>,
+    link: Arc<Link<T>>,
+    tickets: Arc<Tickets<M>>,
+    next_id: AtomicU64,
      _p: P,
-     connected: bool,
      log: L,
     //  msg_size: usize,
 }
-impl<P,M,L> Connector<P,M,L> where
+impl<P,M,L,T> Connector<P,M,L,T> where
     M: Msg + Clone + Send + Default + 'static,
-    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
-    L: Logger + Debug + Copy + Clone + Default
+    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M> + 'static,
+    L: Logger + Debug + Copy + Clone + Default + 'static,
+    T: Transport
 {    
     pub fn is_connected(&self) -> bool {
-        self.connected
+        self.link.state() == ConnectionState::Connected
+    }
+    /*-- current connection state --*/
+    pub fn state(&self) -> ConnectionState {
+        self.link.state()
+    }
+    /*-- blocks until the connection state changes --*/
+    pub fn next_state(&self) -> ConnectionState {
+        self.link.next_state()
+    }
+    /*-----------------------------------------------------
+      msg is given the next message id unless it has one
+      - END is held back until files being sent have been
+        posted, so it follows their last chunk
+    */
+    pub fn post_message(&self, mut msg: M) {
+        if msg.get_msg_id() == 0 {
+            msg.set_msg_id(self.next_id.fetch_add(1, Ordering::Relaxed));
+        }
+        if msg.get_type() == MessageType::END as u8 && self.files.is_posting() {
+            let files = Arc::clone(&self.files);
+            let sq = Arc::clone(&self.snd_queue);
+            let _ = thread::spawn(move || {
+                files.wait_posted();
+                sq.en_q(msg);
+            });
+            return;
+        }
+        self.snd_queue.en_q(msg);
     }
-    pub fn post_message(&self, msg: M) {
+    /*-----------------------------------------------------
+      Post msg with a new message id and return a ticket
+      for its replies.  Replies go to the ticket, not to
+      get_message, in whatever order they arrive.
+    */
+    pub fn request(&self, mut msg: M) -> ReplyTicket<M> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        msg.set_msg_id(id);
+        /*-- register first, the reply may beat en_q's return --*/
+        let ticket = Tickets::register(&self.tickets, id);
         self.snd_queue.en_q(msg);
+        ticket
+    }
+    /*-----------------------------------------------------
+      Post msg and wait for its first reply
+      - Timeout if none arrives within timeout, PeerClosed
+        if the connection ends first
+      - later replies go to get_message
+    */
+    pub fn send_and_wait(&self, msg: M, timeout: Duration) -> CommResult<M> {
+        self.request(msg).wait_timeout(timeout)
     }
     pub fn get_message(&self) -> M {
         self.rcv_queue.de_q()
     }
+    /*-- next message if one has arrived, never blocks --*/
+    pub fn try_get_message(&self) -> Option<M> {
+        self.rcv_queue.try_de_q()
+    }
+    /*-- as get_message, but Timeout if none arrives within timeout --*/
+    pub fn get_message_timeout(&self, timeout: Duration) -> CommResult<M> {
+        self.rcv_queue.de_q_timeout(timeout).ok_or(CommError::Timeout)
+    }
     pub fn has_msg(&self) -> bool {
-        self.rcv_queue.len() > 0
+        !self.rcv_queue.is_empty()
     }
-    pub fn new(addr: &'static str) -> std::io::Result<Connector<P,M,L>>
-    where
-        M: Msg + Clone + Send + Default + 'static,
-        P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M>,
-        L: Logger + Copy + Clone + Default
-    {
-        let mut _is_connected = false;
-        let rslt = TcpStream::connect(addr);
-        if rslt.is_err() {
-             print!("\n-- connection to {:?} failed --", addr);
-             return Err(std::io::Error::new(std::io::ErrorKind::Other, "connect failed"));
-        }
-        else {
-            _is_connected = true;
-            L::write(&format!("\n--connected to {:?}--", addr));
+    /*-- address of the Listener, while connected --*/
+    pub fn peer_addr(&self) -> CommResult<T::PeerAddr> {
+        self.link.peer_addr()
+    }
+    /*-- local address of the current connection --*/
+    pub fn local_addr(&self) -> CommResult<T::PeerAddr> {
+        self.link.local_addr()
+    }
+    /*-----------------------------------------------------
+      Ping the Listener when it has been quiet for
+      heartbeat.interval, and drop the connection when it
+      has been quiet for heartbeat.idle_timeout, see
+      heartbeat.rs
+      - a later call replaces the heartbeat
+    */
+    pub fn set_heartbeat(&self, heartbeat: Heartbeat) {
+        if self.link.set_heartbeat(heartbeat) {
+            let sq = Arc::clone(&self.snd_queue);
+            let link = Arc::clone(&self.link);
+            let _ = thread::spawn(move || watch_link::<M,L,T>(sq, link));
         }
-        let stream = rslt.unwrap();
-        let mut buf_writer = BufWriter::new(stream.try_clone()?);
-        let mut buf_reader = BufReader::new(stream.try_clone()?);
+    }
+    /*-- send every message with a checksum, see checksum.rs --*/
+    pub fn set_checksums(&self, on: bool) {
+        self.link.set_checksums(on);
+    }
+    /*-- what a corrupted message from the Listener does, see checksum.rs --*/
+    pub fn set_checksum_policy(&self, policy: ChecksumPolicy) {
+        self.link.set_checksum_policy(policy);
+    }
+    /*-- largest message body accepted from the Listener, see frame_limit.rs --*/
+    pub fn set_max_msg_size(&self, max: usize) {
+        self.link.set_max_msg_size(max);
+    }
+    /*-----------------------------------------------------
+      Send file in chunks that interleave with posted
+      messages.  Listener replies are routed to the
+      returned FileTransfer, not to get_message.
+      - chunks are read as the Listener acknowledges
+        earlier ones, see file_transfer.rs
+      - END posted meanwhile is sent after the last chunk
+      - fails if the Listener already has a file of that
+        name
+    */
+    pub fn send_file<F: AsRef<Path>>(&self, path: F) -> CommResult<FileTransfer> {
+        let path = path.as_ref().to_path_buf();
+        let name = path.file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| std::io::Error::new(
+                std::io::ErrorKind::InvalidInput, "path has no file name"
+            ))?
+            .to_string();
+        let size = std::fs::metadata(&path)?.len();
+        let hash = file_hash(&path)?;
+        let transfer = self.files.begin(&name, size);
+        let id = transfer.id();
+        self.snd_queue.en_q(file_begin_msg(id, size, hash, &name));
+
+        /*-- chunks are read and posted on their own thread --*/
+        let files = Arc::clone(&self.files);
+        let sq = Arc::clone(&self.snd_queue);
+        files.start_posting();
+        let _ = std::thread::spawn(move || {
+            post_file(&files, id, &path, |msg| sq.en_q(msg));
+            files.done_posting();
+        });
+        Ok(transfer)
+    }
+    /*-- connect once, a lost connection ends the Connector --*/
+    pub fn new<A: ToTransportAddrs<T>>(addr: A) -> CommResult<Connector<P,M,L,T>> {
+        Connector::connect(addr, None)
+    }
+    /*-- connect, then reconnect as policy allows if connection is lost --*/
+    pub fn with_reconnect<A: ToTransportAddrs<T>>(
+        addr: A, policy: ReconnectPolicy
+    ) -> CommResult<Connector<P,M,L,T>> {
+        Connector::connect(addr, Some(policy))
+    }
+    /*-----------------------------------------------------
+      Connect over TLS, see tls.rs, reconnecting as policy
+      allows if it is Some
+    */
+    #[cfg(feature = "tls")]
+    pub fn with_tls<A: ToTransportAddrs<T>>(
+        addr: A, tls: TlsClientConfig, policy: Option<ReconnectPolicy>
+    ) -> CommResult<Connector<P,M,L,T>> {
+        Connector::connect_link(addr, policy, Link::with_tls(tls))
+    }
+    fn connect<A: ToTransportAddrs<T>>(
+        addr: A, policy: Option<ReconnectPolicy>
+    ) -> CommResult<Connector<P,M,L,T>> {
+        Connector::connect_link(addr, policy, Link::new())
+    }
+    /*-- addr is resolved once, reconnects try the same addresses --*/
+    fn connect_link<A: ToTransportAddrs<T>>(
+        addr: A, policy: Option<ReconnectPolicy>, link: Link<T>
+    ) -> CommResult<Connector<P,M,L,T>> {
+        let addrs = addr.to_transport_addrs().map_err(CommError::Connect)?;
+        let strm = connect_any::<T>(&addrs).map_err(CommError::Connect)?;
+        L::write(&format!("\n--connected to {:?}--", addrs));
+        let link = Arc::new(link);
+        let (buf_reader, generation) = link.install(strm)?;
         
         let send_queue = Arc::new(BlockingQueue::<M>::new());
         let recv_queue = Arc::new(BlockingQueue::<M>::new());
+        let files = Arc::new(FileTransfers::new());
+        let tickets = Arc::new(Tickets::<M>::new());
         
         /*-- send thread reads input queue and sends msg --*/
         let sqm = Arc::clone(&send_queue);
+        let slk = Arc::clone(&link);
         let _ = std::thread::spawn(move || {
-            let ssq = Arc::clone(&sqm);
-            loop {
-                L::write("\n  -- dequing send msg --");
-                let msg = ssq.de_q();
-                L::write("\n  sending msg");
-                let msg_type = msg.get_type();
-                let rslt = P::buf_send_message(&msg, &mut buf_writer);
-                if rslt.is_err() {
-                    // may cause panic if io doesn't complete before 
-                    // thread shuts down
-                    // print!("\n  msg send error");
-                    break;
-                }
-                L::write("\n  -- send successful --");
-                if msg_type == MessageType::END as u8 {
-                    L::write("\n--terminating connector send thread--");
-                    break;
-                }
-            }            
+            connector_send_loop::<P,M,L,T>(&sqm, &slk);
         });
         /*-- recv thread recvs msg (may block) and enQs for user --*/
         let rqm = Arc::clone(&recv_queue);
+        let sqr = Arc::clone(&send_queue);
+        let ftm = Arc::clone(&files);
+        let rlk = Arc::clone(&link);
+        let rtk = Arc::clone(&tickets);
         let _ = std::thread::spawn(move || {
-            let srq = Arc::clone(&rqm);
+            let mut buf_reader = buf_reader;
+            let mut generation = generation;
             loop {
                 L::write("\n  attempting to receive msg in connector");
-                let rslt = P::buf_recv_message(&mut buf_reader);
+                let rslt = buf_reader.recv::<P,M>(rlk.max_msg_size());
+                if let Err(e) = &rslt {
+                    if rlk.checksum_policy().skips(e) {
+                        L::write(&format!("\n  dropped message: {}", e));
+                        continue;
+                    }
+                    rlk.reject::<P,M>(generation, e);
+                }
                 if rslt.is_err() {
-                    L::write("\n--terminating connector receive thread--");
-                    break;
+                    rlk.lost(generation);
+                    if rlk.is_closing() || policy.is_none() {
+                        rlk.close(false);
+                        L::write("\n--terminating connector receive thread--");
+                        break;
+                    }
+                    match reconnect::<L,T>(&addrs, &policy.unwrap(), &rlk) {
+                        Some((rdr, gen)) => {
+                            buf_reader = rdr;
+                            generation = gen;
+                            continue;
+                        }
+                        None => break,
+                    }
                 }
                 let msg = rslt.unwrap();
-                srq.en_q(msg);
-                L::write(&format!("\n  recv_queue len: {}", srq.len()));
+                rlk.activity().heard();
+                /*-- heartbeats are answered here, not passed on --*/
+                if msg.get_type() == MessageType::PING as u8 {
+                    sqr.en_q(pong_msg());
+                    continue;
+                }
+                if msg.get_type() == MessageType::PONG as u8 {
+                    continue;
+                }
+                /*-- file transfer replies go to their FileTransfer --*/
+                if ftm.route(&msg) {
+                    continue;
+                }
+                /*-- Listener is shutting down, end after queued msgs --*/
+                if msg.get_type() == MessageType::SHUTDOWN as u8 {
+                    let mut end = M::new(HEADER_SIZE);
+                    end.set_type(MessageType::END as u8);
+                    sqr.en_q(end);
+                }
+                /*-- replies to a request go to its ticket --*/
+                let msg = match rtk.deliver(msg) {
+                    Some(msg) => msg,
+                    None => continue,
+                };
+                rqm.en_q(msg);
+                L::write(&format!("\n  recv_queue len: {}", rqm.len()));
             }
+            /*-- waiting tickets get PeerClosed, and transfers fail --*/
+            rtk.close_all();
+            ftm.close_all();
         });
-        /*-- return new Connector as std::io::Result --*/
+        /*-- return new Connector as CommResult --*/
         let me =
         Self {
             _p: P::default(),
             snd_queue: send_queue,
             rcv_queue: recv_queue,
-            connected: _is_connected,
+            files,
+            link,
+            tickets,
+            next_id: AtomicU64::new(1),
             log: L::default(),
             // msg_size: msg_size,
         };
@@ -152,150 +429,600 @@ impl<P,M,L> Connector<P,M,L> where
     }
 }
 /*---------------------------------------------------------
-  Each threadpool thread executes thread_proc
-  - get next TcpStream instance, strm
-  - communicate with connecter using handle_client(strm)
+  Reconnect to addrs, waiting policy.delay before each
+  attempt
+  - returns reader and generation of the new connection,
+    or None after max_retries failed attempts
+*/
+fn reconnect<L: Logger, T: Transport>(
+    addrs: &[T::Addr], policy: &ReconnectPolicy, link: &Link<T>
+) -> Option<(LinkReader<T>, u64)> {
+    for attempt in 1..=policy.max_retries {
+        link.connecting(attempt);
+        thread::sleep(policy.delay(attempt - 1));
+        L::write(&format!("\n--reconnecting to {:?}, attempt {}--", addrs, attempt));
+        if let Ok(strm) = connect_any::<T>(addrs) {
+            if let Ok(installed) = link.install(strm) {
+                return Some(installed);
+            }
+        }
+    }
+    link.close(true);
+    None
+}
+/*---------------------------------------------------------
+  Connector send loop
+  - pending holds messages that may not have reached the
+    OS: those written to the current connection's buffer
+    and not yet flushed, followed by the next to send
+  - when the connection changes, all of pending is sent
+    again on the new one
+  - ends after sending END, or when there will be no
+    more connections
 */
-pub fn thread_proc(bq: &BlockingQueue<TcpStream>, run: &Arc<AtomicBool>) {
+fn connector_send_loop<P,M,L,T>(sq: &BlockingQueue<M>, link: &Link<T>)
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M>,
+    L: Logger,
+    T: Transport
+{
+    let mut pending = Vec::<M>::new();
+    let mut written = 0;
+    let mut generation = 0;
     loop {
-        if !run.load(Ordering::Relaxed) {
-            print!("\n  terminating listener thread");
-            // let _ = std::io::stdout().flush();
+        if written == pending.len() {
+            L::write("\n  -- dequing send msg --");
+            let msg = sq.de_q();
+            if msg.get_type() == MessageType::END as u8 {
+                link.set_closing();
+            }
+            pending.push(msg);
+        }
+        let mut conn = match link.writer() {
+            Some(conn) => conn,
+            None => break,
+        };
+        if conn.generation != generation {
+            generation = conn.generation;
+            written = 0;
+        }
+        if conn.checksums {
+            let msg = &mut pending[written];
+            msg.set_flags(msg.get_flags() | FLAG_CHECKSUM);
+        }
+        let writer = conn.writer.as_mut().unwrap();
+        L::write("\n  sending msg");
+        if writer.send::<P,M>(&pending[written], sq.is_empty()).is_err() {
+            drop(conn);
+            link.lost(generation);
+            continue;
+        }
+        L::write("\n  -- send successful --");
+        let is_end = pending[written].get_type() == MessageType::END as u8;
+        written += 1;
+        if writer.is_sent() {
+            pending.drain(..written);
+            written = 0;
+        }
+        if is_end {
+            let _ = writer.flush();
+            L::write("\n--terminating send thread--");
             break;
         }
-        let strm = bq.de_q();
-        let rslt = handle_client(strm);
-        if rslt.is_err() {
-            print!("\n  stream failure in handle_client");
-            break;  // this kills one threadpool thread
+    }
+}
+/*---------------------------------------------------------
+  Each threadpool thread executes thread_proc
+  - get next Job, a registered connection, until
+    receiving the stop sentinel, None
+  - communicate with connecter using handle_client with
+    a clone of proc, the Listener's processor
+  - unregister the connection when handle_client returns
+    and tell observer, if any, how it closed
+  - file_dir is where received files are stored, None
+    refuses file transfers
+*/
+pub fn thread_proc<P,M,L,T>(
+    bq: &BlockingQueue<Job<T>>, conns: &Connections,
+    proc: &P, file_dir: &Option<PathBuf>,
+    observer: &Option<Arc<dyn ConnectionObserver<T::PeerAddr>>>
+)
+where
+    M: Msg + Clone + Send + Default,
+    P: Clone + Sndr<M> + Rcvr<M> + Process<M>,
+    L: Logger,
+    T: Transport
+{
+    while let Some((id, peer, reader, writer)) = bq.de_q() {
+        let rslt = handle_client::<P,M,L,_,_>(
+            reader, &writer, T::socket_addr(&peer), proc.clone(), file_dir.clone(),
+            (conns, id)
+        );
+        let closed_by = conns.remove(id);
+        report_close::<L,_>(peer, rslt, closed_by, observer);
+    }
+    L::write("\n  terminating listener thread");
+}
+/*-----------------------------------------------------------
+  Tell observer, if any, how a connection closed
+  - closed_by is from Connections::remove, a timed out
+    connection's failed receive isn't reported as an error
+*/
+pub(crate) fn report_close<L: Logger, A: Clone>(
+    peer: A, rslt: CommResult<CloseReason>, closed_by: Option<CloseReason>,
+    observer: &Option<Arc<dyn ConnectionObserver<A>>>
+) {
+    let reason = match rslt {
+        _ if closed_by == Some(CloseReason::TimedOut) => CloseReason::TimedOut,
+        Ok(reason) => closed_by.unwrap_or(reason),
+        Err(e) => {
+            L::write(&format!("\n  client handler failed: {}", e));
+            if let Some(obs) = observer {
+                obs.on_error(peer.clone(), &e);
+            }
+            CloseReason::Error
         }
+    };
+    if let Some(obs) = observer {
+        obs.on_close(peer, reason);
     }
 }
 /*---------------------------------------------------------
   Handle client messages:
-  - extract message, msg, from stream 
-  - process using reply_msg = P::process_message(msg)
-  - send back reply_msg
+  - extract message, msg, from reader, peer is the
+    client's address, if it has one
+  - process using replies = proc.process_message(msg, ctx)
+    where proc is this connection's processor instance
+  - send back replies on writer, which is shared with
+    ListenerHandle::shutdown
+  - client is the Listener's registry and this
+    connection's id in it, for publish and subscribe,
+    and the connection's Activity, for heartbeats
+  - each message is handled by handle_message
+  - a client closing without END ends the session, other
+    receive failures are returned, except corrupted
+    messages the Listener's ChecksumPolicy drops
+  - a frame that can't be read is answered with
+    PROTOCOL_ERROR, see frame_limit.rs
+  - returns the reason the session ended
 */
-pub fn handle_client(strm: TcpStream) -> Result<()> {
+pub fn handle_client<P,M,L,R,W>(
+    reader: R, writer: &Mutex<BufWriter<W>>, peer: Option<SocketAddr>,
+    mut proc: P, file_dir: Option<PathBuf>, client: (&Connections, u64)
+) -> CommResult<CloseReason>
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M> + Rcvr<M> + Process<M>,
+    L: Logger,
+    R: Read,
+    W: Write
+{
 
     /*-- thread handles client until receiving an END or QUIT message --*/
-    let mut buf_writer = BufWriter::new(strm.try_clone()?);
-    let mut buf_reader = BufReader::new(strm.try_clone()?);
-    loop {
+    let activity = client.0.activity(client.1);
+    let policy = client.0.checksum_policy();
+    let max = client.0.max_msg_size();
+    let mut buf_reader = BufReader::new(Watched::new(reader, Arc::clone(&activity)));
+    let mut files = FileReceiver::new(file_dir);
+    let mut ctx = ConnContext::new(peer);
+    ctx.client = Some(ClientId(client.1));
+    let reason = loop {
         L::write("\n  attempting to recv message in client handler");
         // let _ = std::io::stdout().flush();
-        let rslt:Result<Message> = P::buf_recv_message(&mut buf_reader);
+        let msg: M = match P::buf_recv_message_max(&mut buf_reader, max) {
+            Ok(msg) => msg,
+            Err(CommError::PeerClosed) => {
+                L::write("\n  socket session closed abruptly");
+                break CloseReason::Dropped;
+            }
+            Err(e) if policy.skips(&e) => {
+                L::write(&format!("\n  dropped message: {}", e));
+                continue;
+            }
+            Err(e) => {
+                if let Some(reply) = protocol_error::<M>(&e) {
+                    let _ = P::buf_send_message(&reply, &mut writer.lock().unwrap());
+                }
+                return Err(e);
+            }
+        };
         L::write("\n  receive successful in client handler");
-        if rslt.is_err() {
-            print!("\n  socket session closed abruptly");
-            // let _ = std::io::stdout().flush();
-            break;
+        activity.busy();
+        let replies = match handle_message::<P,M,L>(msg, &mut proc, &mut ctx, &mut files, client) {
+            Handled::Replies(replies) => replies,
+            Handled::Close(reason) => {
+                L::write("\n--terminating client handler loop--");
+                break reason;
+            }
+        };
+        activity.ready(!buf_reader.buffer().is_empty());
+        let mut buf_writer = writer.lock().unwrap();
+        for reply in replies {
+            let _ = P::buf_send_message(&reply, &mut buf_writer);
         }
-        let mut msg = rslt.unwrap();
-        if msg.get_type() == MessageType::END as u8 {
-            L::write("\n--listener received END message--");
-            L::write("\n--terminating client handler loop--");           
-            break;
+    };
+    let _ = writer.lock().unwrap().flush();
+    L::write("\n  terminating handler thread");
+    Ok(reason)
+}
+/*-- result of handling one received message --*/
+pub(crate) enum Handled<M> {
+    Replies(Vec<M>),
+    Close(CloseReason),
+}
+/*---------------------------------------------------------
+  Handle one message for a client session
+  - END and QUIT close the session
+  - file transfer messages go to files
+  - SUBSCRIBE, UNSUBSCRIBE, and PUBLISH go to the
+    client's Connections registry
+  - PING is answered with PONG, PONG is dropped
+  - others go to proc
+  - replies without a correlation id get msg's id, so the
+    client can match them to its request
+  - replies to a checksummed msg are checksummed
+*/
+pub(crate) fn handle_message<P,M,L>(
+    msg: M, proc: &mut P, ctx: &mut ConnContext, files: &mut FileReceiver,
+    client: (&Connections, u64)
+) -> Handled<M>
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M> + Process<M>,
+    L: Logger
+{
+    ctx.msg_count += 1;
+    if msg.get_type() == MessageType::END as u8 {
+        L::write("\n--listener received END message--");
+        return Handled::Close(CloseReason::End);
+    }
+    if msg.get_type() == MessageType::QUIT as u8 {
+        L::write("\n--listener received QUIT message--");
+        return Handled::Close(CloseReason::Quit);
+    }
+    if is_file_msg(msg.get_type()) {
+        return Handled::Replies(files.handle(&msg).into_iter().collect());
+    }
+    if msg.get_type() == MessageType::PONG as u8 {
+        return Handled::Replies(Vec::new());
+    }
+    let req_id = msg.get_msg_id();
+    let checked = msg.get_flags() & FLAG_CHECKSUM;
+    let mut replies =
+        if is_topic_msg(msg.get_type()) {
+            let (conns, id) = client;
+            conns.topic_message::<P,M,L>(id, &msg).into_iter().collect()
         }
-        else if msg.get_type() == MessageType::QUIT as u8 {
-            L::write("\n--listener received QUIT message--");
-            L::write("\n--terminating client handler loop--");
-            break;
+        else if msg.get_type() == MessageType::PING as u8 {
+            vec![pong_msg()]
         }
-        P::process_message(&mut msg);
-        let _ = P::buf_send_message(&msg, &mut buf_writer);
-    } 
-    L::write("\n  terminating handler thread");
-    Ok(())
+        else {
+            proc.process_message(msg, ctx)
+        };
+    for reply in &mut replies {
+        if reply.get_correlation_id() == 0 {
+            reply.set_correlation_id(req_id);
+        }
+        reply.set_flags(reply.get_flags() | checked);
+    }
+    Handled::Replies(replies)
 }
 /*---------------------------------------------------------
-  Listener<P,L> 
+  Listener<P,M,L,T> 
   - attempts to bind to listening address
-  - blocks on accept via the incoming iterator
+  - polls a non-blocking accept until shut down with
+    the ListenerHandle returned by start
+  - each client connection is handled with its own clone
+    of the Listener's processor, p
+  - by default a pool thread serves one client at a time,
+    set_reactor lets the pool serve any number
 */
 #[derive(Debug)]
-pub struct Listener<P,L> 
+pub struct Listener<P,M,L,T = Tcp> 
 where 
-P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M> + 'static,
-L: Logger + Debug + Copy + Clone + Default
+M: Msg + Clone + Send + Default + 'static,
+P: Debug + Clone + Send + Default + Sndr<M> + Rcvr<M> + Process<M> + 'static,
+L: Logger + Debug + Copy + Clone + Default,
+T: Transport
 {
     p: P,
-    run: Arc<AtomicBool>,  // used to terminate Listener
+    _m: std::marker::PhantomData<M>,
+    run: Arc<AtomicBool>,  // used to terminate accept loop
     log: L, 
     num_thrds: u8,
-    addr: &'static str,
-    // msg_size: usize,
+    local: Option<T::Addr>,
+    file_dir: Option<PathBuf>,
+    observer: Option<Arc<dyn ConnectionObserver<T::PeerAddr>>>,
+    reactor: Option<(u8, StartReactor<P,T>)>,
+    heartbeat: Option<Heartbeat>,
+    frame_timeout: Option<Duration>,
+    checksum_policy: ChecksumPolicy,
+    max_msg_size: usize,
+    clients: Clients<P,M,T>,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsServerConfig>,
     /*-- ThreadPool instance is aggregated in self.start() --*/
 }
-impl<P,L> Listener<P,L> 
+/*-- what start hands the thread that accepts clients --*/
+pub(crate) struct Serving<P, T: Transport> {
+    pub(crate) lsnr: T::Listener,
+    pub(crate) run: Arc<AtomicBool>,
+    pub(crate) conns: Arc<Connections>,
+    pub(crate) proc: P,
+    pub(crate) file_dir: Option<PathBuf>,
+    pub(crate) observer: Option<Arc<dyn ConnectionObserver<T::PeerAddr>>>,
+    pub(crate) nt: u8,
+}
+/*-- reactor::start, set only for Tcp Listeners --*/
+type StartReactor<P,T> = fn(Serving<P,T>, u8) -> std::io::Result<JoinHandle<Workers>>;
+
+impl<P,M,L,T> Listener<P,M,L,T> 
 where 
-    P: Debug + Copy + Clone + Send + Sync + Default + Sndr<M> + Rcvr<M> + Process<M> + 'static,
-    L: Logger + Debug + Copy + Clone + Default
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Clone + Send + Default + Sndr<M> + Rcvr<M> + Process<M> + 'static,
+    L: Logger + Debug + Copy + Clone + Default + 'static,
+    T: Transport
     {    
-    pub fn new(nt: u8) -> Listener<P,L> {
+    pub fn new(nt: u8) -> Listener<P,M,L,T> {
+        Listener::with_processor(nt, P::default())
+    }
+    /*-- p is cloned for each client connection --*/
+    pub fn with_processor(nt: u8, p: P) -> Listener<P,M,L,T> {
         Listener {
-              p: P::default(),
+              p,
+              _m: std::marker::PhantomData,
               run: Arc::new(AtomicBool::new(true)),
               log: L::default(),
               num_thrds: nt,
-              addr: "",
-            //   msg_size: 64,
+              local: None,
+              file_dir: None,
+              observer: None,
+              reactor: None,
+              heartbeat: None,
+              frame_timeout: None,
+              checksum_policy: ChecksumPolicy::default(),
+              max_msg_size: DEFAULT_MAX_MSG_SIZE,
+              clients: Clients::new(),
+              #[cfg(feature = "tls")]
+              tls: None,
         }
     }
-    // pub fn set_msg_size(&mut self, msg_size:usize) {
-    //     self.msg_size = msg_size;
-    // }
-    // pub fn get_msg_size(&self) -> usize {
-    //     self.msg_size
-    // }
-    /*-- starts thread wrapping incoming loop which often blocks --*/
-    pub fn start(&mut self, addr: &'static str) -> Result<JoinHandle<()>> 
+    /*-- accept file transfers, storing files in dir --*/
+    pub fn set_file_dir<F: AsRef<Path>>(&mut self, dir: F) {
+        self.file_dir = Some(dir.as_ref().to_path_buf());
+    }
+    pub fn get_file_dir(&self) -> Option<&Path> {
+        self.file_dir.as_deref()
+    }
+    /*-- report connection events to observer, takes effect at start --*/
+    pub fn set_observer(&mut self, observer: Arc<dyn ConnectionObserver<T::PeerAddr>>) {
+        self.observer = Some(observer);
+    }
+    /*-- ping quiet clients, dropping those idle too long, see heartbeat.rs, takes effect at start --*/
+    pub fn set_heartbeat(&mut self, heartbeat: Heartbeat) {
+        self.heartbeat = Some(heartbeat);
+    }
+    /*-- drop clients that take longer than timeout to send a message, takes effect at start --*/
+    pub fn set_frame_timeout(&mut self, timeout: Duration) {
+        self.frame_timeout = Some(timeout);
+    }
+    /*-- what a corrupted message does to its client's connection, takes effect at start --*/
+    pub fn set_checksum_policy(&mut self, policy: ChecksumPolicy) {
+        self.checksum_policy = policy;
+    }
+    /*-- serve clients over TLS, see tls.rs, takes effect at start --*/
+    #[cfg(feature = "tls")]
+    pub fn set_tls(&mut self, tls: TlsServerConfig) {
+        self.tls = Some(tls);
+    }
+    /*-- largest message body accepted from clients, see frame_limit.rs, takes effect at start --*/
+    pub fn set_max_msg_size(&mut self, max: usize) {
+        self.max_msg_size = max;
+    }
+    pub fn get_max_msg_size(&self) -> usize {
+        self.max_msg_size
+    }
+    /*-- this Listener's clients, for pushing to them from other threads --*/
+    pub fn clients(&self) -> Clients<P,M,T> {
+        self.clients.clone()
+    }
+    /*-- serve clients registered with clients, made by Clients::new --*/
+    pub fn set_clients<Q>(&mut self, clients: &Clients<Q,M,T>) {
+        self.clients = clients.with_framing::<P>();
+    }
+    /*-- queue msg for client, PeerClosed if it isn't connected --*/
+    pub fn send_to(&self, client: ClientId, msg: M) -> CommResult<()> {
+        self.clients.send_to(client, msg)
+    }
+    /*-- queue msg for every connected client, returning their count --*/
+    pub fn broadcast(&self, msg: M) -> usize {
+        self.clients.broadcast(msg)
+    }
+    /*-- connected clients and their peer addresses --*/
+    pub fn list_clients(&self) -> Vec<(ClientId, T::PeerAddr)> {
+        self.clients.list()
+    }
+    /*-- address bound by start, shows the port chosen for port 0 --*/
+    pub fn local_addr(&self) -> Option<T::Addr> {
+        self.local.clone()
+    }
+    /*-- starts thread wrapping accept loop, see ListenerHandle --*/
+    pub fn start<A: ToTransportAddrs<T>>(&mut self, addr: A) -> CommResult<ListenerHandle<P,M,L>> 
     {
-        self.addr = addr;
-        L::write(&format!("\n--starting listener on {:?}--", addr));
-        let rslt = TcpListener::bind(addr);
-        if rslt.is_err() {
-            print!("\n  binding to {:?} failed", addr);
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "listener bind failed"));
+        #[cfg(feature = "tls")]
+        if self.tls.is_some() && self.reactor.is_some() {
+            return Err(CommError::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported, "reactor mode doesn't support TLS"
+            )));
+        }
+        let addrs = addr.to_transport_addrs().map_err(CommError::Bind)?;
+        L::write(&format!("\n--starting listener on {:?}--", addrs));
+        let lsnr = bind_any::<T>(&addrs).map_err(CommError::Bind)?;
+        self.local = Some(T::listen_addr(&lsnr)?);
+        self.run.store(true, Ordering::Relaxed);
+        let conns = Arc::clone(self.clients.connections());
+        conns.reopen();
+        conns.set_checksum_policy(self.checksum_policy);
+        conns.set_max_msg_size(self.max_msg_size);
+        let serving = Serving {
+            lsnr,
+            run: Arc::clone(&self.run),
+            conns: Arc::clone(&conns),
+            proc: self.p.clone(),
+            file_dir: self.file_dir.clone(),
+            observer: self.observer.clone(),
+            nt: self.num_thrds,
+        };
+        if self.heartbeat.is_some() || self.frame_timeout.is_some() {
+            watch_clients::<P,M,L>(
+                Arc::clone(&conns), Arc::clone(&self.run), self.heartbeat, self.frame_timeout
+            );
         }
-        let tcpl = rslt.unwrap();
-        let nt = self.num_thrds;
-        let run_ref = Arc::clone(&self.run);
+        let handle = match self.reactor {
+            Some((io_thrds, start)) => start(serving, io_thrds)?,
+            None => self.serve(serving),
+        };
+        Ok(ListenerHandle::new(Arc::clone(&self.run), conns, handle))
+    }
+    #[cfg(feature = "tls")]
+    fn serve(&self, serving: Serving<P,T>) -> JoinHandle<Workers> {
+        match &self.tls {
+            Some(tls) => tls::start::<P,M,L,T>(serving, tls.clone()),
+            None => serve_pool::<P,M,L,T>(serving),
+        }
+    }
+    #[cfg(not(feature = "tls"))]
+    fn serve(&self, serving: Serving<P,T>) -> JoinHandle<Workers> {
+        serve_pool::<P,M,L,T>(serving)
+    }
+}
+impl<P,M,L> Listener<P,M,L,Tcp> 
+where 
+    M: Msg + Clone + Send + Default + 'static,
+    P: Debug + Clone + Send + Default + Sndr<M> + Rcvr<M> + Process<M> + 'static,
+    L: Logger + Debug + Copy + Clone + Default + 'static
+    {    
+    /*-----------------------------------------------------
+      Serve clients with io_thrds non-blocking I/O threads
+      that post each received message to the pool for
+      processing, see reactor.rs, takes effect at start
+    */
+    pub fn set_reactor(&mut self, io_thrds: u8) {
+        self.reactor = Some((io_thrds.max(1), reactor::start::<P,M,L>));
+    }
+}
+/*---------------------------------------------------------
+  Start the accept thread and pool threads, each serving
+  one client at a time
+  - the accept thread returns Workers that join the pool
+*/
+fn serve_pool<P,M,L,T>(serving: Serving<P,T>) -> JoinHandle<Workers>
+where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Clone + Send + Sndr<M> + Rcvr<M> + Process<M> + 'static,
+    L: Logger + 'static,
+    T: Transport
+{
+    let Serving { lsnr, run, conns, proc, file_dir, observer, nt } = serving;
 
-        /*-- this outer thread prevents appl from blocking waiting for connections --*/
-        let handle = std::thread::spawn(move || {
-            let mut tp = ThreadPool::<TcpStream>::new(nt, thread_proc);
-            /*-- loop on incoming iterator which calls accept and so blocks --*/
-            for stream in tcpl.incoming() {
-                if !run_ref.load(Ordering::Relaxed) {
-                    break;
-                }
-                if stream.is_ok() {
-                    tp.post(stream.unwrap());
-                }
-                else {
-                    continue;
+    /*-- this outer thread prevents appl from blocking waiting for connections --*/
+    std::thread::spawn(move || {
+        let pool_conns = Arc::clone(&conns);
+        let pool_observer = observer.clone();
+        let mut tp = ThreadPool::<Job<T>>::new(
+            nt, move |bq, _run| thread_proc::<P,M,L,T>(
+                bq, &pool_conns, &proc, &file_dir, &pool_observer
+            )
+        );
+        accept_loop::<T,_>(&lsnr, &run, |strm, peer| {
+            if let Ok((id, reader, writer)) = conns.add::<T>(strm, peer.clone()) {
+                if let Some(obs) = &observer {
+                    obs.on_accept(peer.clone());
                 }
+                tp.post(Some((id, peer, reader, writer)));
             }
-            tp.stop();
-            L::write("\n--terminating listener thread--");  
         });
-        Ok(handle)
+        /*-- one stop sentinel per pool thread --*/
+        for _ in 0..nt {
+            tp.post(None);
+        }
+        L::write("\n--terminating listener thread--");  
+        Box::new(move || tp.wait()) as Workers
+    })
+}
+/*-- poll accept so shutdown is seen promptly --*/
+pub(crate) fn accept_loop<T, F>(lsnr: &T::Listener, run: &AtomicBool, mut accepted: F)
+where
+    T: Transport,
+    F: FnMut(T::Stream, T::PeerAddr)
+{
+    while run.load(Ordering::Relaxed) {
+        match T::accept(lsnr) {
+            Ok((strm, peer)) => accepted(strm, peer),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_POLL);
+            }
+            Err(_) => {}
+        }
+    }
+}
+/*---------------------------------------------------------
+  Bind to the first of addrs that succeeds
+  - an IPv6 wildcard address, [::], is bound dual-stack,
+    so it accepts IPv4 clients too, whatever the platform
+    default for IPV6_V6ONLY
+*/
+pub fn bind_listener(addrs: &[SocketAddr]) -> std::io::Result<TcpListener> {
+    let mut last_err = std::io::Error::new(
+        std::io::ErrorKind::InvalidInput, "no addresses to bind"
+    );
+    for addr in addrs {
+        match bind_one(addr) {
+            Ok(tcpl) => return Ok(tcpl),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(last_err)
+}
+fn bind_one(addr: &SocketAddr) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(*addr), Type::STREAM, Some(Protocol::TCP))?;
+    if cfg!(unix) {
+        socket.set_reuse_address(true)?;
     }
-    pub fn stop(&mut self) {
-        self.run.store(false, Ordering::Relaxed);
-        let conn = Connector::<P,M,L>::new(self.addr).unwrap();
-        let mut msg = Message::new(TYPE_SIZE + CONTENT_SIZE + 1);
-        msg.set_type(MessageType::QUIT as u8);
-        conn.post_message(msg);
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        socket.set_only_v6(false)?;
     }
+    socket.bind(&(*addr).into())?;
+    socket.listen(128)?;
+    Ok(socket.into())
 }
+/*-- how often the accept loop checks for shutdown --*/
+const ACCEPT_POLL: Duration = Duration::from_millis(10);
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    type P = CommProcessing<MuteLog>;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+    #[test]
+    fn errors_keep_their_cause() {
+        use std::error::Error;
+        /*-- bind then drop, so nothing is listening on port --*/
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let rslt = Connector::<P,Message,MuteLog>::new(("127.0.0.1", port));
+        match rslt {
+            Err(e @ CommError::Connect(_)) => assert!(e.source().is_some()),
+            other => panic!("expected Connect error, got {:?}", other.err()),
+        }
+
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut lsnr = Listener::<P,Message,MuteLog>::new(1);
+        let rslt = lsnr.start(taken.local_addr().unwrap());
+        assert!(matches!(rslt, Err(CommError::Bind(_))));
+    }
 }
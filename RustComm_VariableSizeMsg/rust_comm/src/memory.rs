@@ -0,0 +1,347 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::memory.rs - in-process Transport             //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Memory is a Transport that connects a Connector to a
+   Listener in the same process, without sockets:
+   - a Listener started on a name, e.g., "svc", registers
+     it in a process wide table until it shuts down, and
+     Connectors connect to that name
+   - each connection is a pair of byte pipes, one in each
+     direction
+
+   Pipes behave like socket buffers.  A read returns what
+   has arrived, up to the size of its buffer, and blocks
+   only while nothing has.  A write blocks while the pipe
+   is full and may write less than it was given.  Reads
+   return end of stream once the writing end is closed and
+   the pipe is drained, and writes fail with BrokenPipe
+   once the reading end is closed.  An end closes when
+   the last of its handles is dropped, or on shutdown.
+
+   Tests built on Memory need no ports, so they run in
+   parallel and don't collide with other processes.
+*/
+
+use crate::*;
+
+use std::collections::{HashMap, VecDeque};
+use std::io::ErrorKind;
+use std::sync::{Condvar, OnceLock};
+
+/*-- bytes a pipe holds before writes block --*/
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/*---------------------------------------------------------
+  Pipe - one direction of a connection
+*/
+#[derive(Debug, Default)]
+struct PipeState {
+    bytes: VecDeque<u8>,
+    readers: usize,
+    writers: usize,
+    read_shut: bool,
+    write_shut: bool,
+}
+#[derive(Debug, Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    cv: Condvar,
+}
+impl Pipe {
+    fn read(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.read_shut {
+                return Ok(0);
+            }
+            if !state.bytes.is_empty() {
+                let n = buf.len().min(state.bytes.len());
+                for (dst, src) in buf.iter_mut().zip(state.bytes.drain(..n)) {
+                    *dst = src;
+                }
+                self.cv.notify_all();
+                return Ok(n);
+            }
+            if state.writers == 0 || state.write_shut {
+                return Ok(0);
+            }
+            state = self.cv.wait(state).unwrap();
+        }
+    }
+    fn write(&self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.write_shut || state.read_shut || state.readers == 0 {
+                return Err(ErrorKind::BrokenPipe.into());
+            }
+            let free = PIPE_CAPACITY - state.bytes.len();
+            if free > 0 {
+                let n = buf.len().min(free);
+                state.bytes.extend(&buf[..n]);
+                self.cv.notify_all();
+                return Ok(n);
+            }
+            state = self.cv.wait(state).unwrap();
+        }
+    }
+    fn shut_read(&self) {
+        self.state.lock().unwrap().read_shut = true;
+        self.cv.notify_all();
+    }
+    fn shut_write(&self) {
+        self.state.lock().unwrap().write_shut = true;
+        self.cv.notify_all();
+    }
+}
+
+/*-- reading end of a pipe, a connection's Transport::Reader --*/
+#[derive(Debug)]
+pub struct MemoryReader {
+    pipe: Arc<Pipe>,
+}
+impl MemoryReader {
+    fn new(pipe: &Arc<Pipe>) -> MemoryReader {
+        pipe.state.lock().unwrap().readers += 1;
+        MemoryReader { pipe: Arc::clone(pipe) }
+    }
+}
+impl Read for MemoryReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.pipe.read(buf)
+    }
+}
+impl Drop for MemoryReader {
+    fn drop(&mut self) {
+        self.pipe.state.lock().unwrap().readers -= 1;
+        self.pipe.cv.notify_all();
+    }
+}
+
+/*-- writing end of a pipe, a connection's Transport::Writer --*/
+#[derive(Debug)]
+pub struct MemoryWriter {
+    pipe: Arc<Pipe>,
+}
+impl MemoryWriter {
+    fn new(pipe: &Arc<Pipe>) -> MemoryWriter {
+        pipe.state.lock().unwrap().writers += 1;
+        MemoryWriter { pipe: Arc::clone(pipe) }
+    }
+}
+impl Write for MemoryWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.pipe.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+impl Drop for MemoryWriter {
+    fn drop(&mut self) {
+        self.pipe.state.lock().unwrap().writers -= 1;
+        self.pipe.cv.notify_all();
+    }
+}
+
+/*---------------------------------------------------------
+  MemoryStream - one end of an in-process connection
+  - peer is the Listener's name for a Connector, and the
+    name with a connection number, e.g., "svc#3", for a
+    Listener
+*/
+#[derive(Debug)]
+pub struct MemoryStream {
+    reader: MemoryReader,
+    writer: MemoryWriter,
+    local: String,
+    peer: String,
+}
+impl MemoryStream {
+    /*-- connected pair of streams, for tests that need no Listener --*/
+    pub fn pair() -> (MemoryStream, MemoryStream) {
+        MemoryStream::connected("pair".to_string(), "pair#0".to_string())
+    }
+    fn connected(name: String, client: String) -> (MemoryStream, MemoryStream) {
+        let (up, down) = (Arc::new(Pipe::default()), Arc::new(Pipe::default()));
+        let client_end = MemoryStream {
+            reader: MemoryReader::new(&down),
+            writer: MemoryWriter::new(&up),
+            local: client.clone(),
+            peer: name.clone(),
+        };
+        let listener_end = MemoryStream {
+            reader: MemoryReader::new(&up),
+            writer: MemoryWriter::new(&down),
+            local: name,
+            peer: client,
+        };
+        (client_end, listener_end)
+    }
+    pub fn peer_addr(&self) -> &str {
+        &self.peer
+    }
+    /*-- end of stream for both ends' readers, both ends' writes fail --*/
+    pub fn shutdown(&self) {
+        self.reader.pipe.shut_read();
+        self.writer.pipe.shut_write();
+    }
+}
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/*---------------------------------------------------------
+  MemoryListener - a name Connectors can connect to
+  - the name is free again when the listener is dropped
+*/
+type Backlog = Mutex<VecDeque<MemoryStream>>;
+
+fn listeners() -> &'static Mutex<HashMap<String, Arc<Backlog>>> {
+    static LISTENERS: OnceLock<Mutex<HashMap<String, Arc<Backlog>>>> = OnceLock::new();
+    LISTENERS.get_or_init(Default::default)
+}
+
+#[derive(Debug)]
+pub struct MemoryListener {
+    name: String,
+    backlog: Arc<Backlog>,
+}
+impl Drop for MemoryListener {
+    fn drop(&mut self) {
+        let mut map = listeners().lock().unwrap();
+        if map.get(&self.name).is_some_and(|b| Arc::ptr_eq(b, &self.backlog)) {
+            map.remove(&self.name);
+        }
+    }
+}
+
+/*---------------------------------------------------------
+  Memory - the in-process Transport
+*/
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Memory;
+
+impl Transport for Memory {
+    type Addr = String;
+    type PeerAddr = String;
+    type Stream = MemoryStream;
+    type Reader = MemoryReader;
+    type Writer = MemoryWriter;
+    type Listener = MemoryListener;
+
+    fn connect(addr: &String) -> std::io::Result<MemoryStream> {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        let backlog = listeners().lock().unwrap().get(addr).cloned()
+            .ok_or(ErrorKind::ConnectionRefused)?;
+        let client = format!("{}#{}", addr, NEXT.fetch_add(1, Ordering::Relaxed));
+        let (client_end, listener_end) = MemoryStream::connected(addr.clone(), client);
+        backlog.lock().unwrap().push_back(listener_end);
+        Ok(client_end)
+    }
+    fn bind(addr: &String) -> std::io::Result<MemoryListener> {
+        let mut map = listeners().lock().unwrap();
+        if map.contains_key(addr) {
+            return Err(ErrorKind::AddrInUse.into());
+        }
+        let backlog = Arc::new(Backlog::default());
+        map.insert(addr.clone(), Arc::clone(&backlog));
+        Ok(MemoryListener { name: addr.clone(), backlog })
+    }
+    fn accept(lsnr: &MemoryListener) -> std::io::Result<(MemoryStream, String)> {
+        let strm = lsnr.backlog.lock().unwrap().pop_front()
+            .ok_or(ErrorKind::WouldBlock)?;
+        let peer = strm.peer.clone();
+        Ok((strm, peer))
+    }
+    fn listen_addr(lsnr: &MemoryListener) -> std::io::Result<String> {
+        Ok(lsnr.name.clone())
+    }
+    fn split(strm: &MemoryStream) -> std::io::Result<(MemoryReader, MemoryWriter)> {
+        Ok((MemoryReader::new(&strm.reader.pipe), MemoryWriter::new(&strm.writer.pipe)))
+    }
+    fn shutdown(strm: &MemoryStream) -> std::io::Result<()> {
+        strm.shutdown();
+        Ok(())
+    }
+    fn peer_addr(strm: &MemoryStream) -> std::io::Result<String> {
+        Ok(strm.peer.clone())
+    }
+    fn local_addr(strm: &MemoryStream) -> std::io::Result<String> {
+        Ok(strm.local.clone())
+    }
+}
+impl<A: AsRef<str>> ToTransportAddrs<Memory> for A {
+    fn to_transport_addrs(&self) -> std::io::Result<Vec<String>> {
+        Ok(vec![self.as_ref().to_string()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipes_behave_like_sockets() {
+        let (mut a, mut b) = MemoryStream::pair();
+        a.write_all(b"hello world").unwrap();
+        let mut buf = [0u8; 5];
+        assert_eq!(b.read(&mut buf).unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+
+        /*-- a full pipe takes part of a write --*/
+        let big = vec![7u8; PIPE_CAPACITY];
+        assert_eq!(a.write(&big).unwrap(), PIPE_CAPACITY - 6);
+
+        /*-- drained, then end of stream, once the writer is gone --*/
+        let (reader, writer) = Memory::split(&a).unwrap();
+        drop(a);
+        drop(writer);
+        let mut rest = Vec::new();
+        b.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest.len(), PIPE_CAPACITY);
+
+        drop(reader);
+        assert_eq!(b.write(b"x").unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+    #[test]
+    fn shutdown_wakes_blocked_reader() {
+        let (a, b) = MemoryStream::pair();
+        let (mut reader, _writer) = Memory::split(&b).unwrap();
+        let blocked = std::thread::spawn(move || reader.read(&mut [0u8; 8]).unwrap());
+        std::thread::sleep(Duration::from_millis(20));
+        Memory::shutdown(&b).unwrap();
+        assert_eq!(blocked.join().unwrap(), 0);
+        drop(a);
+    }
+    #[test]
+    fn names_are_registered_while_listening() {
+        let lsnr = Memory::bind(&"memory_unit".to_string()).unwrap();
+        assert!(Memory::bind(&"memory_unit".to_string()).is_err());
+        assert_eq!(Memory::accept(&lsnr).unwrap_err().kind(), ErrorKind::WouldBlock);
+        let client = Memory::connect(&"memory_unit".to_string()).unwrap();
+        let (server, peer) = Memory::accept(&lsnr).unwrap();
+        assert_eq!(peer, client.local);
+        assert_eq!(server.peer_addr(), client.local);
+        drop(lsnr);
+        let rslt = Memory::connect(&"memory_unit".to_string());
+        assert_eq!(rslt.unwrap_err().kind(), ErrorKind::ConnectionRefused);
+    }
+}
@@ -0,0 +1,53 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::observer.rs - connection lifecycle events    //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   A ConnectionObserver registered with
+   Listener::set_observer is told about each client
+   connection's lifecycle:
+   - on_accept when the connection is accepted
+   - on_error when receiving fails for any reason other
+     than the client closing, e.g., a framing error
+   - on_close exactly once, when its handler is done,
+     with the reason it closed
+
+   Methods are called on the accept thread and on pool
+   threads, so implementations must be Send + Sync and
+   should return quickly.  All methods default to doing
+   nothing, so observers implement only what they need.
+*/
+
+use rust_traits::*;
+
+use std::fmt;
+use std::net::SocketAddr;
+
+/*-- why a client connection closed --*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /*-- client sent END --*/
+    End,
+    /*-- client sent QUIT --*/
+    Quit,
+    /*-- client closed without sending END --*/
+    Dropped,
+    /*-- closed by ListenerHandle::shutdown at its deadline --*/
+    Shutdown,
+    /*-- client was idle, or slow to send a message, see heartbeat.rs --*/
+    TimedOut,
+    /*-- receive failed, reported first with on_error --*/
+    Error,
+}
+
+/*-- A is the Listener's Transport::PeerAddr, SocketAddr for Tcp --*/
+pub trait ConnectionObserver<A = SocketAddr> : Send + Sync {
+    fn on_accept(&self, _peer: A) {}
+    fn on_close(&self, _peer: A, _reason: CloseReason) {}
+    fn on_error(&self, _peer: A, _err: &CommError) {}
+}
+impl<A> fmt::Debug for dyn ConnectionObserver<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ConnectionObserver")
+    }
+}
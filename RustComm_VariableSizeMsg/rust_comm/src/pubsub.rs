@@ -0,0 +1,273 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::pubsub.rs - Listener topics                  //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   A Listener is also a small message bus.  Clients send
+
+     SUBSCRIBE    topic | pattern
+     UNSUBSCRIBE  topic | pattern
+     PUBLISH      topic | payload
+
+   where topic is the topic field, a u64 length, big endian,
+   followed by that many bytes of utf-8.  The Listener
+   answers SUBSCRIBE and UNSUBSCRIBE by echoing them, so
+   Connector::request can wait until a subscription is in
+   place, and forwards each PUBLISH, unchanged except for a
+   zero correlation id, to every connection subscribed to a
+   pattern that matches its topic, including the publisher.
+   PUBLISH has no reply.  A malformed message, pattern, or
+   topic is answered with TOPIC_ERROR (topic | text).
+
+   Topics are levels separated by '/', e.g., "sensors/3/temp".
+   In patterns '+' matches any one level and '#', allowed
+   only as the last level, matches the rest of the topic,
+   including nothing, so "sensors/+/temp" and "sensors/#"
+   both match "sensors/3/temp".  Topics can't hold '+' or
+   '#'.
+
+   Published messages are pushed, see push.rs, so each
+   subscriber has its own queue of messages waiting to be
+   sent and a thread that sends them, and publishing never
+   waits on a subscriber.  A subscriber that falls
+   SUBSCRIBER_QUEUE_LIMIT messages behind misses messages
+   until it catches up.  Subscriptions end when the
+   connection closes.
+
+   Defined Types:
+   - Topics - a Listener's subscriptions, held by its
+     Connections registry, see shutdown.rs
+*/
+
+use crate::*;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/*-- messages queued for one subscriber before more are dropped --*/
+pub const SUBSCRIBER_QUEUE_LIMIT: usize = 1024;
+
+const LEN_SIZE: usize = 8;
+
+/*-- is mt one of the types a Listener handles as a message bus? --*/
+pub fn is_topic_msg(mt: u8) -> bool {
+    mt == MessageType::SUBSCRIBE as u8
+        || mt == MessageType::UNSUBSCRIBE as u8
+        || mt == MessageType::PUBLISH as u8
+}
+/*---------------------------------------------------------
+  Message construction and parsing helpers
+*/
+fn topic_msg<M: Msg>(mt: MessageType, topic: &str, rest: &[u8]) -> M {
+    let mut body = (topic.len() as u64).to_be_bytes().to_vec();
+    body.extend_from_slice(topic.as_bytes());
+    body.extend_from_slice(rest);
+    let mut msg = M::new(HEADER_SIZE + body.len());
+    msg.set_type(mt as u8);
+    msg.set_content_bytes(&body);
+    msg
+}
+pub fn subscribe_msg<M: Msg>(pattern: &str) -> M {
+    topic_msg(MessageType::SUBSCRIBE, pattern, &[])
+}
+pub fn unsubscribe_msg<M: Msg>(pattern: &str) -> M {
+    topic_msg(MessageType::UNSUBSCRIBE, pattern, &[])
+}
+pub fn publish_msg<M: Msg>(topic: &str, payload: &[u8]) -> M {
+    topic_msg(MessageType::PUBLISH, topic, payload)
+}
+pub fn topic_error_msg<M: Msg>(topic: &str, err: &str) -> M {
+    topic_msg(MessageType::TOPIC_ERROR, topic, err.as_bytes())
+}
+/*-- end of topic field, None if malformed --*/
+fn topic_end(body: &[u8]) -> Option<usize> {
+    let mut len = [0u8; LEN_SIZE];
+    len.copy_from_slice(body.get(..LEN_SIZE)?);
+    let end = LEN_SIZE.checked_add(usize::try_from(u64::from_be_bytes(len)).ok()?)?;
+    if end > body.len() {
+        return None;
+    }
+    Some(end)
+}
+/*-- topic, or pattern, of a topic message --*/
+pub fn msg_topic<M: Msg>(msg: &M) -> Option<&str> {
+    let body = msg.get_content_bytes();
+    let end = topic_end(body)?;
+    std::str::from_utf8(&body[LEN_SIZE..end]).ok()
+}
+/*-- what follows the topic, PUBLISH payload or TOPIC_ERROR text --*/
+pub fn msg_payload<M: Msg>(msg: &M) -> &[u8] {
+    let body = msg.get_content_bytes();
+    match topic_end(body) {
+        Some(end) => &body[end..],
+        None => &[],
+    }
+}
+/*---------------------------------------------------------
+  Topics and patterns
+*/
+pub fn topic_matches(pattern: &str, topic: &str) -> bool {
+    let mut levels = topic.split('/');
+    for part in pattern.split('/') {
+        if part == "#" {
+            return true;
+        }
+        match levels.next() {
+            Some(level) if part == "+" || part == level => {}
+            _ => return false,
+        }
+    }
+    levels.next().is_none()
+}
+fn check_pattern(pattern: &str) -> std::result::Result<(), String> {
+    if pattern.is_empty() {
+        return Err("empty pattern".to_string());
+    }
+    let parts: Vec<&str> = pattern.split('/').collect();
+    for (i, part) in parts.iter().enumerate() {
+        let wild = *part == "+" || (*part == "#" && i == parts.len() - 1);
+        if !wild && part.contains(['+', '#']) {
+            return Err(format!("misplaced wildcard in pattern {:?}", pattern));
+        }
+    }
+    Ok(())
+}
+fn check_topic(topic: &str) -> std::result::Result<(), String> {
+    if topic.is_empty() {
+        return Err("empty topic".to_string());
+    }
+    if topic.contains(['+', '#']) {
+        return Err(format!("wildcard in topic {:?}", topic));
+    }
+    Ok(())
+}
+
+/*---------------------------------------------------------
+  Topics - a Listener's subscriptions, patterns by
+  connection id
+  - published messages are pushed, see push.rs, so each
+    subscriber has its own queue and thread
+*/
+#[derive(Debug, Default)]
+pub(crate) struct Topics {
+    subs: Mutex<HashMap<u64, Vec<String>>>,
+}
+impl Topics {
+    /*-----------------------------------------------------
+      Handle a topic message from connection id, returning
+      the reply, if any
+      - conns is the registry holding these Topics
+    */
+    pub(crate) fn handle<P,M,L>(&self, conns: &Connections, id: u64, msg: &M) -> Option<M>
+    where
+        M: Msg + Clone + Send + Default,
+        P: Sndr<M>,
+        L: Logger
+    {
+        let topic = match msg_topic(msg) {
+            Some(topic) => topic,
+            None => return Some(topic_error_msg("", "malformed topic message")),
+        };
+        let mt = msg.get_type();
+        let rslt =
+            if mt == MessageType::SUBSCRIBE as u8 {
+                check_pattern(topic).map(|_| {
+                    self.subscribe(id, topic);
+                    Some(subscribe_msg(topic))
+                })
+            }
+            else if mt == MessageType::UNSUBSCRIBE as u8 {
+                self.unsubscribe(id, topic);
+                Ok(Some(unsubscribe_msg(topic)))
+            }
+            else if mt == MessageType::PUBLISH as u8 {
+                check_topic(topic).map(|_| {
+                    self.publish::<P,M,L>(conns, topic, msg);
+                    None
+                })
+            }
+            else {
+                Err(format!("unexpected topic message {}", msg.type_display()))
+            };
+        rslt.unwrap_or_else(|err| Some(topic_error_msg(topic, &err)))
+    }
+    fn subscribe(&self, id: u64, pattern: &str) {
+        let mut subs = self.subs.lock().unwrap();
+        let patterns = subs.entry(id).or_default();
+        if !patterns.iter().any(|p| p == pattern) {
+            patterns.push(pattern.to_string());
+        }
+    }
+    fn unsubscribe(&self, id: u64, pattern: &str) {
+        let mut subs = self.subs.lock().unwrap();
+        if let Some(patterns) = subs.get_mut(&id) {
+            patterns.retain(|p| p != pattern);
+            if patterns.is_empty() {
+                subs.remove(&id);
+            }
+        }
+    }
+    /*-- push msg to every subscriber with a pattern matching topic --*/
+    fn publish<P,M,L>(&self, conns: &Connections, topic: &str, msg: &M)
+    where
+        M: Msg + Clone + Send + Default,
+        P: Sndr<M>,
+        L: Logger
+    {
+        let mut fwd = msg.clone();
+        fwd.set_correlation_id(0);
+        let bytes = P::encode(&fwd);
+        let ids: Vec<u64> = self.subs.lock().unwrap().iter()
+            .filter(|(_, patterns)| patterns.iter().any(|p| topic_matches(p, topic)))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            if conns.pending(id) >= SUBSCRIBER_QUEUE_LIMIT {
+                L::write(&format!("\n  subscriber {} is behind, dropping {}", id, topic));
+                continue;
+            }
+            conns.push(id, bytes.clone());
+        }
+    }
+    /*-- connection id closed --*/
+    pub(crate) fn remove(&self, id: u64) {
+        self.subs.lock().unwrap().remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_match_levels() {
+        assert!(topic_matches("a/b", "a/b"));
+        assert!(!topic_matches("a/b", "a/b/c"));
+        assert!(!topic_matches("a/b/c", "a/b"));
+        assert!(topic_matches("a/+/c", "a/b/c"));
+        assert!(!topic_matches("a/+", "a/b/c"));
+        assert!(topic_matches("a/#", "a/b/c"));
+        assert!(topic_matches("a/#", "a"));
+        assert!(topic_matches("#", "x/y"));
+        assert!(topic_matches("+/+", "x/y"));
+    }
+    #[test]
+    fn patterns_and_topics_are_checked() {
+        assert!(check_pattern("a/+/#").is_ok());
+        assert!(check_pattern("a/#/b").is_err());
+        assert!(check_pattern("a+/b").is_err());
+        assert!(check_pattern("").is_err());
+        assert!(check_topic("a/b").is_ok());
+        assert!(check_topic("a/+").is_err());
+    }
+    #[test]
+    fn topic_field_round_trips() {
+        let msg: Message = publish_msg("news/today", b"payload");
+        assert_eq!(msg.get_type(), MessageType::PUBLISH as u8);
+        assert_eq!(msg_topic(&msg), Some("news/today"));
+        assert_eq!(msg_payload(&msg), b"payload");
+        let bad = Message::create_msg_bytes_fit(&[0, 0, 0, 0, 0, 0, 0, 9, b'x']);
+        assert_eq!(msg_topic(&bad), None);
+        assert!(msg_payload(&bad).is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::push.rs - Listener initiated messages        //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   A Listener's replies go back on the connection a message
+   arrived on.  Clients<P,M,T> lets application code send
+   to any connected client at any time, from a Process or
+   from any other thread:
+   - each accepted connection gets a ClientId, also handed
+     to Process in ConnContext::client
+   - send_to(id, msg) sends msg to one client, broadcast
+     sends it to every client, and list shows who is
+     connected, with their peer addresses
+   - pushed messages should have a zero correlation id, so
+     Connectors pass them to get_message
+
+   The first message pushed to a connection starts its
+   Pusher, a queue and a thread that writes what is queued,
+   so pushing never waits on a client that is slow to read.
+   In reactor mode pushed messages go straight to the
+   Session's output instead, which never waits, so pushing
+   costs no thread.  Published messages, see pubsub.rs,
+   heartbeat PINGs, and SHUTDOWN, see shutdown.rs, are sent
+   the same way.
+
+   A Listener makes its Clients when constructed.  A
+   processor that pushes needs one before the Listener
+   exists, so make one with Clients::new, give clones to
+   the processor, and hand it to Listener::set_clients.
+*/
+
+use crate::*;
+
+use std::marker::PhantomData;
+
+/*---------------------------------------------------------
+  Pusher - one connection's queue of pushed messages
+  - its thread sends queued bytes on the connection's
+    outbox until None, queued when the Pusher drops
+*/
+type Pending = BlockingQueue<Option<Vec<u8>>>;
+
+#[derive(Debug)]
+pub(crate) struct Pusher {
+    pending: Arc<Pending>,
+}
+impl Pusher {
+    pub(crate) fn start(outbox: Arc<dyn Outbox>) -> Pusher {
+        let pending = Arc::new(Pending::new());
+        let queue = Arc::clone(&pending);
+        thread::spawn(move || {
+            while let Some(bytes) = queue.de_q() {
+                outbox.queue(bytes);
+            }
+        });
+        Pusher { pending }
+    }
+    pub(crate) fn push(&self, bytes: Vec<u8>) {
+        self.pending.en_q(Some(bytes));
+    }
+    /*-- messages waiting to be sent --*/
+    pub(crate) fn len(&self) -> usize {
+        self.pending.len()
+    }
+}
+impl Drop for Pusher {
+    fn drop(&mut self) {
+        self.pending.en_q(None);
+    }
+}
+
+/*---------------------------------------------------------
+  Clients<P,M,T> - handle to a Listener's connected
+  clients, cheap to clone
+  - P frames pushed messages, any P with the Listener's
+    framing will do
+*/
+/*-- Send and Sync whatever P, M, and T are --*/
+type Marker<P, M, T> = PhantomData<fn() -> (P, M, T)>;
+
+pub struct Clients<P, M, T = Tcp> {
+    conns: Arc<Connections>,
+    _t: Marker<P, M, T>,
+}
+impl<P, M, T> Clone for Clients<P, M, T> {
+    fn clone(&self) -> Self {
+        Clients { conns: Arc::clone(&self.conns), _t: PhantomData }
+    }
+}
+impl<P, M, T> Debug for Clients<P, M, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_struct("Clients").field("connected", &self.conns.len()).finish()
+    }
+}
+impl<P, M, T> Default for Clients<P, M, T> {
+    fn default() -> Self {
+        Clients { conns: Arc::new(Connections::new()), _t: PhantomData }
+    }
+}
+impl<P, M, T> Clients<P, M, T> {
+    pub fn new() -> Clients<P, M, T> {
+        Clients::default()
+    }
+    pub(crate) fn connections(&self) -> &Arc<Connections> {
+        &self.conns
+    }
+    /*-- same clients, framed by Q --*/
+    pub fn with_framing<Q>(&self) -> Clients<Q, M, T> {
+        Clients { conns: Arc::clone(&self.conns), _t: PhantomData }
+    }
+}
+impl<P, M, T> Clients<P, M, T>
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M>,
+    T: Transport
+{
+    /*-- queue msg for client, PeerClosed if it isn't connected --*/
+    pub fn send_to(&self, client: ClientId, msg: M) -> CommResult<()> {
+        match self.conns.push(client.0, P::encode(&msg)) {
+            true => Ok(()),
+            false => Err(CommError::PeerClosed),
+        }
+    }
+    /*-- queue msg for every connected client, returning their count --*/
+    pub fn broadcast(&self, msg: M) -> usize {
+        let bytes = P::encode(&msg);
+        self.conns.ids().into_iter()
+            .filter(|id| self.conns.push(*id, bytes.clone()))
+            .count()
+    }
+    /*-- connected clients and their peer addresses, in order of connection --*/
+    pub fn list(&self) -> Vec<(ClientId, T::PeerAddr)> {
+        self.conns.peers::<T::PeerAddr>().into_iter()
+            .map(|(id, peer)| (ClientId(id), peer))
+            .collect()
+    }
+}
@@ -0,0 +1,582 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::reactor.rs - multiplexed Listener clients    //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   By default each Listener pool thread runs handle_client
+   for one client until it disconnects, so Listener::new(nt)
+   serves at most nt clients at a time.  After
+   Listener::set_reactor(io_thrds), start serves clients
+   this way instead:
+   - the accept thread hands each connection, now
+     non-blocking, to one of io_thrds I/O threads
+   - each I/O thread waits on its connections with a mio
+     Poll, reads whatever has arrived, and cuts it into
+     messages with P::decode
+   - each whole message is queued on its connection's
+     Session, and the Session is posted to the pool
+   - a pool thread processes a Session's queued messages
+     in order, as handle_client would, and queues replies,
+     encoded with P::encode, for the I/O thread to write
+
+   A Session is on the pool at most once at a time, so each
+   connection's messages are processed in the order they
+   arrived and its replies are sent in that order, while
+   other connections are processed concurrently.  Pool
+   threads block only while processing, not while clients
+   are idle.
+
+   An I/O thread stops reading a connection while its
+   Session is processing, or has REACTOR_OUTPUT_LIMIT bytes
+   of output the client hasn't read, and resumes when the
+   Session catches up.  A client that sends faster than it
+   is served waits on TCP flow control instead of filling
+   the Listener's memory.  Messages pushed to a client that
+   far behind are dropped.
+
+   A connection closes when its Session has processed END
+   or QUIT and the replies are written, or when the client
+   closes and its queued messages are processed.
+   ListenerHandle::shutdown, observers, file transfer, and
+   heartbeats work as in the default mode, though time
+   spent processing a client's messages counts as idle
+   unless the client keeps sending.
+*/
+
+use crate::*;
+
+use mio::net::TcpStream as MioStream;
+use mio::{Events, Interest, Poll, Token, Waker};
+use std::collections::{HashMap, VecDeque};
+use std::io::ErrorKind;
+use std::marker::PhantomData;
+
+/*-- token of each I/O thread's Waker, connections use their ids --*/
+const WAKER: Token = Token(usize::MAX);
+
+/*-- unwritten output bytes at which a connection stops being read --*/
+pub const REACTOR_OUTPUT_LIMIT: usize = 4 * 1024 * 1024;
+
+/*-- work item for reactor pool threads, None stops a thread --*/
+type ReactorJob<P,M,L> = Option<Arc<Session<P,M,L>>>;
+type SharedPool<P,M,L> = Arc<Mutex<ThreadPool<ReactorJob<P,M,L>>>>;
+/*-- accepted connection waiting for its I/O thread --*/
+type Incoming<P,M,L> = (Arc<Session<P,M,L>>, MioStream);
+
+/*---------------------------------------------------------
+  IoShared - state an I/O thread shares with the accept
+  thread and pool threads
+  - incoming holds accepted connections not yet polled
+  - ready holds ids of Sessions with output to write or
+    that may be ready to close
+*/
+struct IoShared<P,M,L> {
+    waker: Waker,
+    incoming: Mutex<Vec<Incoming<P,M,L>>>,
+    ready: Mutex<Vec<u64>>,
+    run: AtomicBool,
+}
+impl<P,M,L> IoShared<P,M,L> {
+    fn wake_for(&self, id: u64) {
+        self.ready.lock().unwrap().push(id);
+        let _ = self.waker.wake();
+    }
+}
+
+/*-- what a Session's pool thread and I/O thread share --*/
+struct SessionState<M> {
+    inbox: VecDeque<M>,
+    busy: bool,
+    out: Vec<u8>,
+    /*-- set by END or QUIT, no more messages are processed --*/
+    done: Option<CloseReason>,
+    /*-- set when the client's input has ended --*/
+    ended: Option<CommResult<CloseReason>>,
+    /*-- PROTOCOL_ERROR, written after replies to the messages before it --*/
+    refusal: Vec<u8>,
+}
+/*-- used only by the pool thread running the Session --*/
+struct Worker<P> {
+    proc: P,
+    ctx: ConnContext,
+    files: FileReceiver,
+}
+
+/*---------------------------------------------------------
+  Session<P,M,L> - one client connection
+*/
+struct Session<P,M,L> {
+    id: u64,
+    peer: SocketAddr,
+    io: Arc<IoShared<P,M,L>>,
+    conns: Arc<Connections>,
+    state: Mutex<SessionState<M>>,
+    worker: Mutex<Worker<P>>,
+    _l: PhantomData<fn() -> L>,
+}
+impl<P,M,L> Debug for Session<P,M,L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_struct("Session")
+            .field("id", &self.id)
+            .field("peer", &self.peer)
+            .finish()
+    }
+}
+impl<P,M,L> Outbox for Session<P,M,L>
+where
+    M: Send,
+    P: Send
+{
+    /*-- drops bytes for a client too far behind, as pubsub does --*/
+    fn queue(&self, bytes: Vec<u8>) {
+        let mut state = self.state.lock().unwrap();
+        if state.out.len() >= REACTOR_OUTPUT_LIMIT {
+            return;
+        }
+        state.out.extend_from_slice(&bytes);
+        drop(state);
+        self.io.wake_for(self.id);
+    }
+    fn never_waits(&self) -> bool {
+        true
+    }
+}
+impl<P,M,L> Session<P,M,L>
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M> + Rcvr<M> + Process<M>,
+    L: Logger
+{
+    /*-- queue msg, true if the Session must be posted to the pool --*/
+    fn push(&self, msg: M) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.done.is_some() {
+            return false;
+        }
+        state.inbox.push_back(msg);
+        !std::mem::replace(&mut state.busy, true)
+    }
+    /*-----------------------------------------------------
+      Pool thread: process queued messages in order, as
+      handle_client does, until the queue is empty
+    */
+    fn run(&self) {
+        loop {
+            let msg = {
+                let mut state = self.state.lock().unwrap();
+                match state.inbox.pop_front() {
+                    Some(msg) => msg,
+                    None => {
+                        state.busy = false;
+                        drop(state);
+                        /*-- I/O thread may be waiting to close --*/
+                        self.io.wake_for(self.id);
+                        return;
+                    }
+                }
+            };
+            let mut worker = self.worker.lock().unwrap();
+            let Worker { proc, ctx, files } = &mut *worker;
+            let client = (&*self.conns, self.id);
+            let (replies, done) = match handle_message::<P,M,L>(msg, proc, ctx, files, client) {
+                Handled::Replies(replies) => (replies, None),
+                Handled::Close(reason) => (Vec::new(), Some(reason)),
+            };
+            drop(worker);
+            let mut state = self.state.lock().unwrap();
+            for reply in &replies {
+                state.out.extend_from_slice(&P::encode(reply));
+            }
+            if done.is_some() {
+                state.done = done;
+                state.inbox.clear();
+                state.busy = false;
+            }
+            drop(state);
+            if done.is_some() {
+                self.io.wake_for(self.id);
+                return;
+            }
+            if !replies.is_empty() {
+                self.io.wake_for(self.id);
+            }
+        }
+    }
+    /*-- should the I/O thread stop reading, until this catches up? --*/
+    fn backlogged(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.busy || state.out.len() >= REACTOR_OUTPUT_LIMIT
+    }
+    /*-- how the connection closes, once it should --*/
+    fn closing(&self) -> Option<CommResult<CloseReason>> {
+        let mut state = self.state.lock().unwrap();
+        if !state.out.is_empty() {
+            return None;
+        }
+        if let Some(reason) = state.done {
+            return Some(Ok(reason));
+        }
+        if state.busy {
+            return None;
+        }
+        if !state.refusal.is_empty() {
+            state.out = std::mem::take(&mut state.refusal);
+            drop(state);
+            self.io.wake_for(self.id);
+            return None;
+        }
+        state.ended.take()
+    }
+}
+
+/*-- an I/O thread's view of one connection --*/
+struct Conn<P,M,L> {
+    strm: MioStream,
+    inbuf: Vec<u8>,
+    activity: Arc<Activity>,
+    session: Arc<Session<P,M,L>>,
+    writing: bool,
+    /*-- not read until the Session is no longer backlogged --*/
+    paused: bool,
+    /*-- what the socket is registered for, None if deregistered --*/
+    watching: Option<Interest>,
+    /*-- a frame couldn't be read, later input is discarded --*/
+    failed: bool,
+}
+
+/*---------------------------------------------------------
+  Start I/O threads, pool threads, and the accept thread
+  - returns the accept thread, which returns Workers that
+    stop and join the I/O threads, then the pool threads
+*/
+pub(crate) fn start<P,M,L>(
+    serving: Serving<P,Tcp>, io_thrds: u8
+) -> std::io::Result<JoinHandle<Workers>>
+where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Clone + Send + Sndr<M> + Rcvr<M> + Process<M> + 'static,
+    L: Logger + 'static
+{
+    let Serving { lsnr, run, conns, proc, file_dir, observer, nt } = serving;
+    let pool: SharedPool<P,M,L> = Arc::new(Mutex::new(ThreadPool::new(
+        nt, |bq: &BlockingQueue<ReactorJob<P,M,L>>, _run: &Arc<AtomicBool>| {
+            while let Some(session) = bq.de_q() {
+                session.run();
+            }
+        }
+    )));
+    let mut ios = Vec::new();
+    let mut io_handles = Vec::new();
+    for _ in 0..io_thrds {
+        let poll = Poll::new()?;
+        let io = Arc::new(IoShared {
+            waker: Waker::new(poll.registry(), WAKER)?,
+            incoming: Mutex::new(Vec::new()),
+            ready: Mutex::new(Vec::new()),
+            run: AtomicBool::new(true),
+        });
+        let (io_ref, pool, conns, observer) =
+            (Arc::clone(&io), Arc::clone(&pool), Arc::clone(&conns), observer.clone());
+        io_handles.push(thread::spawn(move || {
+            io_loop::<P,M,L>(poll, &io_ref, &pool, &conns, &observer);
+        }));
+        ios.push(io);
+    }
+    let handle = thread::spawn(move || {
+        let mut next = 0;
+        accept_loop::<Tcp,_>(&lsnr, &run, |strm, peer| {
+            let registered = match strm.try_clone() {
+                Ok(registered) => registered,
+                Err(_) => return,
+            };
+            if strm.set_nonblocking(true).is_err() {
+                return;
+            }
+            let io = &ios[next % ios.len()];
+            next += 1;
+            let id = conns.reserve_id();
+            let session = Arc::new(Session {
+                id,
+                peer,
+                io: Arc::clone(io),
+                conns: Arc::clone(&conns),
+                state: Mutex::new(SessionState {
+                    inbox: VecDeque::new(),
+                    busy: false,
+                    out: Vec::new(),
+                    done: None,
+                    ended: None,
+                    refusal: Vec::new(),
+                }),
+                worker: Mutex::new(Worker {
+                    proc: proc.clone(),
+                    ctx: ConnContext {
+                        client: Some(ClientId(id)), ..ConnContext::new(Some(peer))
+                    },
+                    files: FileReceiver::new(file_dir.clone()),
+                }),
+                _l: PhantomData,
+            });
+            conns.insert::<Tcp>(id, registered, peer, session.clone());
+            if let Some(obs) = &observer {
+                obs.on_accept(peer);
+            }
+            io.incoming.lock().unwrap().push((session, MioStream::from_std(strm)));
+            let _ = io.waker.wake();
+        });
+        L::write("\n--terminating listener thread--");
+        Box::new(move || {
+            for io in &ios {
+                io.run.store(false, Ordering::Relaxed);
+                let _ = io.waker.wake();
+            }
+            for handle in io_handles {
+                let _ = handle.join();
+            }
+            let mut pool = pool.lock().unwrap();
+            for _ in 0..nt {
+                pool.post(None);
+            }
+            pool.wait();
+        }) as Workers
+    });
+    Ok(handle)
+}
+
+/*---------------------------------------------------------
+  I/O thread
+  - reads and decodes arriving messages, posting their
+    Sessions to the pool
+  - writes queued replies as sockets accept them
+  - closes connections, until stopped with none open
+*/
+fn io_loop<P,M,L>(
+    mut poll: Poll, io: &IoShared<P,M,L>, pool: &SharedPool<P,M,L>,
+    conns: &Connections, observer: &Option<Arc<dyn ConnectionObserver>>
+)
+where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Send + Sndr<M> + Rcvr<M> + Process<M> + 'static,
+    L: Logger + 'static
+{
+    let mut live = HashMap::<u64, Conn<P,M,L>>::new();
+    let mut events = Events::with_capacity(256);
+    let mut scratch = vec![0u8; 64 * 1024];
+    loop {
+        if let Err(e) = poll.poll(&mut events, None) {
+            if e.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            L::write(&format!("\n  reactor poll failed: {}", e));
+            break;
+        }
+        let mut touched = Vec::<u64>::new();
+        for event in events.iter() {
+            if event.token() == WAKER {
+                continue;
+            }
+            let id = event.token().0 as u64;
+            if let Some(conn) = live.get_mut(&id) {
+                if event.is_readable() {
+                    read_conn(conn, &mut scratch, pool);
+                }
+                touched.push(id);
+            }
+        }
+        let incoming: Vec<_> = io.incoming.lock().unwrap().drain(..).collect();
+        for (session, mut strm) in incoming {
+            let id = session.id;
+            let token = Token(id as usize);
+            if poll.registry().register(&mut strm, token, Interest::READABLE).is_err() {
+                conns.remove(id);
+                continue;
+            }
+            let activity = conns.activity(id);
+            live.insert(id, Conn {
+                strm, inbuf: Vec::new(), activity, session, writing: false,
+                paused: false, watching: Some(Interest::READABLE), failed: false
+            });
+            touched.push(id);
+        }
+        touched.extend(io.ready.lock().unwrap().drain(..));
+        for id in touched {
+            let conn = match live.get_mut(&id) {
+                Some(conn) => conn,
+                None => continue,
+            };
+            write_conn(conn);
+            if conn.paused && !conn.session.backlogged() {
+                conn.paused = false;
+                read_conn(conn, &mut scratch, pool);
+            }
+            watch(conn, &poll);
+            if let Some(rslt) = conn.session.closing() {
+                let mut conn = live.remove(&id).unwrap();
+                let _ = poll.registry().deregister(&mut conn.strm);
+                let peer = conn.session.peer;
+                drop(conn);
+                let closed_by = conns.remove(id);
+                report_close::<L,_>(peer, rslt, closed_by, observer);
+            }
+        }
+        if !io.run.load(Ordering::Relaxed) && live.is_empty()
+            && io.incoming.lock().unwrap().is_empty()
+        {
+            break;
+        }
+    }
+    L::write("\n  terminating reactor I/O thread");
+}
+/*---------------------------------------------------------
+  Read until the socket would block, posting each whole
+  message as it arrives
+  - stops, leaving the rest in the socket, once the
+    Session is backlogged
+  - end of input, or a read or framing error, is recorded
+    in the Session, which closes once its queued messages
+    are processed
+*/
+fn read_conn<P,M,L>(conn: &mut Conn<P,M,L>, scratch: &mut [u8], pool: &SharedPool<P,M,L>)
+where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Send + Sndr<M> + Rcvr<M> + Process<M> + 'static,
+    L: Logger + 'static
+{
+    let mut ended = None;
+    while !conn.paused {
+        match conn.strm.read(scratch) {
+            Ok(0) => {
+                ended = Some(Ok(CloseReason::Dropped));
+                break;
+            }
+            Ok(n) => {
+                conn.activity.heard();
+                if !conn.failed {
+                    conn.inbuf.extend_from_slice(&scratch[..n]);
+                    frame_conn::<P,M,L>(conn, pool);
+                }
+                conn.paused = conn.session.backlogged();
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => {
+                ended = Some(Err(CommError::from(e)));
+                break;
+            }
+        }
+    }
+    /*-- as handle_client, input ending within a message is a framing error --*/
+    if let Some(Ok(_)) = ended {
+        if !conn.inbuf.is_empty() {
+            ended = Some(Err(CommError::Framing(format!(
+                "stream ended within message, {} bytes unread", conn.inbuf.len()
+            ))));
+        }
+    }
+    if ended.is_some() {
+        let mut state = conn.session.state.lock().unwrap();
+        if state.ended.is_none() {
+            state.ended = ended;
+        }
+    }
+}
+/*---------------------------------------------------------
+  Post each whole message in conn's input
+  - corrupted messages are skipped if the ChecksumPolicy
+    drops them
+  - a frame that can't be read is answered with
+    PROTOCOL_ERROR, see frame_limit.rs, ends the input,
+    and what follows it is discarded
+*/
+fn frame_conn<P,M,L>(conn: &mut Conn<P,M,L>, pool: &SharedPool<P,M,L>)
+where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Send + Sndr<M> + Rcvr<M> + Process<M> + 'static,
+    L: Logger + 'static
+{
+    let policy = conn.session.conns.checksum_policy();
+    let max = conn.session.conns.max_msg_size();
+    let mut used = 0;
+    while !conn.failed {
+        match P::decode_max(&conn.inbuf[used..], max) {
+            Ok(Some((msg, n))) => {
+                used += n;
+                if conn.session.push(msg) {
+                    pool.lock().unwrap().post(Some(Arc::clone(&conn.session)));
+                }
+            }
+            Ok(None) => break,
+            Err(CommError::ChecksumMismatch { size, .. }) if policy == ChecksumPolicy::Drop => {
+                L::write("\n  dropped corrupted message");
+                used += size;
+            }
+            Err(e) => {
+                let mut state = conn.session.state.lock().unwrap();
+                if let Some(reply) = protocol_error::<M>(&e) {
+                    state.refusal = P::encode(&reply);
+                }
+                if state.ended.is_none() {
+                    state.ended = Some(Err(e));
+                }
+                conn.failed = true;
+                used = conn.inbuf.len();
+            }
+        }
+    }
+    conn.inbuf.drain(..used);
+    if used > 0 {
+        conn.activity.framed(!conn.inbuf.is_empty());
+    }
+}
+/*---------------------------------------------------------
+  Write queued output until the socket would block
+  - as in handle_client, failed writes are dropped, the
+    read side reports why the connection ended
+*/
+fn write_conn<P,M,L>(conn: &mut Conn<P,M,L>) {
+    let mut state = conn.session.state.lock().unwrap();
+    let mut sent = 0;
+    while sent < state.out.len() {
+        match conn.strm.write(&state.out[sent..]) {
+            Ok(0) => {
+                sent = state.out.len();
+            }
+            Ok(n) => sent += n,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => {
+                sent = state.out.len();
+            }
+        }
+    }
+    state.out.drain(..sent);
+    conn.writing = !state.out.is_empty();
+}
+/*---------------------------------------------------------
+  Register conn's socket for reading unless paused, and
+  for writing while output remains
+  - a paused socket with nothing to write is deregistered,
+    the Session wakes its I/O thread when it catches up
+*/
+fn watch<P,M,L>(conn: &mut Conn<P,M,L>, poll: &Poll) {
+    let wanted = match (conn.paused, conn.writing) {
+        (false, false) => Some(Interest::READABLE),
+        (false, true) => Some(Interest::READABLE | Interest::WRITABLE),
+        (true, true) => Some(Interest::WRITABLE),
+        (true, false) => None,
+    };
+    if wanted == conn.watching {
+        return;
+    }
+    let token = Token(conn.session.id as usize);
+    let registry = poll.registry();
+    let rslt = match (conn.watching, wanted) {
+        (None, Some(interest)) => registry.register(&mut conn.strm, token, interest),
+        (Some(_), Some(interest)) => registry.reregister(&mut conn.strm, token, interest),
+        (Some(_), None) => registry.deregister(&mut conn.strm),
+        (None, None) => Ok(()),
+    };
+    if rslt.is_ok() {
+        conn.watching = wanted;
+    }
+}
@@ -0,0 +1,449 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::reconnect.rs - Connector reconnection        //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Connector::with_reconnect keeps a Connector alive across
+   lost connections:
+   - when its receive thread sees the connection fail, it
+     reconnects, waiting ReconnectPolicy::delay before each
+     attempt, and gives up after max_retries attempts
+   - the send thread waits while disconnected, so posted
+     messages stay queued, then sends them on the new
+     connection
+   - messages still held in the send buffer when the
+     connection failed are sent again; messages already
+     handed to the OS may be lost with the old connection
+   - a Connector that has sent END does not reconnect
+
+   Link is the connection state shared by the send and
+   receive threads.  Each new connection bumps its
+   generation, so a thread can tell whether the connection
+   it saw fail has already been replaced.  A Link made
+   with a TlsClientConfig does the TLS handshake on each
+   new connection, see tls.rs.  Link also holds the
+   Connector's Heartbeat, if it has one, and when the
+   connection was last heard from, see heartbeat.rs, and
+   its checksum settings, see checksum.rs, and its maximum
+   message size, see frame_limit.rs.
+
+   Link keeps the last STATES_KEPT transitions for
+   Connector::next_state, so a Connector whose state is
+   never read, while it reconnects for ever, holds only
+   those.
+*/
+
+use rust_blocking_queue::*;
+
+use rust_traits::*;
+
+use crate::transport::*;
+use crate::heartbeat::*;
+use crate::checksum::*;
+use crate::frame_limit::*;
+#[cfg(feature = "tls")]
+use crate::tls::*;
+
+use std::collections::VecDeque;
+use std::io::{BufReader, BufWriter, Write};
+use std::sync::{Condvar, Mutex, MutexGuard, atomic::AtomicUsize, atomic::Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/*---------------------------------------------------------
+  ReconnectPolicy - exponential backoff with jitter
+  - delay before attempt n is initial_delay * multiplier^n,
+    capped at max_delay, less a random fraction, up to
+    jitter, of that value
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: u32,
+    pub jitter: f64,
+    pub max_retries: u32,
+}
+impl Default for ReconnectPolicy {
+    fn default() -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2,
+            jitter: 0.2,
+            max_retries: 10,
+        }
+    }
+}
+impl ReconnectPolicy {
+    pub fn new(max_retries: u32) -> ReconnectPolicy {
+        ReconnectPolicy { max_retries, ..ReconnectPolicy::default() }
+    }
+    /*-- delay before attempt, counting from zero --*/
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.saturating_pow(attempt);
+        let base = self.initial_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |d| d.min(self.max_delay));
+        let jitter = self.jitter.clamp(0.0, 1.0) * random_fraction();
+        base.mul_f64(1.0 - jitter)
+    }
+}
+/*-- value in [0, 1), good enough to spread out clients --*/
+fn random_fraction() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    let mut x = (nanos as u64) ^ 0x9e37_79b9_7f4a_7c15;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/*---------------------------------------------------------
+  ConnectionState - Connector state transitions, see
+  Connector::next_state
+*/
+/*-- transitions kept for next_state, older ones are dropped --*/
+const STATES_KEPT: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting { attempt: u32 },
+    Connected,
+    Disconnected,
+    GaveUp,
+}
+
+/*---------------------------------------------------------
+  LinkWriter and LinkReader - the halves of a Link's
+  connection, plain or TLS
+*/
+#[derive(Debug)]
+pub(crate) enum LinkWriter<T: Transport> {
+    Plain(BufWriter<T::Writer>),
+    #[cfg(feature = "tls")]
+    Tls(BufWriter<TlsWriter<T>>),
+}
+impl<T: Transport> LinkWriter<T> {
+    /*-- send msg, a TLS writer also flushes when idle --*/
+    #[cfg_attr(not(feature = "tls"), allow(unused_variables))]
+    pub(crate) fn send<P: Sndr<M>, M>(&mut self, msg: &M, idle: bool) -> CommResult<()>
+    where M: Msg + Clone + Send + Default
+    {
+        match self {
+            LinkWriter::Plain(writer) => P::buf_send_message(msg, writer),
+            #[cfg(feature = "tls")]
+            LinkWriter::Tls(writer) => {
+                writer.write_all(&P::encode(msg))?;
+                if idle {
+                    writer.flush()?;
+                }
+                Ok(())
+            }
+        }
+    }
+    /*-- nothing written is still held here --*/
+    pub(crate) fn is_sent(&self) -> bool {
+        match self {
+            LinkWriter::Plain(writer) => writer.buffer().is_empty(),
+            #[cfg(feature = "tls")]
+            LinkWriter::Tls(writer) => writer.buffer().is_empty(),
+        }
+    }
+    pub(crate) fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            LinkWriter::Plain(writer) => writer.flush(),
+            #[cfg(feature = "tls")]
+            LinkWriter::Tls(writer) => writer.flush(),
+        }
+    }
+}
+#[derive(Debug)]
+pub(crate) enum LinkReader<T: Transport> {
+    Plain(BufReader<T::Reader>),
+    #[cfg(feature = "tls")]
+    Tls(FrameReader<TlsReader<T>>),
+}
+impl<T: Transport> LinkReader<T> {
+    pub(crate) fn recv<P: Rcvr<M>, M>(&mut self, max: usize) -> CommResult<M>
+    where M: Msg + Clone + Send + Default
+    {
+        match self {
+            LinkReader::Plain(reader) => P::buf_recv_message_max(reader, max),
+            #[cfg(feature = "tls")]
+            LinkReader::Tls(reader) => reader.next::<P,M>(max),
+        }
+    }
+}
+
+/*---------------------------------------------------------
+  Link - connection shared by Connector's threads
+*/
+#[derive(Debug)]
+pub(crate) struct Link<T: Transport> {
+    conn: Mutex<LinkConn<T>>,
+    cv: Condvar,
+    /*-- transitions not yet taken by next_state, newest last --*/
+    states: Mutex<VecDeque<ConnectionState>>,
+    state_cv: Condvar,
+    activity: Activity,
+    /*-- read without locking conn, which the sender may hold --*/
+    max_msg_size: AtomicUsize,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsClientConfig>,
+}
+#[derive(Debug)]
+pub(crate) struct LinkConn<T: Transport> {
+    pub(crate) writer: Option<LinkWriter<T>>,
+    /*-- current connection, while writer is Some --*/
+    strm: Option<T::Stream>,
+    pub(crate) generation: u64,
+    state: ConnectionState,
+    heartbeat: Option<Heartbeat>,
+    /*-- send messages with FLAG_CHECKSUM --*/
+    pub(crate) checksums: bool,
+    checksum_policy: ChecksumPolicy,
+    closing: bool,
+    closed: bool,
+}
+impl<T: Transport> Link<T> {
+    pub(crate) fn new() -> Link<T> {
+        Link {
+            conn: Mutex::new(LinkConn {
+                writer: None,
+                strm: None,
+                generation: 0,
+                state: ConnectionState::Disconnected,
+                heartbeat: None,
+                checksums: false,
+                checksum_policy: ChecksumPolicy::default(),
+                closing: false,
+                closed: false,
+            }),
+            cv: Condvar::new(),
+            states: Mutex::new(VecDeque::new()),
+            state_cv: Condvar::new(),
+            activity: Activity::new(),
+            max_msg_size: AtomicUsize::new(DEFAULT_MAX_MSG_SIZE),
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+    /*-- connections are TLS sessions, set up with tls --*/
+    #[cfg(feature = "tls")]
+    pub(crate) fn with_tls(tls: TlsClientConfig) -> Link<T> {
+        Link { tls: Some(tls), ..Link::new() }
+    }
+    fn publish(&self, conn: &mut LinkConn<T>, state: ConnectionState) {
+        conn.state = state;
+        let mut states = self.states.lock().unwrap();
+        if states.len() == STATES_KEPT {
+            states.pop_front();
+        }
+        states.push_back(state);
+        self.state_cv.notify_all();
+    }
+    pub(crate) fn state(&self) -> ConnectionState {
+        self.conn.lock().unwrap().state
+    }
+    pub(crate) fn next_state(&self) -> ConnectionState {
+        let mut states = self.states.lock().unwrap();
+        loop {
+            if let Some(state) = states.pop_front() {
+                return state;
+            }
+            states = self.state_cv.wait(states).unwrap();
+        }
+    }
+    pub(crate) fn peer_addr(&self) -> CommResult<T::PeerAddr> {
+        match &self.conn.lock().unwrap().strm {
+            Some(strm) => Ok(T::peer_addr(strm)?),
+            None => Err(CommError::PeerClosed),
+        }
+    }
+    pub(crate) fn local_addr(&self) -> CommResult<T::PeerAddr> {
+        match &self.conn.lock().unwrap().strm {
+            Some(strm) => Ok(T::local_addr(strm)?),
+            None => Err(CommError::PeerClosed),
+        }
+    }
+    pub(crate) fn connecting(&self, attempt: u32) {
+        let mut conn = self.conn.lock().unwrap();
+        self.publish(&mut conn, ConnectionState::Connecting { attempt });
+    }
+    /*-- make strm the current connection, returning its reader and generation --*/
+    pub(crate) fn install(&self, strm: T::Stream) -> CommResult<(LinkReader<T>, u64)> {
+        let (reader, writer) = self.split(&strm)?;
+        let mut conn = self.conn.lock().unwrap();
+        conn.writer = Some(writer);
+        conn.strm = Some(strm);
+        conn.generation += 1;
+        let generation = conn.generation;
+        self.activity.ready(false);
+        self.publish(&mut conn, ConnectionState::Connected);
+        self.cv.notify_all();
+        Ok((reader, generation))
+    }
+    #[cfg(feature = "tls")]
+    fn split(&self, strm: &T::Stream) -> CommResult<(LinkReader<T>, LinkWriter<T>)> {
+        match &self.tls {
+            Some(tls) => {
+                let (reader, writer) = tls.connect::<T>(strm)?;
+                Ok((
+                    LinkReader::Tls(FrameReader::new(reader)),
+                    LinkWriter::Tls(BufWriter::new(writer)),
+                ))
+            }
+            None => split_plain::<T>(strm),
+        }
+    }
+    #[cfg(not(feature = "tls"))]
+    fn split(&self, strm: &T::Stream) -> CommResult<(LinkReader<T>, LinkWriter<T>)> {
+        split_plain::<T>(strm)
+    }
+    pub(crate) fn generation(&self) -> u64 {
+        self.conn.lock().unwrap().generation
+    }
+    pub(crate) fn activity(&self) -> &Activity {
+        &self.activity
+    }
+    /*-- true if this is the first Heartbeat, which starts the watching thread --*/
+    pub(crate) fn set_heartbeat(&self, heartbeat: Heartbeat) -> bool {
+        self.conn.lock().unwrap().heartbeat.replace(heartbeat).is_none()
+    }
+    pub(crate) fn set_checksums(&self, on: bool) {
+        self.conn.lock().unwrap().checksums = on;
+    }
+    pub(crate) fn set_checksum_policy(&self, policy: ChecksumPolicy) {
+        self.conn.lock().unwrap().checksum_policy = policy;
+    }
+    pub(crate) fn checksum_policy(&self) -> ChecksumPolicy {
+        self.conn.lock().unwrap().checksum_policy
+    }
+    pub(crate) fn set_max_msg_size(&self, max: usize) {
+        self.max_msg_size.store(max, Ordering::Relaxed);
+    }
+    pub(crate) fn max_msg_size(&self) -> usize {
+        self.max_msg_size.load(Ordering::Relaxed)
+    }
+    /*-- tell the peer why its frame was refused, see frame_limit.rs --*/
+    pub(crate) fn reject<P: Sndr<M>, M>(&self, generation: u64, e: &CommError)
+    where M: Msg + Clone + Send + Default
+    {
+        let reply = match protocol_error::<M>(e) {
+            Some(reply) => reply,
+            None => return,
+        };
+        let mut conn = self.conn.lock().unwrap();
+        if conn.generation != generation {
+            return;
+        }
+        if let Some(writer) = conn.writer.as_mut() {
+            let _ = writer.send::<P,M>(&reply, true);
+            let _ = writer.flush();
+        }
+    }
+    /*-- None once there will be no more connections --*/
+    pub(crate) fn heartbeat(&self) -> Option<Heartbeat> {
+        let conn = self.conn.lock().unwrap();
+        match conn.closed {
+            true => None,
+            false => conn.heartbeat,
+        }
+    }
+    /*-- connection generation failed, unless already replaced --*/
+    pub(crate) fn lost(&self, generation: u64) {
+        let mut conn = self.conn.lock().unwrap();
+        if conn.generation != generation {
+            return;
+        }
+        if let Some(strm) = conn.strm.take() {
+            /*-- discard unsent bytes, sender resends them --*/
+            let _ = T::shutdown(&strm);
+            conn.writer = None;
+            self.publish(&mut conn, ConnectionState::Disconnected);
+        }
+    }
+    /*-- no more connections, wakes a waiting sender --*/
+    pub(crate) fn close(&self, gave_up: bool) {
+        let mut conn = self.conn.lock().unwrap();
+        conn.closed = true;
+        if gave_up {
+            self.publish(&mut conn, ConnectionState::GaveUp);
+        }
+        self.cv.notify_all();
+    }
+    /*-- sender is about to send END, don't reconnect after --*/
+    pub(crate) fn set_closing(&self) {
+        self.conn.lock().unwrap().closing = true;
+    }
+    pub(crate) fn is_closing(&self) -> bool {
+        self.conn.lock().unwrap().closing
+    }
+    /*-- wait for a connection, None if there won't be one --*/
+    pub(crate) fn writer(&self) -> Option<MutexGuard<'_, LinkConn<T>>> {
+        let mut conn = self.conn.lock().unwrap();
+        while conn.writer.is_none() {
+            if conn.closed {
+                return None;
+            }
+            conn = self.cv.wait(conn).unwrap();
+        }
+        Some(conn)
+    }
+}
+
+fn split_plain<T: Transport>(strm: &T::Stream) -> CommResult<(LinkReader<T>, LinkWriter<T>)> {
+    let (reader, writer) = T::split(strm)?;
+    Ok((
+        LinkReader::Plain(BufReader::new(reader)),
+        LinkWriter::Plain(BufWriter::new(writer)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_backs_off_to_max() {
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            multiplier: 2,
+            jitter: 0.0,
+            max_retries: 10,
+        };
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(3), Duration::from_millis(800));
+        assert_eq!(policy.delay(4), Duration::from_millis(1000));
+        assert_eq!(policy.delay(100), Duration::from_millis(1000));
+
+        let jittered = ReconnectPolicy { jitter: 0.5, ..policy };
+        for attempt in 0..10 {
+            let d = jittered.delay(attempt);
+            assert!(d <= policy.delay(attempt));
+            assert!(d >= policy.delay(attempt) / 2);
+        }
+    }
+
+    #[test]
+    fn unread_states_are_bounded() {
+        let link = Link::<Tcp>::new();
+        let mut conn = link.conn.lock().unwrap();
+        for attempt in 0..100 {
+            link.publish(&mut conn, ConnectionState::Connecting { attempt });
+        }
+        link.publish(&mut conn, ConnectionState::GaveUp);
+        drop(conn);
+        assert_eq!(link.states.lock().unwrap().len(), STATES_KEPT);
+        let first = 101 - STATES_KEPT as u32;
+        assert_eq!(link.next_state(), ConnectionState::Connecting { attempt: first });
+        for _ in 1..STATES_KEPT - 1 {
+            link.next_state();
+        }
+        assert_eq!(link.next_state(), ConnectionState::GaveUp);
+        assert_eq!(link.state(), ConnectionState::GaveUp);
+    }
+}
@@ -0,0 +1,410 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::shutdown.rs - graceful Listener shutdown     //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Listener::start returns a ListenerHandle.  Its shutdown
+   method stops the Listener in stages:
+   - stop accepting: the accept thread polls a non-blocking
+     listener, so it sees the run flag promptly, then
+     posts one stop sentinel, None, for each pool thread
+   - notify: every open connection is sent a SHUTDOWN
+     message.  Connector answers with END, after anything
+     it has already queued, so in-flight requests finish.
+     SHUTDOWN goes through the connection's Pusher, see
+     push.rs, so a client that has stopped reading, or a
+     handler holding its writer, can't hold up shutdown
+   - drain: wait, up to timeout, for client handlers to
+     see END and close their connections
+   - force: connections still open at the deadline are
+     shut down, which ends their handlers' reads
+   - join the accept thread and every pool thread
+
+   Connections is the registry that makes this possible.
+   The accept thread registers each connection, with the
+   writer its handler sends on, and the pool thread that
+   handles it removes the entry when the handler returns.
+   In reactor mode, see reactor.rs, SHUTDOWN, like every
+   pushed message, is queued for the connection's I/O
+   thread instead, without a Pusher, and the I/O thread
+   removes the entry.  Each entry also keeps its stream,
+   so close_all can shut it down with Transport::shutdown.
+
+   Connections also holds the Listener's Topics, see
+   pubsub.rs, so a connection's subscriptions end when it
+   is removed, each connection's peer address and Pusher,
+   for Clients, see push.rs, its Activity, for the
+   Listener's heartbeat, see heartbeat.rs, and the
+   Listener's ChecksumPolicy, see checksum.rs, and maximum
+   message size, see frame_limit.rs.
+*/
+
+use crate::transport::*;
+use crate::pubsub::*;
+use crate::push::*;
+use crate::heartbeat::*;
+use crate::observer::*;
+use crate::checksum::*;
+use rust_traits::*;
+use rust_message::*;
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::io::{BufWriter, Result, Write};
+use std::marker::PhantomData;
+use std::sync::{Arc, Condvar, Mutex, atomic::AtomicBool, atomic::Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/*-- writer shared by a client handler and shutdown --*/
+pub type SharedWriter<T = Tcp> = Arc<Mutex<BufWriter<<T as Transport>::Writer>>>;
+
+/*-- work item for Listener pool threads, None stops a thread --*/
+pub type Job<T = Tcp> = Option<(
+    u64, <T as Transport>::PeerAddr, <T as Transport>::Reader, SharedWriter<T>
+)>;
+
+/*-- joins a Listener's pool, and I/O threads, once it stops accepting --*/
+pub(crate) type Workers = Box<dyn FnOnce() + Send>;
+
+/*-- how SHUTDOWN reaches a connection: its writer, or its I/O thread's queue --*/
+pub(crate) trait Outbox : Send + Sync {
+    fn queue(&self, bytes: Vec<u8>);
+    /*-- true if queue returns at once, whatever the peer is doing --*/
+    fn never_waits(&self) -> bool {
+        false
+    }
+}
+impl<W: Write + Send> Outbox for Mutex<BufWriter<W>> {
+    fn queue(&self, bytes: Vec<u8>) {
+        let mut writer = self.lock().unwrap();
+        let _ = writer.write_all(&bytes);
+        let _ = writer.flush();
+    }
+}
+/*-- registered connection, close shuts it down --*/
+struct Entry {
+    close: Box<dyn Fn() + Send>,
+    outbox: Arc<dyn Outbox>,
+    /*-- Transport::PeerAddr --*/
+    peer: Box<dyn Any + Send + Sync>,
+    /*-- started by the first push --*/
+    pusher: Option<Pusher>,
+    activity: Arc<Activity>,
+    /*-- closed by the heartbeat or frame timeout --*/
+    timed_out: bool,
+}
+impl Entry {
+    /*-- behind earlier pushes, and never waiting on a slow client --*/
+    fn push(&mut self, bytes: Vec<u8>) {
+        if self.pusher.is_none() && self.outbox.never_waits() {
+            self.outbox.queue(bytes);
+            return;
+        }
+        let outbox = &self.outbox;
+        self.pusher.get_or_insert_with(|| Pusher::start(Arc::clone(outbox))).push(bytes);
+    }
+}
+impl std::fmt::Debug for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Entry")
+    }
+}
+
+/*---------------------------------------------------------
+  Connections - registry of a Listener's open connections
+*/
+#[derive(Debug, Default)]
+pub struct Connections {
+    map: Mutex<HashMap<u64, Entry>>,
+    cv: Condvar,
+    next_id: Mutex<u64>,
+    forcing: AtomicBool,
+    topics: Topics,
+    checksum_policy: Mutex<ChecksumPolicy>,
+    max_msg_size: Mutex<Option<usize>>,
+}
+impl Connections {
+    pub fn new() -> Connections {
+        Connections::default()
+    }
+    /*-- register strm, returning its id, reader, and shared writer --*/
+    pub fn add<T: Transport>(
+        &self, strm: T::Stream, peer: T::PeerAddr
+    ) -> Result<(u64, T::Reader, SharedWriter<T>)> {
+        let (reader, writer) = T::split(&strm)?;
+        let writer = Arc::new(Mutex::new(BufWriter::new(writer)));
+        let id = self.reserve_id();
+        self.insert::<T>(id, strm, peer, writer.clone());
+        Ok((id, reader, writer))
+    }
+    pub(crate) fn reserve_id(&self) -> u64 {
+        let mut next = self.next_id.lock().unwrap();
+        *next += 1;
+        *next
+    }
+    /*-- register strm, with id from reserve_id --*/
+    pub(crate) fn insert<T: Transport>(
+        &self, id: u64, strm: T::Stream, peer: T::PeerAddr, outbox: Arc<dyn Outbox>
+    ) {
+        let close = Box::new(move || {
+            let _ = T::shutdown(&strm);
+        });
+        let entry = Entry {
+            close, outbox, peer: Box::new(peer), pusher: None,
+            activity: Arc::new(Activity::new()), timed_out: false,
+        };
+        self.map.lock().unwrap().insert(id, entry);
+    }
+    pub(crate) fn set_checksum_policy(&self, policy: ChecksumPolicy) {
+        *self.checksum_policy.lock().unwrap() = policy;
+    }
+    pub(crate) fn checksum_policy(&self) -> ChecksumPolicy {
+        *self.checksum_policy.lock().unwrap()
+    }
+    pub(crate) fn set_max_msg_size(&self, max: usize) {
+        *self.max_msg_size.lock().unwrap() = Some(max);
+    }
+    pub(crate) fn max_msg_size(&self) -> usize {
+        self.max_msg_size.lock().unwrap().unwrap_or(DEFAULT_MAX_MSG_SIZE)
+    }
+    /*-- a restarted Listener's connections aren't forced --*/
+    pub(crate) fn reopen(&self) {
+        self.forcing.store(false, Ordering::Relaxed);
+    }
+    /*-----------------------------------------------------
+      Called when connection id's handler returns
+      - returns Shutdown if it was forced closed, TimedOut
+        if the heartbeat or frame timeout closed it
+    */
+    pub fn remove(&self, id: u64) -> Option<CloseReason> {
+        let entry = self.map.lock().unwrap().remove(&id);
+        self.topics.remove(id);
+        self.cv.notify_all();
+        if entry.is_some_and(|entry| entry.timed_out) {
+            return Some(CloseReason::TimedOut);
+        }
+        self.forcing.load(Ordering::Relaxed).then_some(CloseReason::Shutdown)
+    }
+    /*-- connection id's Activity, a new one if it isn't registered --*/
+    pub(crate) fn activity(&self, id: u64) -> Arc<Activity> {
+        self.map.lock().unwrap().get(&id)
+            .map_or_else(|| Arc::new(Activity::new()), |entry| Arc::clone(&entry.activity))
+    }
+    /*-- check every connection's Activity, closing those expired --*/
+    pub(crate) fn check(
+        &self, heartbeat: Option<&Heartbeat>, frame_timeout: Option<Duration>
+    ) -> Vec<(u64, Verdict)> {
+        let mut map = self.map.lock().unwrap();
+        let mut verdicts = Vec::new();
+        for (id, entry) in map.iter_mut() {
+            let verdict = entry.activity.check(heartbeat, frame_timeout);
+            if verdict == Verdict::Expired {
+                if entry.timed_out {
+                    continue;
+                }
+                entry.timed_out = true;
+                (entry.close)();
+            }
+            if verdict != Verdict::Fine {
+                verdicts.push((*id, verdict));
+            }
+        }
+        verdicts
+    }
+    /*-- handle SUBSCRIBE, UNSUBSCRIBE, or PUBLISH from connection id --*/
+    pub(crate) fn topic_message<P,M,L>(&self, id: u64, msg: &M) -> Option<M>
+    where
+        M: Msg + Clone + Send + Default,
+        P: Sndr<M>,
+        L: Logger
+    {
+        self.topics.handle::<P,M,L>(self, id, msg)
+    }
+    /*-- queue bytes for connection id, false if it isn't open --*/
+    pub(crate) fn push(&self, id: u64, bytes: Vec<u8>) -> bool {
+        match self.map.lock().unwrap().get_mut(&id) {
+            Some(entry) => {
+                entry.push(bytes);
+                true
+            }
+            None => false,
+        }
+    }
+    /*-- messages queued on connection id's Pusher --*/
+    pub(crate) fn pending(&self, id: u64) -> usize {
+        self.map.lock().unwrap().get(&id)
+            .and_then(|entry| entry.pusher.as_ref())
+            .map_or(0, |pusher| pusher.len())
+    }
+    /*-- ids of open connections, in order of connection --*/
+    pub(crate) fn ids(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = self.map.lock().unwrap().keys().cloned().collect();
+        ids.sort_unstable();
+        ids
+    }
+    /*-- ids and peer addresses, A is the Transport's PeerAddr --*/
+    pub(crate) fn peers<A: Clone + 'static>(&self) -> Vec<(u64, A)> {
+        let mut peers: Vec<(u64, A)> = self.map.lock().unwrap().iter()
+            .filter_map(|(id, entry)| entry.peer.downcast_ref::<A>().map(|a| (*id, a.clone())))
+            .collect();
+        peers.sort_unstable_by_key(|(id, _)| *id);
+        peers
+    }
+    pub fn len(&self) -> usize {
+        self.map.lock().unwrap().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.map.lock().unwrap().is_empty()
+    }
+    /*-- send SHUTDOWN on every open connection, after queued pushes --*/
+    pub fn notify<P, M>(&self)
+    where
+        M: Msg + Clone + Send + Default,
+        P: Sndr<M>
+    {
+        let mut msg = M::new(HEADER_SIZE);
+        msg.set_type(MessageType::SHUTDOWN as u8);
+        let bytes = P::encode(&msg);
+        for entry in self.map.lock().unwrap().values_mut() {
+            entry.push(bytes.clone());
+        }
+    }
+    /*-- wait until no connections are open or deadline, if any, passes --*/
+    pub fn wait_empty(&self, deadline: Option<Instant>) -> bool {
+        let mut map = self.map.lock().unwrap();
+        while !map.is_empty() {
+            let deadline = match deadline {
+                Some(deadline) => deadline,
+                None => {
+                    map = self.cv.wait(map).unwrap();
+                    continue;
+                }
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            map = self.cv.wait_timeout(map, deadline - now).unwrap().0;
+        }
+        true
+    }
+    /*-- shut down every open socket, returning their count --*/
+    pub fn close_all(&self) -> usize {
+        let map = self.map.lock().unwrap();
+        self.forcing.store(true, Ordering::Relaxed);
+        for entry in map.values() {
+            (entry.close)();
+        }
+        map.len()
+    }
+}
+
+/*---------------------------------------------------------
+  ShutdownReport - outcome of ListenerHandle::shutdown
+  - drained connections closed on their own
+  - forced connections were still open at the deadline
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    pub drained: usize,
+    pub forced: usize,
+}
+
+/*---------------------------------------------------------
+  ListenerHandle<P,M,L> - returned by Listener::start
+*/
+#[derive(Debug)]
+pub struct ListenerHandle<P,M,L> {
+    run: Arc<AtomicBool>,
+    conns: Arc<Connections>,
+    accept: Option<JoinHandle<Workers>>,
+    _t: PhantomData<(P, M, L)>,
+}
+impl<P,M,L> ListenerHandle<P,M,L>
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M>,
+    L: Logger
+{
+    pub(crate) fn new(
+        run: Arc<AtomicBool>, conns: Arc<Connections>,
+        accept: JoinHandle<Workers>
+    ) -> ListenerHandle<P,M,L> {
+        ListenerHandle {
+            run, conns, accept: Some(accept), _t: PhantomData,
+        }
+    }
+    /*-- number of currently open client connections --*/
+    pub fn connections(&self) -> usize {
+        self.conns.len()
+    }
+    /*-----------------------------------------------------
+      Stop accepting, notify clients, wait up to timeout
+      for them to close, force the rest, and join the
+      accept thread, every pool thread, and in reactor
+      mode every I/O thread
+      - a handler busy in process_message is joined when
+        that call returns
+    */
+    pub fn shutdown(mut self, timeout: Duration) -> ShutdownReport {
+        L::write("\n--shutting down listener--");
+        /*-- None, for a timeout too long to represent, waits for every client --*/
+        let deadline = Instant::now().checked_add(timeout);
+        self.run.store(false, Ordering::Relaxed);
+        let workers = self.accept.take().and_then(|h| h.join().ok());
+
+        /*-- no new connections now, so this count is final --*/
+        let open = self.conns.len();
+        self.conns.notify::<P,M>();
+        let forced = if self.conns.wait_empty(deadline) {
+            0
+        }
+        else {
+            self.conns.close_all()
+        };
+        if let Some(workers) = workers {
+            workers();
+        }
+        L::write(&format!(
+            "\n--listener shut down, {} drained, {} forced--", open - forced, forced
+        ));
+        ShutdownReport { drained: open - forced, forced }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::*;
+
+    /*-- outbox of a reactor Session, queue never waits --*/
+    #[derive(Default)]
+    struct Queued(Mutex<Vec<u8>>);
+    impl Outbox for Queued {
+        fn queue(&self, bytes: Vec<u8>) {
+            self.0.lock().unwrap().extend(bytes);
+        }
+        fn never_waits(&self) -> bool {
+            true
+        }
+    }
+    #[test]
+    fn never_waiting_outboxes_get_no_pusher() {
+        let name = "never_waits".to_string();
+        let _lsnr = Memory::bind(&name).unwrap();
+        let conns = Connections::new();
+        let outbox = Arc::new(Queued::default());
+        let id = conns.reserve_id();
+        conns.insert::<Memory>(id, Memory::connect(&name).unwrap(), name.clone(), outbox.clone());
+
+        assert!(conns.push(id, vec![1, 2]));
+        conns.notify::<rust_comm_processing::CommProcessing<rust_comm_logger::MuteLog>, Message>();
+        assert!(conns.map.lock().unwrap()[&id].pusher.is_none());
+        let queued = outbox.0.lock().unwrap();
+        assert_eq!(queued[..2], [1, 2]);
+        assert_eq!(queued[TYPE_OFFSET + 2], MessageType::SHUTDOWN as u8);
+        drop(queued);
+        assert!(!conns.push(id + 1, vec![3]));
+    }
+}
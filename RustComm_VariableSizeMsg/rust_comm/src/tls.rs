@@ -0,0 +1,565 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::tls.rs - TLS for Connector and Listener      //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   With the tls feature, Connector and Listener can encrypt
+   their connections with rustls:
+   - Listener::set_tls(TlsServerConfig) makes the Listener
+     a TLS server with a certificate chain and key, and,
+     with TlsServerConfig::with_client_auth, requires
+     clients to present certificates signed by given roots
+   - Connector::with_tls(addr, TlsClientConfig, policy)
+     connects as a TLS client that trusts a root store,
+     TlsClientConfig::new, or exactly one pinned server
+     certificate, TlsClientConfig::pinned.  Either may
+     present a client certificate for mutual TLS.
+   - from_rustls wraps a rustls config built by hand
+
+   Certificates and keys are PEM.  Handshakes run on the
+   Connector's thread and on the Listener's pool threads,
+   failures are CommError::Tls.
+
+   TLS runs over any Transport.  Messages are framed with
+   P::encode and P::decode, which use the same framing as
+   Sndr and Rcvr.  A TLS Connector flushes whenever its
+   send queue is empty, and a TLS Listener after each
+   message's replies.  Reactor mode doesn't support TLS.
+
+   A rustls session is shared by the reader and writer
+   halves of its connection.  The reader reads its half
+   without holding the session, then decrypts under its
+   lock, so one thread can send while another waits to
+   receive.
+*/
+
+use crate::*;
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{
+    ClientConfig, ClientConnection, Connection, DigitallySignedStruct,
+    RootCertStore, ServerConfig, ServerConnection, SignatureScheme,
+};
+use std::convert::TryFrom;
+use std::io::{ErrorKind, Read};
+use std::sync::MutexGuard;
+
+fn tls_error<E: std::fmt::Display>(e: E) -> CommError {
+    CommError::Tls(e.to_string())
+}
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+fn certs_from_pem(pem: &[u8]) -> CommResult<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_slice_iter(pem)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(tls_error)?;
+    if certs.is_empty() {
+        return Err(CommError::Tls("no certificates in PEM".to_string()));
+    }
+    Ok(certs)
+}
+fn roots_from_pem(pem: &[u8]) -> CommResult<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in certs_from_pem(pem)? {
+        roots.add(cert).map_err(tls_error)?;
+    }
+    Ok(roots)
+}
+
+/*---------------------------------------------------------
+  TlsIdentity - certificate chain and private key, for a
+  server, or a client doing mutual TLS
+*/
+#[derive(Debug)]
+pub struct TlsIdentity {
+    certs: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+}
+impl TlsIdentity {
+    /*-- chain starts with the end entity certificate --*/
+    pub fn from_pem(cert_chain_pem: &[u8], key_pem: &[u8]) -> CommResult<TlsIdentity> {
+        Ok(TlsIdentity {
+            certs: certs_from_pem(cert_chain_pem)?,
+            key: PrivateKeyDer::from_pem_slice(key_pem).map_err(tls_error)?,
+        })
+    }
+}
+
+/*---------------------------------------------------------
+  TlsServerConfig - configures Listener::set_tls
+*/
+#[derive(Debug, Clone)]
+pub struct TlsServerConfig {
+    config: Arc<ServerConfig>,
+}
+impl TlsServerConfig {
+    /*-- clients are not asked for certificates --*/
+    pub fn new(identity: TlsIdentity) -> CommResult<TlsServerConfig> {
+        let config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions().map_err(tls_error)?
+            .with_no_client_auth()
+            .with_single_cert(identity.certs, identity.key).map_err(tls_error)?;
+        Ok(TlsServerConfig::from_rustls(Arc::new(config)))
+    }
+    /*-- mutual TLS: clients must present certificates signed by client_roots_pem --*/
+    pub fn with_client_auth(
+        identity: TlsIdentity, client_roots_pem: &[u8]
+    ) -> CommResult<TlsServerConfig> {
+        let roots = Arc::new(roots_from_pem(client_roots_pem)?);
+        let verifier = WebPkiClientVerifier::builder_with_provider(roots, provider())
+            .build().map_err(tls_error)?;
+        let config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions().map_err(tls_error)?
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(identity.certs, identity.key).map_err(tls_error)?;
+        Ok(TlsServerConfig::from_rustls(Arc::new(config)))
+    }
+    pub fn from_rustls(config: Arc<ServerConfig>) -> TlsServerConfig {
+        TlsServerConfig { config }
+    }
+    /*-- server side handshake on a connection's halves --*/
+    pub(crate) fn accept<T: Transport>(
+        &self, reader: T::Reader, writer: T::Writer
+    ) -> CommResult<(TlsReader<T>, TlsWriter<T>)> {
+        let conn = ServerConnection::new(Arc::clone(&self.config)).map_err(tls_error)?;
+        handshake::<T>(conn.into(), reader, writer)
+    }
+}
+
+/*---------------------------------------------------------
+  TlsClientConfig - configures Connector::with_tls
+  - server_name is checked against the server certificate,
+    except by pinned configs, which match the whole
+    certificate instead
+*/
+#[derive(Debug, Clone)]
+pub struct TlsClientConfig {
+    config: Arc<ClientConfig>,
+    server_name: ServerName<'static>,
+}
+impl TlsClientConfig {
+    /*-- trust servers with certificates signed by roots_pem --*/
+    pub fn new(
+        roots_pem: &[u8], server_name: &str, identity: Option<TlsIdentity>
+    ) -> CommResult<TlsClientConfig> {
+        let builder = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions().map_err(tls_error)?
+            .with_root_certificates(roots_from_pem(roots_pem)?);
+        let config = match identity {
+            Some(id) => builder.with_client_auth_cert(id.certs, id.key).map_err(tls_error)?,
+            None => builder.with_no_client_auth(),
+        };
+        TlsClientConfig::from_rustls(Arc::new(config), server_name)
+    }
+    /*-- trust only the server presenting server_cert_pem --*/
+    pub fn pinned(
+        server_cert_pem: &[u8], server_name: &str, identity: Option<TlsIdentity>
+    ) -> CommResult<TlsClientConfig> {
+        let pinned = PinnedCert {
+            cert: certs_from_pem(server_cert_pem)?.remove(0),
+            provider: provider(),
+        };
+        let builder = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions().map_err(tls_error)?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(pinned));
+        let config = match identity {
+            Some(id) => builder.with_client_auth_cert(id.certs, id.key).map_err(tls_error)?,
+            None => builder.with_no_client_auth(),
+        };
+        TlsClientConfig::from_rustls(Arc::new(config), server_name)
+    }
+    pub fn from_rustls(
+        config: Arc<ClientConfig>, server_name: &str
+    ) -> CommResult<TlsClientConfig> {
+        let server_name = ServerName::try_from(server_name.to_string()).map_err(tls_error)?;
+        Ok(TlsClientConfig { config, server_name })
+    }
+    /*-- client side handshake on strm --*/
+    pub(crate) fn connect<T: Transport>(
+        &self, strm: &T::Stream
+    ) -> CommResult<(TlsReader<T>, TlsWriter<T>)> {
+        let conn = ClientConnection::new(Arc::clone(&self.config), self.server_name.clone())
+            .map_err(tls_error)?;
+        let (reader, writer) = T::split(strm)?;
+        handshake::<T>(conn.into(), reader, writer)
+    }
+}
+
+/*---------------------------------------------------------
+  PinnedCert - accepts exactly one server certificate,
+  whatever signed it, and checks handshake signatures
+  as usual
+*/
+#[derive(Debug)]
+struct PinnedCert {
+    cert: CertificateDer<'static>,
+    provider: Arc<CryptoProvider>,
+}
+impl ServerCertVerifier for PinnedCert {
+    fn verify_server_cert(
+        &self, end_entity: &CertificateDer<'_>, _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>, _ocsp_response: &[u8], _now: UnixTime
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        if end_entity.as_ref() == self.cert.as_ref() {
+            Ok(ServerCertVerified::assertion())
+        }
+        else {
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure
+            ))
+        }
+    }
+    fn verify_tls12_signature(
+        &self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message, cert, dss, &self.provider.signature_verification_algorithms
+        )
+    }
+    fn verify_tls13_signature(
+        &self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message, cert, dss, &self.provider.signature_verification_algorithms
+        )
+    }
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/*---------------------------------------------------------
+  TlsSession - rustls session shared by TlsReader and
+  TlsWriter
+  - send orders writes to the connection: it is locked
+    before the session is released, so records go out in
+    the order the session made them
+*/
+struct TlsSession<W> {
+    conn: Mutex<Connection>,
+    send: Mutex<W>,
+}
+impl<W: Write> TlsSession<W> {
+    /*-- write records the session has queued --*/
+    fn send_pending(&self, mut conn: MutexGuard<'_, Connection>) -> std::io::Result<()> {
+        let mut records = Vec::new();
+        while conn.wants_write() {
+            conn.write_tls(&mut records)?;
+        }
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut send = self.send.lock().unwrap();
+        drop(conn);
+        send.write_all(&records)?;
+        send.flush()
+    }
+}
+/*-- a connection's halves as one stream, for the handshake --*/
+struct Duplex<'a, R, W>(&'a mut R, &'a mut W);
+impl<R: Read, W> Read for Duplex<'_, R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+impl<R, W: Write> Write for Duplex<'_, R, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.1.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.1.flush()
+    }
+}
+fn handshake<T: Transport>(
+    mut conn: Connection, mut reader: T::Reader, mut writer: T::Writer
+) -> CommResult<(TlsReader<T>, TlsWriter<T>)> {
+    let mut io = Duplex(&mut reader, &mut writer);
+    while conn.is_handshaking() {
+        conn.complete_io(&mut io).map_err(tls_error)?;
+    }
+    while conn.wants_write() {
+        conn.write_tls(&mut io)?;
+    }
+    io.flush()?;
+    let session = Arc::new(TlsSession {
+        conn: Mutex::new(conn),
+        send: Mutex::new(writer),
+    });
+    let reader = TlsReader {
+        session: Arc::clone(&session),
+        sock: reader,
+        raw: vec![0u8; 16 * 1024],
+    };
+    Ok((reader, TlsWriter { session }))
+}
+
+/*-- decrypting half of a TLS connection --*/
+pub(crate) struct TlsReader<T: Transport> {
+    session: Arc<TlsSession<T::Writer>>,
+    sock: T::Reader,
+    raw: Vec<u8>,
+}
+impl<T: Transport> Debug for TlsReader<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "TlsReader({:?})", self.sock)
+    }
+}
+impl<T: Transport> Read for TlsReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            match self.session.conn.lock().unwrap().reader().read(buf) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                rslt => return rslt,
+            }
+            let n = self.sock.read(&mut self.raw)?;
+            let mut conn = self.session.conn.lock().unwrap();
+            let mut bytes = &self.raw[..n];
+            /*-- reading no bytes tells the session the connection closed --*/
+            let rslt = loop {
+                conn.read_tls(&mut bytes)?;
+                if let Err(e) = conn.process_new_packets() {
+                    break Err(std::io::Error::new(ErrorKind::InvalidData, e));
+                }
+                if bytes.is_empty() {
+                    break Ok(());
+                }
+            };
+            /*-- alerts and post-handshake replies --*/
+            self.session.send_pending(conn)?;
+            rslt?;
+        }
+    }
+}
+
+/*-- encrypting half of a TLS connection --*/
+pub(crate) struct TlsWriter<T: Transport> {
+    session: Arc<TlsSession<T::Writer>>,
+}
+impl<T: Transport> Debug for TlsWriter<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "TlsWriter({:?})", self.session.send.lock().unwrap())
+    }
+}
+impl<T: Transport> TlsWriter<T> {
+    /*-- tell the peer no more data follows --*/
+    pub(crate) fn close(&mut self) {
+        let mut conn = self.session.conn.lock().unwrap();
+        conn.send_close_notify();
+        let _ = self.session.send_pending(conn);
+    }
+}
+impl<T: Transport> Write for TlsWriter<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut conn = self.session.conn.lock().unwrap();
+        let n = conn.writer().write(buf)?;
+        self.session.send_pending(conn)?;
+        Ok(n)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        let mut conn = self.session.conn.lock().unwrap();
+        conn.writer().flush()?;
+        self.session.send_pending(conn)
+    }
+}
+
+/*---------------------------------------------------------
+  FrameReader<R> - messages from a byte stream, cut with
+  P::decode
+  - end of stream before a whole header is PeerClosed,
+    within a message is a Framing error, as for
+    Rcvr::buf_recv_message
+*/
+#[derive(Debug)]
+pub(crate) struct FrameReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+}
+impl<R: Read> FrameReader<R> {
+    pub(crate) fn new(inner: R) -> FrameReader<R> {
+        FrameReader { inner, buf: Vec::new() }
+    }
+    /*-- part of a message has been read --*/
+    pub(crate) fn is_partial(&self) -> bool {
+        !self.buf.is_empty()
+    }
+    pub(crate) fn next<P: Rcvr<M>, M>(&mut self, max: usize) -> CommResult<M>
+    where M: Msg + Clone + Send + Default
+    {
+        let mut chunk = [0u8; 8 * 1024];
+        loop {
+            match P::decode_max(&self.buf, max) {
+                Ok(Some((msg, n))) => {
+                    self.buf.drain(..n);
+                    return Ok(msg);
+                }
+                Ok(None) => {}
+                /*-- skip the corrupted frame, in case it's dropped --*/
+                Err(CommError::ChecksumMismatch { expected, actual, size }) => {
+                    self.buf.drain(..size);
+                    return Err(CommError::ChecksumMismatch { expected, actual, size });
+                }
+                Err(e) => return Err(e),
+            }
+            let n = self.inner.read(&mut chunk)?;
+            if n == 0 {
+                if self.buf.len() < HEADER_SIZE {
+                    return Err(CommError::PeerClosed);
+                }
+                return Err(CommError::Framing(format!(
+                    "stream ended within message, {} bytes unread", self.buf.len()
+                )));
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+/*---------------------------------------------------------
+  TlsOutbox - a TLS client handler's writer, shared with
+  ListenerHandle::shutdown
+  - None until the handshake completes
+*/
+pub(crate) struct TlsOutbox<T: Transport> {
+    writer: Mutex<Option<BufWriter<TlsWriter<T>>>>,
+}
+impl<T: Transport> Debug for TlsOutbox<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.write_str("TlsOutbox")
+    }
+}
+impl<T: Transport> TlsOutbox<T> {
+    fn new() -> TlsOutbox<T> {
+        TlsOutbox { writer: Mutex::new(None) }
+    }
+    fn send(&self, bytes: &[u8]) {
+        if let Some(writer) = self.writer.lock().unwrap().as_mut() {
+            let _ = writer.write_all(bytes);
+            let _ = writer.flush();
+        }
+    }
+    fn close(&self) {
+        if let Some(writer) = self.writer.lock().unwrap().take() {
+            if let Ok(mut writer) = writer.into_inner() {
+                writer.close();
+            }
+        }
+    }
+}
+impl<T: Transport> Outbox for TlsOutbox<T> {
+    fn queue(&self, bytes: Vec<u8>) {
+        self.send(&bytes);
+    }
+}
+
+/*-- work item for TLS Listener pool threads, None stops a thread --*/
+type TlsJob<T> = Option<(
+    u64, <T as Transport>::PeerAddr, <T as Transport>::Reader,
+    <T as Transport>::Writer, Arc<TlsOutbox<T>>
+)>;
+
+/*---------------------------------------------------------
+  Start the accept thread and pool of a TLS Listener
+  - as for a plain Listener, but each connection's outbox
+    is its TlsOutbox, so SHUTDOWN is encrypted too
+*/
+pub(crate) fn start<P,M,L,T>(serving: Serving<P,T>, tls: TlsServerConfig) -> JoinHandle<Workers>
+where
+    M: Msg + Clone + Send + Default + 'static,
+    P: Clone + Send + Sndr<M> + Rcvr<M> + Process<M> + 'static,
+    L: Logger + 'static,
+    T: Transport
+{
+    let Serving { lsnr, run, conns, proc, file_dir, observer, nt } = serving;
+    thread::spawn(move || {
+        let pool_conns = Arc::clone(&conns);
+        let pool_observer = observer.clone();
+        let mut tp = ThreadPool::<TlsJob<T>>::new(nt, move |bq, _run| {
+            while let Some((id, peer, reader, writer, outbox)) = bq.de_q() {
+                let rslt = handle_tls_client::<P,M,L,T>(
+                    (reader, writer), T::socket_addr(&peer), &outbox,
+                    &tls, proc.clone(), file_dir.clone(), (&pool_conns, id)
+                );
+                let closed_by = pool_conns.remove(id);
+                report_close::<L,_>(peer, rslt, closed_by, &pool_observer);
+            }
+        });
+        accept_loop::<T,_>(&lsnr, &run, |strm, peer| {
+            let (reader, writer) = match T::split(&strm) {
+                Ok(halves) => halves,
+                Err(_) => return,
+            };
+            let outbox = Arc::new(TlsOutbox::<T>::new());
+            let id = conns.reserve_id();
+            conns.insert::<T>(id, strm, peer.clone(), outbox.clone());
+            if let Some(obs) = &observer {
+                obs.on_accept(peer.clone());
+            }
+            tp.post(Some((id, peer, reader, writer, outbox)));
+        });
+        for _ in 0..nt {
+            tp.post(None);
+        }
+        L::write("\n--terminating listener thread--");
+        Box::new(move || tp.wait()) as Workers
+    })
+}
+/*---------------------------------------------------------
+  Handle a TLS client, as handle_client does a plain one,
+  after the handshake
+*/
+fn handle_tls_client<P,M,L,T>(
+    (reader, writer): (T::Reader, T::Writer), peer: Option<SocketAddr>,
+    outbox: &TlsOutbox<T>, tls: &TlsServerConfig, mut proc: P, file_dir: Option<PathBuf>,
+    client: (&Connections, u64)
+) -> CommResult<CloseReason>
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M> + Rcvr<M> + Process<M>,
+    L: Logger,
+    T: Transport
+{
+    let mut ctx = ConnContext::new(peer);
+    ctx.client = Some(ClientId(client.1));
+    let (reader, writer) = tls.accept::<T>(reader, writer)?;
+    *outbox.writer.lock().unwrap() = Some(BufWriter::new(writer));
+    let activity = client.0.activity(client.1);
+    let policy = client.0.checksum_policy();
+    let max = client.0.max_msg_size();
+    let mut frames = FrameReader::new(Watched::new(reader, Arc::clone(&activity)));
+    let mut files = FileReceiver::new(file_dir);
+    let rslt = loop {
+        let msg: M = match frames.next::<P,M>(max) {
+            Ok(msg) => msg,
+            Err(CommError::PeerClosed) => break Ok(CloseReason::Dropped),
+            Err(e) if policy.skips(&e) => {
+                L::write(&format!("\n  dropped message: {}", e));
+                continue;
+            }
+            Err(e) => {
+                if let Some(reply) = protocol_error::<M>(&e) {
+                    outbox.send(&P::encode(&reply));
+                }
+                break Err(e);
+            }
+        };
+        activity.busy();
+        let handled = handle_message::<P,M,L>(msg, &mut proc, &mut ctx, &mut files, client);
+        activity.ready(frames.is_partial());
+        match handled {
+            Handled::Replies(replies) => {
+                let bytes: Vec<u8> = replies.iter().flat_map(|r| P::encode(r)).collect();
+                outbox.send(&bytes);
+            }
+            Handled::Close(reason) => break Ok(reason),
+        }
+    };
+    outbox.close();
+    L::write("\n  terminating TLS handler thread");
+    rslt
+}
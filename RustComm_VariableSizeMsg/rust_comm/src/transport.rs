@@ -0,0 +1,200 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::transport.rs - byte stream transports        //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Connector<P,M,L,T> and Listener<P,M,L,T> move bytes with
+   a Transport, T, that connects, accepts, and splits each
+   connection into reader and writer halves.  Sndr and Rcvr
+   frame messages on those halves.
+   - Tcp, the default, uses TcpStream and a dual-stack
+     TcpListener, see bind_listener
+   - Unix, on unix platforms, uses Unix domain sockets
+     named by paths, so same-host services skip the TCP
+     stack
+
+   ToTransportAddrs<T> turns what applications pass to
+   Connector::new and Listener::start into T's addresses,
+   e.g., "localhost:8080" for Tcp, or a socket path for
+   Unix.  A Unix Listener's socket file is left in place
+   when it shuts down, and binding fails while it exists.
+
+   Reactor mode, see reactor.rs, and Comm are TCP only.
+*/
+
+use crate::*;
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/*---------------------------------------------------------
+  Transport - how Connector and Listener reach their peers
+  - Addr is where to connect or listen, PeerAddr names
+    either end of a connection
+  - accept returns WouldBlock when nothing is pending, so
+    Listener's accept loop can see shutdown, and returns
+    streams that block
+  - shutdown closes both directions, waking any thread
+    blocked on the connection's halves
+*/
+pub trait Transport : Debug + Send + Sync + 'static {
+    type Addr: Clone + Debug + Send + Sync + 'static;
+    type PeerAddr: Clone + Debug + Send + Sync + 'static;
+    type Stream: Debug + Send + Sync + 'static;
+    type Reader: Read + Debug + Send + 'static;
+    type Writer: Write + Debug + Send + 'static;
+    type Listener: Debug + Send + 'static;
+
+    fn connect(addr: &Self::Addr) -> std::io::Result<Self::Stream>;
+    fn bind(addr: &Self::Addr) -> std::io::Result<Self::Listener>;
+    fn accept(lsnr: &Self::Listener) -> std::io::Result<(Self::Stream, Self::PeerAddr)>;
+    /*-- address lsnr is bound to --*/
+    fn listen_addr(lsnr: &Self::Listener) -> std::io::Result<Self::Addr>;
+    fn split(strm: &Self::Stream) -> std::io::Result<(Self::Reader, Self::Writer)>;
+    fn shutdown(strm: &Self::Stream) -> std::io::Result<()>;
+    fn peer_addr(strm: &Self::Stream) -> std::io::Result<Self::PeerAddr>;
+    fn local_addr(strm: &Self::Stream) -> std::io::Result<Self::PeerAddr>;
+    /*-- peer as ConnContext::peer, None if it has no socket address --*/
+    fn socket_addr(_peer: &Self::PeerAddr) -> Option<SocketAddr> {
+        None
+    }
+}
+/*-- addresses of transport T, tried in order --*/
+pub trait ToTransportAddrs<T: Transport> {
+    fn to_transport_addrs(&self) -> std::io::Result<Vec<T::Addr>>;
+}
+/*-- connect to the first of addrs that accepts --*/
+pub(crate) fn connect_any<T: Transport>(addrs: &[T::Addr]) -> std::io::Result<T::Stream> {
+    let mut last_err = std::io::Error::new(
+        std::io::ErrorKind::InvalidInput, "no addresses to connect to"
+    );
+    for addr in addrs {
+        match T::connect(addr) {
+            Ok(strm) => return Ok(strm),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(last_err)
+}
+/*-- bind to the first of addrs that succeeds --*/
+pub(crate) fn bind_any<T: Transport>(addrs: &[T::Addr]) -> std::io::Result<T::Listener> {
+    let mut last_err = std::io::Error::new(
+        std::io::ErrorKind::InvalidInput, "no addresses to bind"
+    );
+    for addr in addrs {
+        match T::bind(addr) {
+            Ok(lsnr) => return Ok(lsnr),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(last_err)
+}
+
+/*---------------------------------------------------------
+  Tcp - TCP/IP, the default Transport
+*/
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tcp;
+
+impl Transport for Tcp {
+    type Addr = SocketAddr;
+    type PeerAddr = SocketAddr;
+    type Stream = TcpStream;
+    type Reader = TcpStream;
+    type Writer = TcpStream;
+    type Listener = TcpListener;
+
+    fn connect(addr: &SocketAddr) -> std::io::Result<TcpStream> {
+        TcpStream::connect(addr)
+    }
+    fn bind(addr: &SocketAddr) -> std::io::Result<TcpListener> {
+        let tcpl = bind_listener(std::slice::from_ref(addr))?;
+        tcpl.set_nonblocking(true)?;
+        Ok(tcpl)
+    }
+    fn accept(lsnr: &TcpListener) -> std::io::Result<(TcpStream, SocketAddr)> {
+        let (strm, peer) = lsnr.accept()?;
+        strm.set_nonblocking(false)?;
+        Ok((strm, peer))
+    }
+    fn listen_addr(lsnr: &TcpListener) -> std::io::Result<SocketAddr> {
+        lsnr.local_addr()
+    }
+    fn split(strm: &TcpStream) -> std::io::Result<(TcpStream, TcpStream)> {
+        Ok((strm.try_clone()?, strm.try_clone()?))
+    }
+    fn shutdown(strm: &TcpStream) -> std::io::Result<()> {
+        strm.shutdown(Shutdown::Both)
+    }
+    fn peer_addr(strm: &TcpStream) -> std::io::Result<SocketAddr> {
+        strm.peer_addr()
+    }
+    fn local_addr(strm: &TcpStream) -> std::io::Result<SocketAddr> {
+        strm.local_addr()
+    }
+    fn socket_addr(peer: &SocketAddr) -> Option<SocketAddr> {
+        Some(*peer)
+    }
+}
+impl<A: ToSocketAddrs> ToTransportAddrs<Tcp> for A {
+    fn to_transport_addrs(&self) -> std::io::Result<Vec<SocketAddr>> {
+        Ok(self.to_socket_addrs()?.collect())
+    }
+}
+
+/*---------------------------------------------------------
+  Unix - Unix domain stream sockets, named by paths
+*/
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Unix;
+
+#[cfg(unix)]
+impl Transport for Unix {
+    type Addr = PathBuf;
+    type PeerAddr = std::os::unix::net::SocketAddr;
+    type Stream = UnixStream;
+    type Reader = UnixStream;
+    type Writer = UnixStream;
+    type Listener = UnixListener;
+
+    fn connect(addr: &PathBuf) -> std::io::Result<UnixStream> {
+        UnixStream::connect(addr)
+    }
+    fn bind(addr: &PathBuf) -> std::io::Result<UnixListener> {
+        let lsnr = UnixListener::bind(addr)?;
+        lsnr.set_nonblocking(true)?;
+        Ok(lsnr)
+    }
+    fn accept(lsnr: &UnixListener) -> std::io::Result<(UnixStream, Self::PeerAddr)> {
+        let (strm, peer) = lsnr.accept()?;
+        strm.set_nonblocking(false)?;
+        Ok((strm, peer))
+    }
+    fn listen_addr(lsnr: &UnixListener) -> std::io::Result<PathBuf> {
+        lsnr.local_addr()?
+            .as_pathname()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| std::io::Error::new(
+                std::io::ErrorKind::InvalidInput, "listener socket has no path"
+            ))
+    }
+    fn split(strm: &UnixStream) -> std::io::Result<(UnixStream, UnixStream)> {
+        Ok((strm.try_clone()?, strm.try_clone()?))
+    }
+    fn shutdown(strm: &UnixStream) -> std::io::Result<()> {
+        strm.shutdown(Shutdown::Both)
+    }
+    fn peer_addr(strm: &UnixStream) -> std::io::Result<Self::PeerAddr> {
+        strm.peer_addr()
+    }
+    fn local_addr(strm: &UnixStream) -> std::io::Result<Self::PeerAddr> {
+        strm.local_addr()
+    }
+}
+#[cfg(unix)]
+impl<A: AsRef<Path>> ToTransportAddrs<Unix> for A {
+    fn to_transport_addrs(&self) -> std::io::Result<Vec<PathBuf>> {
+        Ok(vec![self.as_ref().to_path_buf()])
+    }
+}
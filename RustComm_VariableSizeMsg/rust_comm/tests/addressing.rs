@@ -0,0 +1,51 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::addressing.rs - ephemeral ports, dual-stack  //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   A Listener bound to the IPv6 wildcard on port 0 serves
+   both IPv4 and IPv6 Connectors.  Addresses are built at
+   run time rather than with &'static str.
+*/
+
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+type P = CommProcessing<MuteLog>;
+
+fn echo(conn: &Connector<P,Message,MuteLog>, s: &str) -> String {
+    let mut msg = Message::create_msg_str_fit(s);
+    msg.set_type(MessageType::FLUSH as u8);
+    conn.post_message(msg);
+    conn.get_message().get_content_str().unwrap().to_string()
+}
+
+#[test]
+fn dual_stack_listener_on_ephemeral_port() {
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(2);
+    let handle = lsnr.start(String::from("[::]:0")).unwrap();
+    let port = lsnr.local_addr().unwrap().port();
+    assert_ne!(port, 0);
+
+    let v4 = Connector::<P,Message,MuteLog>::new((Ipv4Addr::LOCALHOST, port)).unwrap();
+    let v6 = Connector::<P,Message,MuteLog>::new(format!("[::1]:{}", port)).unwrap();
+    assert_eq!(echo(&v4, "over ipv4"), "over ipv4");
+    assert_eq!(echo(&v6, "over ipv6"), "over ipv6");
+    assert_eq!(v4.peer_addr().unwrap(), SocketAddr::from((Ipv4Addr::LOCALHOST, port)));
+    assert_eq!(v6.peer_addr().unwrap(), SocketAddr::from((Ipv6Addr::LOCALHOST, port)));
+    assert!(v6.local_addr().unwrap().is_ipv6());
+
+    for conn in [v4, v6].iter() {
+        let mut msg = Message::create_msg_header_only();
+        msg.set_type(MessageType::END as u8);
+        conn.post_message(msg);
+    }
+    let report = handle.shutdown(Duration::from_secs(1));
+    assert_eq!(report.forced, 0);
+}
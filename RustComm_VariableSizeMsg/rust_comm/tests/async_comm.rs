@@ -0,0 +1,169 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::async_comm.rs - async endpoints interoperate //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Run with: cargo test --features async
+
+   AsyncConnector and AsyncListener talk to each other and
+   to the blocking Connector and Listener, both refuse
+   messages over their maximum size, and a client that
+   never reads can't hold up AsyncListener shutdown.
+*/
+#![cfg(feature = "async")]
+
+use std::io::{BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+mod common;
+use common::*;
+
+type P = CommProcessing<MuteLog>;
+
+#[tokio::test]
+async fn async_connector_and_listener() {
+    let mut lsnr = AsyncListener::<P,Message,MuteLog>::new();
+    let handle = lsnr.start("127.0.0.1:0").await.unwrap();
+    let addr = lsnr.local_addr().unwrap();
+
+    let conns: Vec<AsyncConnector<P,Message,MuteLog>> = vec![
+        AsyncConnector::new(addr).await.unwrap(),
+        AsyncConnector::new(addr).await.unwrap(),
+    ];
+    for (i, conn) in conns.iter().enumerate() {
+        conn.post_message(flush_msg(&format!("task {}", i)));
+        conn.post_message(flush_msg("again"));
+    }
+    for (i, conn) in conns.iter().enumerate() {
+        let reply = conn.get_message().await.unwrap();
+        assert_eq!(reply.get_content_str().unwrap(), format!("task {}", i));
+        assert_eq!(reply.get_correlation_id(), 1);
+        let reply = conn.get_message().await.unwrap();
+        assert_eq!(reply.get_correlation_id(), 2);
+    }
+    assert_eq!(handle.connections(), 2);
+
+    /*-- one ends, the other answers SHUTDOWN --*/
+    conns[0].post_message(end_msg());
+    assert!(matches!(conns[0].get_message().await, Err(CommError::PeerClosed)));
+    let report = handle.shutdown(Duration::from_secs(1)).await;
+    assert_eq!(report, ShutdownReport { drained: 1, forced: 0 });
+    let msg = conns[1].get_message().await.unwrap();
+    assert_eq!(msg.get_type(), MessageType::SHUTDOWN as u8);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn async_and_blocking_interoperate() {
+    /*-- blocking Connector, AsyncListener --*/
+    let mut alsnr = AsyncListener::<P,Message,MuteLog>::new();
+    let ahandle = alsnr.start("127.0.0.1:0").await.unwrap();
+    let addr = alsnr.local_addr().unwrap();
+    let text = tokio::task::spawn_blocking(move || {
+        let conn = Connector::<P,Message,MuteLog>::new(addr).unwrap();
+        conn.post_message(flush_msg("blocking to async"));
+        let text = conn.get_message().get_content_str().unwrap().to_string();
+        conn.post_message(end_msg());
+        text
+    }).await.unwrap();
+    assert_eq!(text, "blocking to async");
+    assert_eq!(ahandle.shutdown(Duration::from_secs(1)).await.forced, 0);
+
+    /*-- AsyncConnector, blocking Listener --*/
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(2);
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    let conn = AsyncConnector::<P,Message,MuteLog>::new(lsnr.local_addr().unwrap()).await.unwrap();
+    conn.post_message(flush_msg("async to blocking"));
+    let reply = conn.get_message().await.unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), "async to blocking");
+    conn.post_message(end_msg());
+    let report = tokio::task::spawn_blocking(move || {
+        handle.shutdown(Duration::from_secs(1))
+    }).await.unwrap();
+    assert_eq!(report.forced, 0);
+}
+
+/*-- PROTOCOL_ERROR text from strm, then end of stream --*/
+fn protocol_error(strm: &mut TcpStream) -> String {
+    let mut reader = BufReader::new(strm);
+    let reply: Message = P::buf_recv_message(&mut reader).unwrap();
+    assert_eq!(reply.get_type(), MessageType::PROTOCOL_ERROR as u8);
+    assert_eq!(reader.read(&mut [0u8; 8]).unwrap(), 0);
+    reply.get_content_str().unwrap().to_string()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn oversize_messages_are_refused() {
+    let mut lsnr = AsyncListener::<P,Message,MuteLog>::new();
+    lsnr.set_max_msg_size(64);
+    assert_eq!(lsnr.get_max_msg_size(), 64);
+    let handle = lsnr.start("127.0.0.1:0").await.unwrap();
+    let addr = lsnr.local_addr().unwrap();
+    let text = tokio::task::spawn_blocking(move || {
+        let mut hostile = TcpStream::connect(addr).unwrap();
+        hostile.write_all(&claiming(1 << 40)).unwrap();
+        protocol_error(&mut hostile)
+    }).await.unwrap();
+    assert_eq!(text, "frame of 1099511627776 bytes exceeds maximum of 64");
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).await.forced, 0);
+
+    let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+    let conn = AsyncConnector::<P,Message,MuteLog>::new(tcpl.local_addr().unwrap()).await.unwrap();
+    conn.set_max_msg_size(16);
+    assert_eq!(conn.get_max_msg_size(), 16);
+    let (mut strm, _) = tcpl.accept().unwrap();
+    strm.write_all(&<P as Sndr<Message>>::encode(&flush_msg("sixteen bytes ok"))).unwrap();
+    let msg = conn.get_message().await.unwrap();
+    assert_eq!(msg.get_content_str().unwrap(), "sixteen bytes ok");
+    let text = tokio::task::spawn_blocking(move || {
+        strm.write_all(&claiming(17)).unwrap();
+        protocol_error(&mut strm)
+    }).await.unwrap();
+    assert_eq!(text, "frame of 17 bytes exceeds maximum of 16");
+    assert!(matches!(conn.get_message().await, Err(CommError::PeerClosed)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shutdown_is_bounded_by_a_client_that_never_reads() {
+    let mut lsnr = AsyncListener::<P,Message,MuteLog>::new();
+    let handle = lsnr.start("127.0.0.1:0").await.unwrap();
+    let addr = lsnr.local_addr().unwrap();
+
+    /*-- echoes fill the socket buffers, then the handler blocks writing --*/
+    let raw = TcpStream::connect(addr).unwrap();
+    let mut flood = raw.try_clone().unwrap();
+    let big = <P as Sndr<Message>>::encode(&flush_msg(&"x".repeat(1 << 20)));
+    let writer = std::thread::spawn(move || {
+        while flood.write_all(&big).is_ok() {}
+    });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let start = Instant::now();
+    let report = handle.shutdown(Duration::from_millis(300)).await;
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(report, ShutdownReport { drained: 0, forced: 1 });
+    /*-- fails harmlessly if the listener has already reset the connection --*/
+    let _ = raw.shutdown(std::net::Shutdown::Both);
+    writer.join().unwrap();
+}
+
+#[tokio::test]
+async fn shutdown_without_a_deadline_waits_for_clients() {
+    let mut lsnr = AsyncListener::<P,Message,MuteLog>::new();
+    let handle = lsnr.start("127.0.0.1:0").await.unwrap();
+    let conn = AsyncConnector::<P,Message,MuteLog>::new(lsnr.local_addr().unwrap()).await.unwrap();
+    conn.post_message(flush_msg("hello"));
+    assert_eq!(conn.get_message().await.unwrap().get_content_str().unwrap(), "hello");
+
+    /*-- Duration::MAX can't be added to now, so shutdown has no deadline --*/
+    let report = handle.shutdown(Duration::MAX).await;
+    assert_eq!(report, ShutdownReport { drained: 1, forced: 0 });
+    let msg = conn.get_message().await.unwrap();
+    assert_eq!(msg.get_type(), MessageType::SHUTDOWN as u8);
+}
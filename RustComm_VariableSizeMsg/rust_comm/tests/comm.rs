@@ -0,0 +1,75 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::comm.rs - peers exchange messages            //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Two Comm peers on loopback send and reply over the same
+   connection, stop ends accept even on a wildcard address,
+   and a receive limit is answered with PROTOCOL_ERROR.
+*/
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+mod common;
+use common::*;
+
+type C = Comm<CommProcessing<MuteLog>, Message, MuteLog>;
+
+#[test]
+fn peers_exchange_and_reply() {
+    let mut a = C::new();
+    let mut b = C::new();
+    let ha = a.start("127.0.0.1:0").unwrap();
+    let hb = b.start("127.0.0.1:0").unwrap();
+    let b_addr = b.local_addr().unwrap();
+
+    a.post_message(b_addr, flush_msg("hello b")).unwrap();
+    let (from, msg) = b.get_message();
+    assert_eq!(msg.get_content_str().unwrap(), "hello b");
+
+    /*-- reply goes back over the connection a opened --*/
+    b.post_message(from, flush_msg("hello a")).unwrap();
+    let (from, msg) = a.get_message();
+    assert_eq!(msg.get_content_str().unwrap(), "hello a");
+    assert_eq!(from, b_addr);
+    assert_eq!(a.peers().len(), 1);
+    assert_eq!(b.peers().len(), 1);
+
+    a.stop();
+    b.stop();
+    let _ = ha.join();
+    let _ = hb.join();
+}
+#[test]
+fn stop_ends_accept_on_a_wildcard_address() {
+    let mut c = C::new();
+    let handle = c.start("0.0.0.0:0").unwrap();
+    c.stop();
+    handle.join().unwrap();
+    assert!(c.peers().is_empty());
+}
+#[test]
+fn oversize_messages_are_refused() {
+    let mut a = C::new();
+    let b = C::new();
+    let ha = a.start("127.0.0.1:0").unwrap();
+    a.set_max_msg_size(16);
+    assert_eq!(a.get_max_msg_size(), 16);
+    let a_addr = a.local_addr().unwrap();
+
+    b.post_message(a_addr, flush_msg("small")).unwrap();
+    assert_eq!(a.get_message().1.get_content_str().unwrap(), "small");
+    b.post_message(a_addr, flush_msg("seventeen bytes!!")).unwrap();
+    let (from, msg) = b.get_message();
+    assert_eq!(from, a_addr);
+    assert_eq!(msg.get_type(), MessageType::PROTOCOL_ERROR as u8);
+    assert!(msg.get_content_str().unwrap().contains("17"));
+    assert!(!a.has_msg());
+
+    a.stop();
+    let _ = ha.join();
+}
@@ -0,0 +1,79 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::common - helpers shared by the tests         //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Each test file declares mod common and uses what it needs:
+   - flush_msg and end_msg build the messages most tests
+     send, FLUSH so Connectors send them at once
+   - claiming builds a hostile header, for size limits
+   - delegate_framing! gives a test processor the Sndr and
+     Rcvr impls of CommProcessing<MuteLog>, so it need only
+     implement Process
+*/
+#![allow(dead_code)]
+
+use rust_traits::*;
+use rust_message::*;
+
+pub fn flush_msg(s: &str) -> Message {
+    let mut msg = Message::create_msg_str_fit(s);
+    msg.set_type(MessageType::FLUSH as u8);
+    msg
+}
+pub fn end_msg() -> Message {
+    let mut msg = Message::create_msg_header_only();
+    msg.set_type(MessageType::END as u8);
+    msg
+}
+/*-- header of a FLUSH message announcing size bytes of body, without the body --*/
+pub fn claiming(size: usize) -> Vec<u8> {
+    let mut msg = Message::create_msg_header_only();
+    msg.set_type(MessageType::FLUSH as u8);
+    msg.set_content_size(size);
+    msg.get_ref().clone()
+}
+
+#[macro_export]
+macro_rules! delegate_framing {
+    ($proc:ty) => {
+        impl rust_traits::Sndr<rust_message::Message> for $proc {
+            fn send_message<W: std::io::Write>(
+                msg: &rust_message::Message, stream: &mut W
+            ) -> rust_traits::CommResult<()> {
+                <rust_comm_processing::CommProcessing<rust_comm_logger::MuteLog>
+                    as rust_traits::Sndr<rust_message::Message>>::send_message(msg, stream)
+            }
+            fn buf_send_message<W: std::io::Write>(
+                msg: &rust_message::Message, stream: &mut std::io::BufWriter<W>
+            ) -> rust_traits::CommResult<()> {
+                <rust_comm_processing::CommProcessing<rust_comm_logger::MuteLog>
+                    as rust_traits::Sndr<rust_message::Message>>::buf_send_message(msg, stream)
+            }
+            fn encode(msg: &rust_message::Message) -> Vec<u8> {
+                <rust_comm_processing::CommProcessing<rust_comm_logger::MuteLog>
+                    as rust_traits::Sndr<rust_message::Message>>::encode(msg)
+            }
+        }
+        impl rust_traits::Rcvr<rust_message::Message> for $proc {
+            fn recv_message_max<R: std::io::Read>(
+                stream: &mut R, max: usize
+            ) -> rust_traits::CommResult<rust_message::Message> {
+                <rust_comm_processing::CommProcessing<rust_comm_logger::MuteLog>
+                    as rust_traits::Rcvr<rust_message::Message>>::recv_message_max(stream, max)
+            }
+            fn buf_recv_message_max<R: std::io::Read>(
+                stream: &mut std::io::BufReader<R>, max: usize
+            ) -> rust_traits::CommResult<rust_message::Message> {
+                <rust_comm_processing::CommProcessing<rust_comm_logger::MuteLog>
+                    as rust_traits::Rcvr<rust_message::Message>>::buf_recv_message_max(stream, max)
+            }
+            fn decode_max(
+                buf: &[u8], max: usize
+            ) -> rust_traits::CommResult<Option<(rust_message::Message, usize)>> {
+                <rust_comm_processing::CommProcessing<rust_comm_logger::MuteLog>
+                    as rust_traits::Rcvr<rust_message::Message>>::decode_max(buf, max)
+            }
+        }
+    };
+}
@@ -0,0 +1,117 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::correlation.rs - replies find their request  //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Swapper holds each odd message and answers it after
+   the next one, so replies arrive out of order.  Each
+   ReplyTicket still gets the reply to its own request,
+   and timed receives give up rather than block.
+*/
+
+use std::time::Duration;
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+mod common;
+use common::*;
+
+type Frame = CommProcessing<MuteLog>;
+
+#[derive(Debug, Clone, Default)]
+struct Swapper {
+    held: Option<Message>,
+}
+delegate_framing!(Swapper);
+fn echo(msg: &Message) -> Message {
+    let mut reply = Message::create_msg_str_fit(msg.get_content_str().unwrap());
+    reply.set_type(MessageType::FLUSH as u8);
+    reply
+}
+impl Process<Message> for Swapper {
+    fn process_message(&mut self, msg: Message, _ctx: &mut ConnContext) -> Vec<Message> {
+        if msg.get_content_str() == Ok("silent") {
+            return Vec::new();
+        }
+        match self.held.take() {
+            None => {
+                self.held = Some(msg);
+                Vec::new()
+            }
+            Some(held) => {
+                /*-- msg's reply is correlated by handle_client --*/
+                let mut late = echo(&held);
+                late.set_correlation_id(held.get_msg_id());
+                vec![echo(&msg), late]
+            }
+        }
+    }
+}
+
+#[test]
+fn out_of_order_replies_reach_their_tickets() {
+    let mut lsnr = Listener::<Swapper,Message,MuteLog,Memory>::new(2);
+    let handle = lsnr.start("correlation").unwrap();
+    let conn = Connector::<Frame,Message,MuteLog,Memory>::new("correlation").unwrap();
+
+    let first = conn.request(flush_msg("first"));
+    let second = conn.request(flush_msg("second"));
+    assert_ne!(first.id(), second.id());
+    let reply = second.wait_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), "second");
+    assert_eq!(reply.get_correlation_id(), second.id());
+    let reply = first.wait_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), "first");
+    assert_eq!(reply.get_correlation_id(), first.id());
+
+    let silent = conn.request(flush_msg("silent"));
+    assert!(matches!(
+        silent.wait_timeout(Duration::from_millis(50)),
+        Err(CommError::Timeout)
+    ));
+    assert!(!conn.has_msg());
+
+    let mut msg = Message::create_msg_header_only();
+    msg.set_type(MessageType::END as u8);
+    conn.post_message(msg);
+    assert!(matches!(silent.wait(), Err(CommError::PeerClosed)));
+    let report = handle.shutdown(Duration::from_secs(1));
+    assert_eq!(report.forced, 0);
+}
+
+#[test]
+fn timed_receives_dont_block_forever() {
+    let mut lsnr = Listener::<Frame,Message,MuteLog,Memory>::new(1);
+    let handle = lsnr.start("timed_recv").unwrap();
+    let conn = Connector::<Frame,Message,MuteLog,Memory>::new("timed_recv").unwrap();
+
+    /*-- nothing has arrived --*/
+    assert!(conn.try_get_message().is_none());
+    assert!(matches!(
+        conn.get_message_timeout(Duration::from_millis(50)),
+        Err(CommError::Timeout)
+    ));
+
+    let reply = conn.send_and_wait(flush_msg("waited"), Duration::from_secs(5)).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), "waited");
+    /*-- too long to add to now, so waits without a deadline --*/
+    let reply = conn.send_and_wait(flush_msg("unbounded"), Duration::MAX).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), "unbounded");
+    conn.post_message(flush_msg("posted"));
+    let reply = conn.get_message_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), "posted");
+    assert!(conn.try_get_message().is_none());
+
+    let mut msg = Message::create_msg_header_only();
+    msg.set_type(MessageType::END as u8);
+    conn.post_message(msg);
+    assert!(matches!(
+        conn.send_and_wait(flush_msg("too late"), Duration::from_secs(5)),
+        Err(CommError::PeerClosed)
+    ));
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+}
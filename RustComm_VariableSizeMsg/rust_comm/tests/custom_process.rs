@@ -0,0 +1,67 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::custom_process.rs - Listener uses its P      //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Verifies that Listener<P,M,L> handles clients with the
+   processor it was given, not a built in default:
+   - Shouter replies twice to each message and counts
+     messages in state shared by all its clones
+*/
+
+use std::sync::Arc;
+use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+mod common;
+
+type Frame = CommProcessing<MuteLog>;
+
+#[derive(Debug, Clone, Default)]
+struct Shouter {
+    count: Arc<AtomicUsize>,
+}
+delegate_framing!(Shouter);
+impl Process<Message> for Shouter {
+    fn process_message(&mut self, msg: Message, ctx: &mut ConnContext) -> Vec<Message> {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        let text = msg.get_content_str().unwrap_or("").to_uppercase();
+        let mut first = Message::create_msg_str_fit(&text);
+        first.set_type(MessageType::REPLY as u8);
+        let mut second = Message::create_msg_str_fit(&format!("#{}", ctx.msg_count));
+        second.set_type(MessageType::FLUSH as u8);
+        vec![first, second]
+    }
+}
+
+#[test]
+fn listener_runs_custom_processor() {
+    let shouter = Shouter::default();
+    let count = Arc::clone(&shouter.count);
+    let mut lsnr = Listener::<Shouter,Message,MuteLog,Memory>::with_processor(2, shouter);
+    let handle = lsnr.start("custom_process").unwrap();
+
+    let conn = Connector::<Frame,Message,MuteLog,Memory>::new("custom_process").unwrap();
+    for (i, text) in ["hello", "world"].iter().enumerate() {
+        let mut msg = Message::create_msg_str_fit(text);
+        msg.set_type(MessageType::FLUSH as u8);
+        conn.post_message(msg);
+        let reply = conn.get_message();
+        assert_eq!(reply.get_content_str().unwrap(), text.to_uppercase());
+        let reply = conn.get_message();
+        assert_eq!(reply.get_content_str().unwrap(), format!("#{}", i + 1));
+    }
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+
+    let mut msg = Message::create_msg_header_only();
+    msg.set_type(MessageType::END as u8);
+    conn.post_message(msg);
+    let report = handle.shutdown(Duration::from_secs(1));
+    assert_eq!(report.forced, 0);
+}
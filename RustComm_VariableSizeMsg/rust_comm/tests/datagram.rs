@@ -0,0 +1,89 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::datagram.rs - messages over UDP              //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Datagrams on the loopback interface aren't lost or
+   reordered in practice, so loss and reordering are made
+   by sending hand numbered messages from an unsequenced
+   endpoint.
+*/
+
+use std::time::Duration;
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+type P = CommProcessing<MuteLog>;
+type Udp = Datagram<P,Message,MuteLog>;
+
+fn endpoint() -> Udp {
+    let udp = Udp::bind("127.0.0.1:0").unwrap();
+    udp.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    udp
+}
+fn text_msg(s: &str) -> Message {
+    let mut msg = Message::create_msg_str_fit(s);
+    msg.set_type(MessageType::TEXT as u8);
+    msg
+}
+
+#[test]
+fn datagrams_carry_messages() {
+    let (a, b) = (endpoint(), endpoint());
+    let mut msg = text_msg("over udp");
+    msg.set_correlation_id(42);
+    a.send_to(msg, b.local_addr().unwrap()).unwrap();
+    let (rcvd, src) = b.recv_from().unwrap();
+    assert_eq!(src, a.local_addr().unwrap());
+    assert_eq!(rcvd.get_type(), MessageType::TEXT as u8);
+    assert_eq!(rcvd.get_content_str().unwrap(), "over udp");
+    assert_eq!(rcvd.get_correlation_id(), 42);
+    assert_eq!(b.stats(src), None);
+
+    b.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+    assert!(matches!(b.recv_from(), Err(CommError::Timeout)));
+}
+
+#[test]
+fn oversize_messages_are_refused() {
+    let mut a = endpoint();
+    let b = endpoint();
+    let to = b.local_addr().unwrap();
+    let rslt = a.send_to(text_msg(&"x".repeat(MAX_DATAGRAM)), to);
+    assert!(matches!(rslt, Err(CommError::OversizeFrame { max: MAX_DATAGRAM, .. })));
+
+    a.set_max_datagram(1472);
+    a.send_to(text_msg(&"x".repeat(1472 - HEADER_SIZE)), to).unwrap();
+    let rslt = a.send_to(text_msg(&"x".repeat(1473 - HEADER_SIZE)), to);
+    assert!(matches!(rslt, Err(CommError::OversizeFrame { size: 1473, max: 1472 })));
+    assert_eq!(b.recv_from().unwrap().0.len(), 1472);
+}
+
+#[test]
+fn sequenced_receivers_count_loss_and_reordering() {
+    let mut a = endpoint();
+    let mut b = endpoint();
+    a.set_sequenced(true);
+    b.set_sequenced(true);
+    let (from, to) = (a.local_addr().unwrap(), b.local_addr().unwrap());
+    for i in 1..=3 {
+        a.send_to(text_msg("numbered"), to).unwrap();
+        assert_eq!(b.recv_from().unwrap().0.get_msg_id(), i);
+    }
+    assert_eq!(b.stats(from), Some(SequenceStats { received: 3, lost: 0, reordered: 0, highest: 3 }));
+
+    /*-- 4 is lost, 6 arrives before 5 --*/
+    let raw = endpoint();
+    for id in &[1, 2, 3, 6, 5, 7] {
+        let mut msg = text_msg("by hand");
+        msg.set_msg_id(*id);
+        raw.send_to(msg, to).unwrap();
+        b.recv_from().unwrap();
+    }
+    let stats = b.stats(raw.local_addr().unwrap()).unwrap();
+    assert_eq!(stats, SequenceStats { received: 6, lost: 1, reordered: 1, highest: 7 });
+}
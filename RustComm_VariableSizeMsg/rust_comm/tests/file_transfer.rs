@@ -0,0 +1,106 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::file_transfer.rs - files sent by Connector   //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   A file many windows long arrives intact even though END
+   is posted as soon as send_file returns, and sending it
+   again fails rather than replacing the stored copy, even
+   when two connections upload the same name at once.
+*/
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+mod common;
+use common::*;
+
+type P = CommProcessing<MuteLog>;
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("rust_comm_files_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn end_follows_the_file_and_nothing_is_replaced() {
+    let src = test_dir("src");
+    let dst = test_dir("dst");
+    let size = 3 * FILE_WINDOW * FILE_CHUNK_SIZE + 123;
+    let content: Vec<u8> = (0..size).map(|i| (i * 7 % 251) as u8).collect();
+    let path = src.join("big.bin");
+    fs::write(&path, &content).unwrap();
+
+    let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(1);
+    lsnr.set_file_dir(&dst);
+    let handle = lsnr.start("files").unwrap();
+
+    let conn = Connector::<P,Message,MuteLog,Memory>::new("files").unwrap();
+    let transfer = conn.send_file(&path).unwrap();
+    conn.post_message(end_msg());
+    assert_eq!(transfer.wait().unwrap(), size as u64);
+    assert_eq!(fs::read(dst.join("big.bin")).unwrap(), content);
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+
+    let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(1);
+    lsnr.set_file_dir(&dst);
+    let handle = lsnr.start("files_again").unwrap();
+    let conn = Connector::<P,Message,MuteLog,Memory>::new("files_again").unwrap();
+    fs::write(&path, b"replacement").unwrap();
+    let err = conn.send_file(&path).unwrap().wait().unwrap_err();
+    assert!(matches!(err, CommError::Refused(why) if why == "big.bin already exists"));
+    assert_eq!(fs::read(dst.join("big.bin")).unwrap(), content);
+    conn.post_message(end_msg());
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+
+    let _ = fs::remove_dir_all(&src);
+    let _ = fs::remove_dir_all(&dst);
+}
+
+#[test]
+fn concurrent_uploads_of_one_name_store_one_file() {
+    let srcs = [test_dir("race_a"), test_dir("race_b")];
+    let dst = test_dir("race_dst");
+    let size = 2 * FILE_WINDOW * FILE_CHUNK_SIZE;
+    let contents: Vec<Vec<u8>> = (0..2u8).map(|k| vec![b'a' + k; size]).collect();
+    for (src, content) in srcs.iter().zip(&contents) {
+        fs::write(src.join("same.bin"), content).unwrap();
+    }
+
+    let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(2);
+    lsnr.set_file_dir(&dst);
+    let handle = lsnr.start("files_race").unwrap();
+    let conns: Vec<_> = (0..2)
+        .map(|_| Connector::<P,Message,MuteLog,Memory>::new("files_race").unwrap())
+        .collect();
+    let transfers: Vec<_> = conns.iter().zip(&srcs)
+        .map(|(conn, src)| conn.send_file(src.join("same.bin")).unwrap())
+        .collect();
+    let results: Vec<_> = transfers.iter().map(|t| t.wait()).collect();
+
+    /*-- whichever finished first is stored, the other is refused --*/
+    let stored: Vec<usize> = (0..2).filter(|&k| results[k].is_ok()).collect();
+    assert_eq!(stored.len(), 1, "{:?}", results);
+    let refused = results[1 - stored[0]].as_ref().unwrap_err();
+    assert!(matches!(refused, CommError::Refused(why) if why == "same.bin already exists"));
+    assert_eq!(fs::read(dst.join("same.bin")).unwrap(), contents[stored[0]]);
+    assert_eq!(fs::read_dir(&dst).unwrap().count(), 1);
+
+    for conn in &conns {
+        conn.post_message(end_msg());
+    }
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+    for dir in srcs.iter().chain([&dst]) {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,153 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::heartbeat.rs - idle connection detection     //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Heartbeats keep quiet connections open, while peers
+   that stop answering, never send, or trickle a message
+   byte by byte are dropped.  Raw Memory and Tcp streams
+   play the misbehaving peers.
+*/
+
+use std::fmt::Debug;
+use std::io::{BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+type P = CommProcessing<MuteLog>;
+type Client = Connector<P,Message,MuteLog,Memory>;
+
+fn quick() -> Heartbeat {
+    Heartbeat { interval: Duration::from_millis(20), idle_timeout: Duration::from_millis(150) }
+}
+
+/*-- sends each closed connection's reason --*/
+struct Closes<A>(Mutex<Sender<CloseReason>>, std::marker::PhantomData<fn(A)>);
+impl<A> Closes<A> {
+    fn new() -> (Arc<Closes<A>>, Receiver<CloseReason>) {
+        let (tx, rx) = channel();
+        (Arc::new(Closes(Mutex::new(tx), std::marker::PhantomData)), rx)
+    }
+}
+impl<A: Debug> ConnectionObserver<A> for Closes<A> {
+    fn on_close(&self, _peer: A, reason: CloseReason) {
+        let _ = self.0.lock().unwrap().send(reason);
+    }
+}
+
+#[test]
+fn heartbeats_keep_quiet_connections_open() {
+    let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(2);
+    lsnr.set_heartbeat(quick());
+    let (closes, closed) = Closes::new();
+    lsnr.set_observer(closes);
+    let handle = lsnr.start("hb_quiet").unwrap();
+
+    /*-- pings both ways, answered by Connector and Listener --*/
+    let conn = Client::new("hb_quiet").unwrap();
+    conn.set_heartbeat(quick());
+    thread::sleep(Duration::from_millis(500));
+    assert!(conn.is_connected());
+    assert!(closed.try_recv().is_err());
+
+    /*-- heartbeats never reach get_message --*/
+    let mut msg = Message::create_msg_str_fit("still here");
+    msg.set_type(MessageType::FLUSH as u8);
+    let reply = conn.request(msg)
+        .wait_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), "still here");
+    assert!(!conn.has_msg());
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+    assert_eq!(closed.recv().unwrap(), CloseReason::End);
+}
+
+#[test]
+fn connector_drops_silent_listener() {
+    /*-- accepts, then never reads or answers --*/
+    let lsnr = Memory::bind(&"hb_silent".to_string()).unwrap();
+    let conn = Client::new("hb_silent").unwrap();
+    let (mut strm, _) = Memory::accept(&lsnr).unwrap();
+    conn.set_heartbeat(quick());
+
+    let start = Instant::now();
+    let ping: Message = P::buf_recv_message(&mut BufReader::new(&mut strm)).unwrap();
+    assert_eq!(ping.get_type(), MessageType::PING as u8);
+    assert_eq!(conn.next_state(), ConnectionState::Connected);
+    assert_eq!(conn.next_state(), ConnectionState::Disconnected);
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert!(!conn.is_connected());
+}
+
+#[test]
+fn listener_drops_idle_client() {
+    let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(2);
+    lsnr.set_heartbeat(quick());
+    let (closes, closed) = Closes::new();
+    lsnr.set_observer(closes);
+    let handle = lsnr.start("hb_idle").unwrap();
+
+    /*-- connects, then never sends, nor answers PING --*/
+    let mut idle = Memory::connect(&"hb_idle".to_string()).unwrap();
+    let ping: Message = P::buf_recv_message(&mut BufReader::new(&mut idle)).unwrap();
+    assert_eq!(ping.get_type(), MessageType::PING as u8);
+    let reason = closed.recv_timeout(Duration::from_secs(2)).unwrap();
+    assert_eq!(reason, CloseReason::TimedOut);
+    assert_eq!(idle.read(&mut [0u8; 8]).unwrap(), 0);
+    assert_eq!(handle.shutdown(Duration::from_secs(1)), ShutdownReport::default());
+}
+
+#[test]
+fn listener_drops_client_trickling_a_message() {
+    let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(2);
+    lsnr.set_frame_timeout(Duration::from_millis(100));
+    let (closes, closed) = Closes::new();
+    lsnr.set_observer(closes);
+    let handle = lsnr.start("hb_trickle").unwrap();
+
+    /*-- a whole message is fine, one byte every 10 ms is not --*/
+    let mut slow = Memory::connect(&"hb_trickle".to_string()).unwrap();
+    let msg = Message::create_msg_str_fit(&"x".repeat(1000));
+    let bytes = <P as Sndr<Message>>::encode(&msg);
+    slow.write_all(&bytes).unwrap();
+    let start = Instant::now();
+    for byte in &bytes {
+        if slow.write_all(std::slice::from_ref(byte)).is_err() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(start.elapsed() < Duration::from_secs(2));
+    let reason = closed.recv_timeout(Duration::from_secs(2)).unwrap();
+    assert_eq!(reason, CloseReason::TimedOut);
+    handle.shutdown(Duration::from_secs(1));
+}
+
+#[test]
+fn reactor_drops_idle_client() {
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(1);
+    lsnr.set_reactor(1);
+    lsnr.set_heartbeat(quick());
+    let (closes, closed) = Closes::new();
+    lsnr.set_observer(closes);
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    let addr = lsnr.local_addr().unwrap();
+
+    let conn = Connector::<P,Message,MuteLog>::new(addr).unwrap();
+    conn.set_heartbeat(quick());
+    let idle = TcpStream::connect(addr).unwrap();
+    let reason = closed.recv_timeout(Duration::from_secs(2)).unwrap();
+    assert_eq!(reason, CloseReason::TimedOut);
+    assert!(conn.is_connected());
+    drop(idle);
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+}
@@ -0,0 +1,128 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::observer.rs - connection lifecycle events    //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Clients that end cleanly, drop, send a truncated frame,
+   and outlast shutdown each produce their own events, in
+   the default and reactor modes.
+*/
+
+use std::io::Write;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+type P = CommProcessing<MuteLog>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Event {
+    Accept,
+    Close(CloseReason),
+    Error,
+}
+
+#[derive(Default)]
+struct Recorder {
+    events: Mutex<Vec<(SocketAddr, Event)>>,
+    cv: Condvar,
+}
+impl Recorder {
+    fn record(&self, peer: SocketAddr, event: Event) {
+        self.events.lock().unwrap().push((peer, event));
+        self.cv.notify_all();
+    }
+    /*-- events for peer, once its connection has closed --*/
+    fn closed(&self, peer: SocketAddr) -> Vec<Event> {
+        let mut events = self.events.lock().unwrap();
+        loop {
+            let mine: Vec<Event> = events.iter()
+                .filter(|(p, _)| *p == peer)
+                .map(|(_, e)| e.clone())
+                .collect();
+            if mine.iter().any(|e| matches!(e, Event::Close(_))) {
+                return mine;
+            }
+            events = self.cv.wait(events).unwrap();
+        }
+    }
+}
+impl ConnectionObserver for Recorder {
+    fn on_accept(&self, peer: SocketAddr) {
+        self.record(peer, Event::Accept);
+    }
+    fn on_close(&self, peer: SocketAddr, reason: CloseReason) {
+        self.record(peer, Event::Close(reason));
+    }
+    fn on_error(&self, peer: SocketAddr, err: &CommError) {
+        assert!(matches!(err, CommError::Framing(_)));
+        self.record(peer, Event::Error);
+    }
+}
+
+#[test]
+fn observer_sees_each_connection_close() {
+    check_events(Listener::new(4));
+}
+#[test]
+fn reactor_reports_the_same_events() {
+    let mut lsnr = Listener::new(1);
+    lsnr.set_reactor(1);
+    check_events(lsnr);
+}
+fn check_events(mut lsnr: Listener<P,Message,MuteLog>) {
+    let recorder = Arc::new(Recorder::default());
+    lsnr.set_observer(recorder.clone());
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    let addr = lsnr.local_addr().unwrap();
+
+    let conn = Connector::<P,Message,MuteLog>::new(addr).unwrap();
+    let polite = conn.local_addr().unwrap();
+    let mut msg = Message::create_msg_header_only();
+    msg.set_type(MessageType::END as u8);
+    conn.post_message(msg);
+    assert_eq!(
+        recorder.closed(polite),
+        vec![Event::Accept, Event::Close(CloseReason::End)]
+    );
+
+    let rude = TcpStream::connect(addr).unwrap();
+    let rude_addr = rude.local_addr().unwrap();
+    drop(rude);
+    assert_eq!(
+        recorder.closed(rude_addr),
+        vec![Event::Accept, Event::Close(CloseReason::Dropped)]
+    );
+
+    /*-- header promises 100 bytes of body, then the client leaves --*/
+    let mut broken = TcpStream::connect(addr).unwrap();
+    let broken_addr = broken.local_addr().unwrap();
+    let mut header = Message::create_msg_header_only();
+    header.set_type(MessageType::TEXT as u8);
+    header.set_content_size(100);
+    broken.write_all(header.get_ref()).unwrap();
+    drop(broken);
+    assert_eq!(
+        recorder.closed(broken_addr),
+        vec![Event::Accept, Event::Error, Event::Close(CloseReason::Error)]
+    );
+
+    /*-- never answers SHUTDOWN --*/
+    let stubborn = TcpStream::connect(addr).unwrap();
+    let stubborn_addr = stubborn.local_addr().unwrap();
+    while handle.connections() == 0 {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let report = handle.shutdown(Duration::from_millis(100));
+    assert_eq!(report.forced, 1);
+    assert_eq!(
+        recorder.closed(stubborn_addr),
+        vec![Event::Accept, Event::Close(CloseReason::Shutdown)]
+    );
+}
@@ -0,0 +1,114 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::pubsub.rs - Listener as a message bus        //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Connectors subscribe to topic patterns and publish
+   through a Listener, over the Memory transport.  A client
+   that stops reading doesn't hold up other subscribers.
+*/
+
+use std::io::{BufReader, Write};
+use std::time::Duration;
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+type P = CommProcessing<MuteLog>;
+type Client = Connector<P,Message,MuteLog,Memory>;
+
+fn subscribe(conn: &Client, pattern: &str) -> Message {
+    conn.request(subscribe_msg(pattern)).wait_timeout(Duration::from_secs(5)).unwrap()
+}
+fn next_publish(conn: &Client) -> (String, Vec<u8>) {
+    let msg = conn.get_message();
+    assert_eq!(msg.get_type(), MessageType::PUBLISH as u8);
+    (msg_topic(&msg).unwrap().to_string(), msg_payload(&msg).to_vec())
+}
+
+#[test]
+fn publishes_reach_matching_subscribers() {
+    let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(3);
+    let handle = lsnr.start("pubsub").unwrap();
+    let temps = Client::new("pubsub").unwrap();
+    let all = Client::new("pubsub").unwrap();
+    let publisher = Client::new("pubsub").unwrap();
+
+    let ack = subscribe(&temps, "sensors/+/temp");
+    assert_eq!(ack.get_type(), MessageType::SUBSCRIBE as u8);
+    assert_eq!(msg_topic(&ack), Some("sensors/+/temp"));
+    subscribe(&all, "sensors/#");
+
+    publisher.post_message(publish_msg("sensors/3/temp", b"21.5"));
+    publisher.post_message(publish_msg("sensors/3/humidity", b"40"));
+    publisher.post_message(publish_msg("elsewhere", b"ignored"));
+    assert_eq!(next_publish(&temps), ("sensors/3/temp".to_string(), b"21.5".to_vec()));
+    assert_eq!(next_publish(&all).0, "sensors/3/temp");
+    assert_eq!(next_publish(&all).0, "sensors/3/humidity");
+
+    /*-- after unsubscribing, all misses sensors/4/temp --*/
+    let ack = all.request(unsubscribe_msg("sensors/#"))
+        .wait_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(ack.get_type(), MessageType::UNSUBSCRIBE as u8);
+    publisher.post_message(publish_msg("sensors/4/temp", b"19"));
+    subscribe(&all, "elsewhere");
+    publisher.post_message(publish_msg("elsewhere", b"now seen"));
+    assert_eq!(next_publish(&temps).0, "sensors/4/temp");
+    assert_eq!(next_publish(&all), ("elsewhere".to_string(), b"now seen".to_vec()));
+
+    /*-- bad patterns and topics are answered with TOPIC_ERROR --*/
+    let err = subscribe(&all, "sensors/#/temp");
+    assert_eq!(err.get_type(), MessageType::TOPIC_ERROR as u8);
+    assert_eq!(msg_topic(&err), Some("sensors/#/temp"));
+    let err = publisher.request(publish_msg("sensors/+", b""))
+        .wait_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(err.get_type(), MessageType::TOPIC_ERROR as u8);
+
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+    assert_eq!(temps.get_message().get_type(), MessageType::SHUTDOWN as u8);
+    assert_eq!(all.get_message().get_type(), MessageType::SHUTDOWN as u8);
+}
+
+#[test]
+fn slow_subscriber_does_not_stall_others() {
+    let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(3);
+    let handle = lsnr.start("pubsub_slow").unwrap();
+
+    /*-- a raw client that subscribes, then never reads again --*/
+    let mut slow = Memory::connect(&"pubsub_slow".to_string()).unwrap();
+    slow.write_all(&<P as Sndr<Message>>::encode(&subscribe_msg("bulk"))).unwrap();
+    let ack: Message = P::buf_recv_message(&mut BufReader::new(&mut slow)).unwrap();
+    assert_eq!(ack.get_type(), MessageType::SUBSCRIBE as u8);
+
+    let fast = Client::new("pubsub_slow").unwrap();
+    subscribe(&fast, "bulk");
+    let publisher = Client::new("pubsub_slow").unwrap();
+    let payload = vec![7u8; 10_000];
+    for _ in 0..100 {
+        publisher.post_message(publish_msg("bulk", &payload));
+    }
+    for _ in 0..100 {
+        assert_eq!(next_publish(&fast).1.len(), payload.len());
+    }
+    let report = handle.shutdown(Duration::from_millis(200));
+    assert_eq!(report, ShutdownReport { drained: 2, forced: 1 });
+}
+
+#[test]
+fn reactor_listener_is_a_bus_too() {
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(1);
+    lsnr.set_reactor(1);
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    let addr = lsnr.local_addr().unwrap();
+    let sub = Connector::<P,Message,MuteLog>::new(addr).unwrap();
+    let publisher = Connector::<P,Message,MuteLog>::new(addr).unwrap();
+    sub.request(subscribe_msg("news")).wait_timeout(Duration::from_secs(5)).unwrap();
+    publisher.post_message(publish_msg("news", b"extra"));
+    let msg = sub.get_message();
+    assert_eq!(msg_topic(&msg), Some("news"));
+    assert_eq!(msg_payload(&msg), b"extra");
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+}
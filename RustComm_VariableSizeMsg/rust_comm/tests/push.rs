@@ -0,0 +1,149 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::push.rs - Listener initiated messages        //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   A Listener sends to its clients, by ClientId, without
+   waiting for a message to reply to:
+   - from the test thread, with send_to and broadcast
+   - from inside Process, where Chat relays each message
+     to every other client
+*/
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+mod common;
+
+type Frame = CommProcessing<MuteLog>;
+type Client = Connector<Frame,Message,MuteLog,Memory>;
+
+fn text(s: &str) -> Message {
+    let mut msg = Message::create_msg_str_fit(s);
+    msg.set_type(MessageType::TEXT as u8);
+    msg
+}
+/*-- clients are registered by the accept thread, so wait for them --*/
+fn wait_for_clients<P,M,T>(clients: &Clients<P,M,T>, n: usize) -> Vec<(ClientId, T::PeerAddr)>
+where
+    M: Msg + Clone + Send + Default,
+    P: Sndr<M>,
+    T: Transport
+{
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let list = clients.list();
+        if list.len() == n || Instant::now() > deadline {
+            return list;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn listener_pushes_to_clients_by_id() {
+    let mut lsnr = Listener::<Frame,Message,MuteLog,Memory>::new(3);
+    let handle = lsnr.start("push").unwrap();
+    let conns: Vec<Client> = (0..3).map(|_| Client::new("push").unwrap()).collect();
+
+    /*-- listed in order of connection, with each client's address --*/
+    let list = wait_for_clients(&lsnr.clients(), 3);
+    assert_eq!(lsnr.list_clients().len(), 3);
+    for (conn, (_, peer)) in conns.iter().zip(&list) {
+        assert_eq!(&conn.local_addr().unwrap(), peer);
+    }
+    let ids: Vec<ClientId> = list.iter().map(|(id, _)| *id).collect();
+
+    lsnr.send_to(ids[1], text("just you")).unwrap();
+    assert_eq!(conns[1].get_message().get_content_str().unwrap(), "just you");
+    assert_eq!(lsnr.broadcast(text("everyone")), 3);
+    for conn in &conns {
+        let msg = conn.get_message();
+        assert_eq!(msg.get_content_str().unwrap(), "everyone");
+        assert_eq!(msg.get_correlation_id(), 0);
+    }
+
+    /*-- a closed client is gone from the list --*/
+    let mut end = Message::create_msg_header_only();
+    end.set_type(MessageType::END as u8);
+    conns[0].post_message(end);
+    wait_for_clients(&lsnr.clients(), 2);
+    assert!(matches!(lsnr.send_to(ids[0], text("too late")), Err(CommError::PeerClosed)));
+    assert_eq!(lsnr.broadcast(text("still here")), 2);
+    for conn in &conns[1..] {
+        assert_eq!(conn.get_message().get_content_str().unwrap(), "still here");
+    }
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+}
+
+/*---------------------------------------------------------
+  Chat relays each FLUSH message, which Connectors send
+  at once, to every other client and doesn't reply to
+  the sender
+*/
+#[derive(Debug, Clone, Default)]
+struct Chat {
+    clients: Clients<Frame,Message,Memory>,
+}
+delegate_framing!(Chat);
+impl Process<Message> for Chat {
+    fn process_message(&mut self, msg: Message, ctx: &mut ConnContext) -> Vec<Message> {
+        if msg.get_type() != MessageType::FLUSH as u8 {
+            return Frame::default().process_message(msg, ctx);
+        }
+        let from = ctx.client.unwrap();
+        let line = format!("{}: {}", from, msg.get_content_str().unwrap_or(""));
+        for (id, _) in self.clients.list() {
+            if id != from {
+                let _ = self.clients.send_to(id, text(&line));
+            }
+        }
+        Vec::new()
+    }
+}
+
+#[test]
+fn process_pushes_to_other_clients() {
+    let say = |s: &str| {
+        let mut msg = text(s);
+        msg.set_type(MessageType::FLUSH as u8);
+        msg
+    };
+    let clients = Clients::new();
+    let chat = Chat { clients: clients.clone() };
+    let mut lsnr = Listener::<Chat,Message,MuteLog,Memory>::with_processor(3, chat);
+    lsnr.set_clients(&clients);
+    let handle = lsnr.start("push_chat").unwrap();
+    let alice = Client::new("push_chat").unwrap();
+    let bob = Client::new("push_chat").unwrap();
+    let list = wait_for_clients(&clients, 2);
+    assert_eq!(lsnr.list_clients().len(), 2);
+
+    alice.post_message(say("hi bob"));
+    let line = bob.get_message();
+    assert_eq!(line.get_content_str().unwrap(), format!("{}: hi bob", list[0].0));
+    bob.post_message(say("hi alice"));
+    let line = alice.get_message();
+    assert_eq!(line.get_content_str().unwrap(), format!("{}: hi alice", list[1].0));
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+}
+
+#[test]
+fn reactor_listener_pushes_too() {
+    let mut lsnr = Listener::<Frame,Message,MuteLog>::new(1);
+    lsnr.set_reactor(1);
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    let addr = lsnr.local_addr().unwrap();
+    let conn = Connector::<Frame,Message,MuteLog>::new(addr).unwrap();
+    let list = wait_for_clients(&lsnr.clients(), 1);
+    assert_eq!(list[0].1, conn.local_addr().unwrap());
+    lsnr.send_to(list[0].0, text("pushed")).unwrap();
+    assert_eq!(conn.get_message().get_content_str().unwrap(), "pushed");
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+}
@@ -0,0 +1,104 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::reactor.rs - more clients than pool threads  //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   A reactor mode Listener with two pool threads serves
+   fifty Connectors that stay connected, answering each
+   client's messages in order, then shuts down.  A client
+   that sends without reading replies is made to wait
+   rather than buffered without limit.
+*/
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+mod common;
+use common::*;
+
+type P = CommProcessing<MuteLog>;
+
+const CLIENTS: usize = 50;
+
+
+#[test]
+fn reactor_serves_more_clients_than_threads() {
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(2);
+    lsnr.set_reactor(1);
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    let addr = lsnr.local_addr().unwrap();
+
+    let conns: Vec<Connector<P,Message,MuteLog>> = (0..CLIENTS)
+        .map(|_| Connector::new(addr).unwrap())
+        .collect();
+    for (i, conn) in conns.iter().enumerate() {
+        for n in 0..3 {
+            conn.post_message(flush_msg(&format!("client {} msg {}", i, n)));
+        }
+    }
+    for (i, conn) in conns.iter().enumerate() {
+        for n in 0..3 {
+            let reply = conn.get_message();
+            assert_eq!(reply.get_content_str().unwrap(), format!("client {} msg {}", i, n));
+            assert_eq!(reply.get_correlation_id(), n + 1);
+        }
+    }
+    assert_eq!(handle.connections(), CLIENTS);
+
+    /*-- a truncated frame arrives in pieces, then never finishes --*/
+    let mut raw = TcpStream::connect(addr).unwrap();
+    let msg = flush_msg("partial");
+    raw.write_all(&msg.get_ref()[..HEADER_SIZE - 3]).unwrap();
+    raw.write_all(&msg.get_ref()[HEADER_SIZE - 3..HEADER_SIZE + 2]).unwrap();
+    while handle.connections() <= CLIENTS {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    /*-- Connectors answer SHUTDOWN with END, raw doesn't --*/
+    let report = handle.shutdown(Duration::from_millis(500));
+    assert_eq!(report, ShutdownReport { drained: CLIENTS, forced: 1 });
+    for conn in &conns {
+        assert_eq!(conn.get_message().get_type(), MessageType::SHUTDOWN as u8);
+    }
+}
+
+#[test]
+fn reactor_stops_reading_a_client_that_never_reads() {
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(2);
+    lsnr.set_reactor(1);
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    let addr = lsnr.local_addr().unwrap();
+
+    /*-- every message is echoed, the replies are never read --*/
+    let mut raw = TcpStream::connect(addr).unwrap();
+    raw.set_write_timeout(Some(Duration::from_millis(500))).unwrap();
+    let msg = flush_msg(&"x".repeat(64 * 1024));
+    let frame = msg.get_ref().clone();
+    let limit = 256 * 1024 * 1024;
+    let mut written = 0;
+    loop {
+        assert!(written < limit, "listener read {} bytes without pausing", written);
+        match raw.write(&frame) {
+            Ok(n) => written += n,
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                break;
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /*-- the client still gets its replies once it reads --*/
+    let mut reply = vec![0u8; frame.len()];
+    raw.read_exact(&mut reply).unwrap();
+    assert_eq!(reply[HEADER_SIZE..], frame[HEADER_SIZE..]);
+
+    let report = handle.shutdown(Duration::from_millis(200));
+    assert_eq!(report, ShutdownReport { drained: 0, forced: 1 });
+}
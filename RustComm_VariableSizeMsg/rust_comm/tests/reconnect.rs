@@ -0,0 +1,72 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::reconnect.rs - Connector survives restart    //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   A raw server answers one message then drops the
+   connection.  Messages posted while disconnected are
+   sent once a Listener is started on the same address.
+*/
+
+use std::io::{BufReader, BufWriter};
+use std::net::TcpListener;
+use std::time::Duration;
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+mod common;
+use common::*;
+
+type P = CommProcessing<MuteLog>;
+
+
+#[test]
+fn reconnects_and_sends_queued_messages() {
+    let tcpl = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = tcpl.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (strm, _) = tcpl.accept().unwrap();
+        let mut reader = BufReader::new(strm.try_clone().unwrap());
+        let mut writer = BufWriter::new(strm);
+        let msg: Message = P::buf_recv_message(&mut reader).unwrap();
+        P::buf_send_message(&msg, &mut writer).unwrap();
+    });
+
+    let policy = ReconnectPolicy {
+        initial_delay: Duration::from_millis(20),
+        max_delay: Duration::from_millis(100),
+        max_retries: 50,
+        ..ReconnectPolicy::default()
+    };
+    let conn = Connector::<P,Message,MuteLog>::with_reconnect(addr, policy).unwrap();
+    assert_eq!(conn.next_state(), ConnectionState::Connected);
+    conn.post_message(flush_msg("one"));
+    assert_eq!(conn.get_message().get_content_str().unwrap(), "one");
+    server.join().unwrap();
+    assert_eq!(conn.next_state(), ConnectionState::Disconnected);
+
+    /*-- queued while disconnected --*/
+    conn.post_message(flush_msg("two"));
+    conn.post_message(flush_msg("three"));
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(2);
+    let handle = lsnr.start(addr).unwrap();
+    loop {
+        match conn.next_state() {
+            ConnectionState::Connected => break,
+            ConnectionState::Connecting { .. } => {}
+            state => panic!("unexpected state {:?}", state),
+        }
+    }
+    assert_eq!(conn.get_message().get_content_str().unwrap(), "two");
+    assert_eq!(conn.get_message().get_content_str().unwrap(), "three");
+
+    let mut msg = Message::create_msg_header_only();
+    msg.set_type(MessageType::END as u8);
+    conn.post_message(msg);
+    let report = handle.shutdown(Duration::from_secs(1));
+    assert_eq!(report.forced, 0);
+}
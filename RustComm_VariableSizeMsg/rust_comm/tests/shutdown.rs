@@ -0,0 +1,96 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::shutdown.rs - graceful Listener shutdown     //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   One Connector answers the Listener's SHUTDOWN with END
+   and so drains.  A raw client that never reads ignores
+   it and is force closed at the deadline, even once its
+   handler is stuck writing replies it won't read.
+*/
+
+use std::io::{BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+mod common;
+use common::*;
+
+type P = CommProcessing<MuteLog>;
+
+
+#[test]
+fn shutdown_drains_and_forces() {
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(2);
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    let addr = lsnr.local_addr().unwrap();
+
+    /*-- round trips make sure both connections are being handled --*/
+    let conn = Connector::<P,Message,MuteLog>::new(addr).unwrap();
+    conn.post_message(flush_msg("polite"));
+    assert_eq!(conn.get_message().get_content_str().unwrap(), "polite");
+
+    let raw = TcpStream::connect(addr).unwrap();
+    let mut writer = BufWriter::new(raw.try_clone().unwrap());
+    let mut reader = BufReader::new(raw.try_clone().unwrap());
+    P::buf_send_message(&flush_msg("rude"), &mut writer).unwrap();
+    let reply: Message = P::buf_recv_message(&mut reader).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), "rude");
+    assert_eq!(handle.connections(), 2);
+
+    let start = Instant::now();
+    let report = handle.shutdown(Duration::from_millis(300));
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    assert_eq!(report, ShutdownReport { drained: 1, forced: 1 });
+
+    /*-- Connector sees SHUTDOWN, raw client sees it then EOF --*/
+    assert_eq!(conn.get_message().get_type(), MessageType::SHUTDOWN as u8);
+    let notice: Message = P::buf_recv_message(&mut reader).unwrap();
+    assert_eq!(notice.get_type(), MessageType::SHUTDOWN as u8);
+    let eof: CommResult<Message> = P::buf_recv_message(&mut reader);
+    assert!(matches!(eof, Err(CommError::PeerClosed)));
+}
+
+#[test]
+fn shutdown_is_bounded_by_a_client_that_never_reads() {
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(1);
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    let addr = lsnr.local_addr().unwrap();
+
+    /*-- echoes fill the socket buffers, then the handler blocks writing --*/
+    let raw = TcpStream::connect(addr).unwrap();
+    let mut flood = raw.try_clone().unwrap();
+    let big = <P as Sndr<Message>>::encode(&flush_msg(&"x".repeat(1 << 20)));
+    let writer = thread::spawn(move || {
+        while flood.write_all(&big).is_ok() {}
+    });
+    thread::sleep(Duration::from_millis(500));
+
+    let start = Instant::now();
+    let report = handle.shutdown(Duration::from_millis(300));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(report, ShutdownReport { drained: 0, forced: 1 });
+    drop(raw);
+    writer.join().unwrap();
+}
+
+#[test]
+fn shutdown_without_a_deadline_waits_for_clients() {
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(1);
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    let conn = Connector::<P,Message,MuteLog>::new(lsnr.local_addr().unwrap()).unwrap();
+    conn.post_message(flush_msg("hello"));
+    assert_eq!(conn.get_message().get_content_str().unwrap(), "hello");
+
+    /*-- Duration::MAX can't be added to now, so shutdown has no deadline --*/
+    let report = handle.shutdown(Duration::MAX);
+    assert_eq!(report, ShutdownReport { drained: 1, forced: 0 });
+    assert_eq!(conn.get_message().get_type(), MessageType::SHUTDOWN as u8);
+}
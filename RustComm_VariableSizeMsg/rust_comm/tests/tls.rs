@@ -0,0 +1,176 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::tls.rs - Connector and Listener over TLS     //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   Run with: cargo test --features tls
+
+   Certificates are made with rcgen: a test CA signs the
+   server's and a client's certificates.
+*/
+#![cfg(feature = "tls")]
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair};
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+mod common;
+use common::*;
+
+type P = CommProcessing<MuteLog>;
+
+struct Pki {
+    ca_pem: String,
+    server: (String, String),
+    client: (String, String),
+}
+fn signed(name: &str, ca: &Certificate, ca_key: &KeyPair) -> (String, String) {
+    let key = KeyPair::generate().unwrap();
+    let cert = CertificateParams::new(vec![name.to_string()]).unwrap()
+        .signed_by(&key, ca, ca_key).unwrap();
+    (cert.pem(), key.serialize_pem())
+}
+fn pki() -> Pki {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = params.self_signed(&ca_key).unwrap();
+    Pki {
+        ca_pem: ca.pem(),
+        server: signed("localhost", &ca, &ca_key),
+        client: signed("client", &ca, &ca_key),
+    }
+}
+fn identity(pair: &(String, String)) -> TlsIdentity {
+    TlsIdentity::from_pem(pair.0.as_bytes(), pair.1.as_bytes()).unwrap()
+}
+fn start(tls: TlsServerConfig) -> (Listener<P,Message,MuteLog>, ListenerHandle<P,Message,MuteLog>) {
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(2);
+    lsnr.set_tls(tls);
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    (lsnr, handle)
+}
+fn echo(conn: &Connector<P,Message,MuteLog>, text: &str) {
+    let reply = conn.request(flush_msg(text)).wait_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), text);
+}
+
+#[test]
+fn tls_with_roots_and_pinned() {
+    let pki = pki();
+    let (lsnr, handle) = start(TlsServerConfig::new(identity(&pki.server)).unwrap());
+    let addr = lsnr.local_addr().unwrap();
+
+    let roots = TlsClientConfig::new(pki.ca_pem.as_bytes(), "localhost", None).unwrap();
+    let conn = Connector::<P,Message,MuteLog>::with_tls(addr, roots, None).unwrap();
+    echo(&conn, "over tls");
+    echo(&conn, &"x".repeat(100_000));
+
+    /*-- pinning ignores the name and the CA --*/
+    let pinned = TlsClientConfig::pinned(pki.server.0.as_bytes(), "other", None).unwrap();
+    let pconn = Connector::<P,Message,MuteLog>::with_tls(addr, pinned, None).unwrap();
+    echo(&pconn, "pinned");
+
+    /*-- SHUTDOWN reaches a client over TLS --*/
+    conn.post_message(end_msg());
+    while conn.next_state() != ConnectionState::Disconnected {}
+    let report = handle.shutdown(Duration::from_secs(1));
+    assert_eq!(report, ShutdownReport { drained: 1, forced: 0 });
+    assert_eq!(pconn.get_message().get_type(), MessageType::SHUTDOWN as u8);
+}
+
+#[test]
+fn tls_rejects_wrong_server() {
+    let pki = pki();
+    let other = self::pki();
+    let (lsnr, handle) = start(TlsServerConfig::new(identity(&pki.server)).unwrap());
+    let addr = lsnr.local_addr().unwrap();
+
+    let wrong_roots = TlsClientConfig::new(other.ca_pem.as_bytes(), "localhost", None).unwrap();
+    let rslt = Connector::<P,Message,MuteLog>::with_tls(addr, wrong_roots, None);
+    assert!(matches!(rslt, Err(CommError::Tls(_))));
+    let wrong_name = TlsClientConfig::new(pki.ca_pem.as_bytes(), "elsewhere", None).unwrap();
+    let rslt = Connector::<P,Message,MuteLog>::with_tls(addr, wrong_name, None);
+    assert!(matches!(rslt, Err(CommError::Tls(_))));
+    let wrong_pin = TlsClientConfig::pinned(other.server.0.as_bytes(), "localhost", None).unwrap();
+    let rslt = Connector::<P,Message,MuteLog>::with_tls(addr, wrong_pin, None);
+    assert!(matches!(rslt, Err(CommError::Tls(_))));
+    handle.shutdown(Duration::from_secs(1));
+}
+
+#[derive(Default)]
+struct Errors(Mutex<Vec<String>>);
+impl ConnectionObserver for Errors {
+    fn on_error(&self, _peer: SocketAddr, err: &CommError) {
+        self.0.lock().unwrap().push(err.to_string());
+    }
+}
+
+#[test]
+fn mutual_tls() {
+    let pki = pki();
+    let tls = TlsServerConfig::with_client_auth(
+        identity(&pki.server), pki.ca_pem.as_bytes()
+    ).unwrap();
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(2);
+    lsnr.set_tls(tls);
+    let errors = Arc::new(Errors::default());
+    lsnr.set_observer(errors.clone());
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    let addr = lsnr.local_addr().unwrap();
+
+    let with_cert = TlsClientConfig::new(
+        pki.ca_pem.as_bytes(), "localhost", Some(identity(&pki.client))
+    ).unwrap();
+    let conn = Connector::<P,Message,MuteLog>::with_tls(addr, with_cert, None).unwrap();
+    echo(&conn, "mutual");
+    conn.post_message(end_msg());
+
+    /*-- a client without a certificate gets no replies --*/
+    let without = TlsClientConfig::new(pki.ca_pem.as_bytes(), "localhost", None).unwrap();
+    if let Ok(conn) = Connector::<P,Message,MuteLog>::with_tls(addr, without, None) {
+        let rslt = conn.request(flush_msg("anyone?")).wait_timeout(Duration::from_secs(5));
+        assert!(rslt.is_err());
+    }
+    let report = handle.shutdown(Duration::from_secs(1));
+    assert_eq!(report.forced, 0);
+    let errors = errors.0.lock().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].starts_with("tls error"));
+}
+
+#[test]
+fn tls_and_reactor_are_exclusive() {
+    let pki = pki();
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(2);
+    lsnr.set_tls(TlsServerConfig::new(identity(&pki.server)).unwrap());
+    lsnr.set_reactor(1);
+    assert!(matches!(lsnr.start("127.0.0.1:0"), Err(CommError::Io(_))));
+}
+
+#[cfg(unix)]
+#[test]
+fn tls_over_unix_socket() {
+    let pki = pki();
+    let path = std::env::temp_dir().join(format!("rust_comm_tls_{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut lsnr = Listener::<P,Message,MuteLog,Unix>::new(1);
+    lsnr.set_tls(TlsServerConfig::new(identity(&pki.server)).unwrap());
+    let handle = lsnr.start(&path).unwrap();
+
+    let tls = TlsClientConfig::new(pki.ca_pem.as_bytes(), "localhost", None).unwrap();
+    let conn = Connector::<P,Message,MuteLog,Unix>::with_tls(&path, tls, None).unwrap();
+    let reply = conn.request(flush_msg("tls over unix"))
+        .wait_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), "tls over unix");
+    conn.post_message(end_msg());
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+    std::fs::remove_file(&path).unwrap();
+}
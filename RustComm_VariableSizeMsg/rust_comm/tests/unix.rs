@@ -0,0 +1,70 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::unix.rs - Connector and Listener over Unix   //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   The Unix Transport carries the same messages as Tcp,
+   over a Unix domain socket named by a path.
+*/
+#![cfg(unix)]
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+mod common;
+use common::*;
+
+type P = CommProcessing<MuteLog>;
+
+fn socket_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rust_comm_{}_{}.sock", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[derive(Default)]
+struct Closes(Mutex<Vec<CloseReason>>);
+impl ConnectionObserver<std::os::unix::net::SocketAddr> for Closes {
+    fn on_close(&self, _peer: std::os::unix::net::SocketAddr, reason: CloseReason) {
+        self.0.lock().unwrap().push(reason);
+    }
+}
+
+#[test]
+fn connector_and_listener_over_unix_socket() {
+    let path = socket_path("echo");
+    let mut lsnr = Listener::<P,Message,MuteLog,Unix>::new(2);
+    let closes = Arc::new(Closes::default());
+    lsnr.set_observer(closes.clone());
+    let handle = lsnr.start(&path).unwrap();
+    assert_eq!(lsnr.local_addr(), Some(path.clone()));
+
+    let conn = Connector::<P,Message,MuteLog,Unix>::new(&path).unwrap();
+    let reply = conn.request(flush_msg("over unix")).wait_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), "over unix");
+    let big = "x".repeat(100_000);
+    conn.post_message(flush_msg(&big));
+    assert_eq!(conn.get_message().get_content_str().unwrap(), big);
+    conn.post_message(end_msg());
+    while conn.next_state() != ConnectionState::Disconnected {}
+
+    /*-- the second client is told to shut down --*/
+    let other = Connector::<P,Message,MuteLog,Unix>::new(&path).unwrap();
+    other.request(flush_msg("ping")).wait_timeout(Duration::from_secs(5)).unwrap();
+    let report = handle.shutdown(Duration::from_secs(1));
+    assert_eq!(report, ShutdownReport { drained: 1, forced: 0 });
+    assert_eq!(other.get_message().get_type(), MessageType::SHUTDOWN as u8);
+    assert_eq!(closes.0.lock().unwrap().len(), 2);
+
+    /*-- the socket file stays until removed --*/
+    let rslt = Listener::<P,Message,MuteLog,Unix>::new(1).start(&path);
+    assert!(matches!(rslt, Err(CommError::Bind(_))));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,239 @@
+/////////////////////////////////////////////////////////////
+// rust_comm::wire_format.rs - versioned message header    //
+//   - RustComm_VariableSizeMsg                            //
+/////////////////////////////////////////////////////////////
+/*
+   A client still framing messages with the legacy,
+   unversioned header is answered in the current format,
+   and a client sending bytes that aren't a frame at all
+   is answered with PROTOCOL_ERROR and disconnected, as is
+   one announcing a message over the maximum size.
+   Checksummed messages that arrive corrupted are dropped
+   or close their connection, as the receiver's
+   ChecksumPolicy says, and compressed ones are inflated.
+   Raw Memory and Tcp streams play the misbehaving peers.
+*/
+
+use std::io::{BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use rust_traits::*;
+use rust_message::*;
+use rust_comm_processing::*;
+use rust_comm_logger::*;
+use rust_comm::*;
+
+mod common;
+use common::*;
+
+type P = CommProcessing<MuteLog>;
+
+#[test]
+fn legacy_client_gets_current_replies() {
+    let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(1);
+    let handle = lsnr.start("wire_legacy").unwrap();
+    let mut old = Memory::connect(&"wire_legacy".to_string()).unwrap();
+
+    /*-- the original Message: type | usize content size --*/
+    let body = b"from before versioning";
+    let mut frame = vec![MessageType::FLUSH as u8];
+    frame.extend_from_slice(&body.len().to_be_bytes());
+    frame.extend_from_slice(body);
+    assert_eq!(frame.len(), 9 + body.len());
+    old.write_all(&frame).unwrap();
+
+    /*-- the echo comes back with the versioned header --*/
+    let mut wire = vec![0u8; HEADER_SIZE + body.len()];
+    old.read_exact(&mut wire).unwrap();
+    assert_eq!(wire[..MAGIC_SIZE], MAGIC);
+    assert_eq!(wire[VERSION_OFFSET], VERSION);
+    let (reply, _): (Message, usize) = P::decode(&wire).unwrap().unwrap();
+    assert_eq!(reply.get_content_bytes(), &body[..]);
+    assert_eq!(reply.get_correlation_id(), 0);
+    drop(old);
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+}
+
+#[test]
+fn garbage_is_disconnected() {
+    let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(1);
+    let handle = lsnr.start("wire_garbage").unwrap();
+    let mut rude = Memory::connect(&"wire_garbage".to_string()).unwrap();
+    rude.write_all(b"\xffGET / HTTP/1.1\r\n\r\n").unwrap();
+    let reply: Message = P::recv_message(&mut rude).unwrap();
+    assert_eq!(reply.get_type(), MessageType::PROTOCOL_ERROR as u8);
+    assert!(reply.get_content_str().unwrap().starts_with("bad magic ff47"));
+    assert_eq!(rude.read(&mut [0u8; 8]).unwrap(), 0);
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+}
+
+fn checked(s: &str) -> Message {
+    let mut msg = Message::create_msg_str_fit(s);
+    msg.set_type(MessageType::FLUSH as u8);
+    msg.set_flags(FLAG_CHECKSUM);
+    msg
+}
+/*-- msg's frame with one body bit flipped --*/
+fn corrupted(msg: &Message) -> Vec<u8> {
+    let mut wire = <P as Sndr<Message>>::encode(msg);
+    wire[HEADER_SIZE] ^= 0x01;
+    wire
+}
+
+#[test]
+fn checksummed_requests_get_checksummed_replies() {
+    let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(1);
+    let handle = lsnr.start("wire_checked").unwrap();
+    let conn = Connector::<P,Message,MuteLog,Memory>::new("wire_checked").unwrap();
+    conn.set_checksums(true);
+    let mut msg = Message::create_msg_str_fit("sum me");
+    msg.set_type(MessageType::FLUSH as u8);
+    let reply = conn.send_and_wait(msg, Duration::from_secs(5)).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), "sum me");
+    assert_eq!(reply.get_flags() & FLAG_CHECKSUM, FLAG_CHECKSUM);
+    drop(conn);
+    handle.shutdown(Duration::from_secs(1));
+}
+
+#[test]
+fn listener_drops_or_closes_on_corruption() {
+    for policy in [ChecksumPolicy::Drop, ChecksumPolicy::Close] {
+        let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(1);
+        lsnr.set_checksum_policy(policy);
+        let handle = lsnr.start("wire_corrupt").unwrap();
+        let mut client = Memory::connect(&"wire_corrupt".to_string()).unwrap();
+        client.write_all(&corrupted(&checked("lost"))).unwrap();
+        client.write_all(&<P as Sndr<Message>>::encode(&checked("kept"))).unwrap();
+        let rslt: CommResult<Message> = P::buf_recv_message(&mut BufReader::new(&mut client));
+        match policy {
+            ChecksumPolicy::Drop => assert_eq!(rslt.unwrap().get_content_str().unwrap(), "kept"),
+            ChecksumPolicy::Close => assert!(matches!(rslt, Err(CommError::PeerClosed))),
+        }
+        drop(client);
+        assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+    }
+}
+
+#[test]
+fn reactor_drops_corrupted_messages() {
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(1);
+    lsnr.set_reactor(1);
+    lsnr.set_checksum_policy(ChecksumPolicy::Drop);
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(lsnr.local_addr().unwrap()).unwrap();
+    let mut wire = corrupted(&checked("lost"));
+    wire.extend(<P as Sndr<Message>>::encode(&checked("kept")));
+    client.write_all(&wire).unwrap();
+    let reply: Message = P::buf_recv_message(&mut BufReader::new(&mut client)).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), "kept");
+    drop(client);
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+}
+
+#[test]
+fn connector_drops_or_closes_on_corruption() {
+    for policy in [ChecksumPolicy::Drop, ChecksumPolicy::Close] {
+        let lsnr = Memory::bind(&"wire_corrupt_reply".to_string()).unwrap();
+        let conn = Connector::<P,Message,MuteLog,Memory>::new("wire_corrupt_reply").unwrap();
+        conn.set_checksum_policy(policy);
+        let (mut strm, _) = Memory::accept(&lsnr).unwrap();
+        strm.write_all(&corrupted(&checked("lost"))).unwrap();
+        strm.write_all(&<P as Sndr<Message>>::encode(&checked("kept"))).unwrap();
+        match policy {
+            ChecksumPolicy::Drop => {
+                let msg = conn.get_message_timeout(Duration::from_secs(5)).unwrap();
+                assert_eq!(msg.get_content_str().unwrap(), "kept");
+            }
+            ChecksumPolicy::Close => {
+                assert_eq!(conn.next_state(), ConnectionState::Connected);
+                assert_eq!(conn.next_state(), ConnectionState::Disconnected);
+                assert!(conn.try_get_message().is_none());
+            }
+        }
+    }
+}
+
+#[test]
+fn compressed_requests_are_inflated() {
+    type Z = CommProcessing<MuteLog, Deflate<256>>;
+    let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(1);
+    let handle = lsnr.start("wire_compressed").unwrap();
+    let conn = Connector::<Z,Message,MuteLog,Memory>::new("wire_compressed").unwrap();
+    let text = "all work and no play\n".repeat(200);
+    let mut msg = Message::create_msg_str_fit(&text);
+    msg.set_type(MessageType::FLUSH as u8);
+    let wire = <Z as Sndr<Message>>::encode(&msg);
+    assert_eq!(wire[FLAGS_OFFSET] & FLAG_COMPRESSED, FLAG_COMPRESSED);
+    assert!(wire.len() < msg.len() / 10);
+
+    /*-- the Listener sees, and echoes, the original body --*/
+    let reply = conn.send_and_wait(msg, Duration::from_secs(5)).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), text);
+    assert_eq!(reply.get_flags() & FLAG_COMPRESSED, 0);
+    drop(conn);
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+}
+
+/*-- PROTOCOL_ERROR text from reader, then end of stream --*/
+fn protocol_error<R: Read>(reader: &mut BufReader<R>) -> String {
+    let reply: Message = P::buf_recv_message(reader).unwrap();
+    assert_eq!(reply.get_type(), MessageType::PROTOCOL_ERROR as u8);
+    assert_eq!(reader.read(&mut [0u8; 8]).unwrap(), 0);
+    reply.get_content_str().unwrap().to_string()
+}
+
+#[test]
+fn listener_refuses_oversize_messages() {
+    let mut lsnr = Listener::<P,Message,MuteLog,Memory>::new(2);
+    lsnr.set_max_msg_size(64);
+    assert_eq!(lsnr.get_max_msg_size(), 64);
+    let handle = lsnr.start("wire_oversize").unwrap();
+    let conn = Connector::<P,Message,MuteLog,Memory>::new("wire_oversize").unwrap();
+    let fits = "x".repeat(64);
+    let reply = conn.send_and_wait(flush_msg(&fits), Duration::from_secs(5)).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), fits);
+
+    /*-- no body follows, the Listener must answer from the header alone --*/
+    let mut hostile = Memory::connect(&"wire_oversize".to_string()).unwrap();
+    hostile.write_all(&claiming(1 << 40)).unwrap();
+    let text = protocol_error(&mut BufReader::new(&mut hostile));
+    assert_eq!(text, "frame of 1099511627776 bytes exceeds maximum of 64");
+    drop(conn);
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+}
+
+#[test]
+fn reactor_refuses_oversize_messages() {
+    let mut lsnr = Listener::<P,Message,MuteLog>::new(1);
+    lsnr.set_reactor(1);
+    lsnr.set_max_msg_size(64);
+    let handle = lsnr.start("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(lsnr.local_addr().unwrap()).unwrap();
+    let mut wire = <P as Sndr<Message>>::encode(&flush_msg("first"));
+    wire.extend(claiming(usize::MAX));
+    client.write_all(&wire).unwrap();
+    let mut reader = BufReader::new(&mut client);
+    let reply: Message = P::buf_recv_message(&mut reader).unwrap();
+    assert_eq!(reply.get_content_str().unwrap(), "first");
+    assert!(protocol_error(&mut reader).contains("exceeds maximum of 64"));
+    drop(reader);
+    drop(client);
+    assert_eq!(handle.shutdown(Duration::from_secs(1)).forced, 0);
+}
+
+#[test]
+fn connector_refuses_oversize_messages() {
+    let lsnr = Memory::bind(&"wire_oversize_reply".to_string()).unwrap();
+    let conn = Connector::<P,Message,MuteLog,Memory>::new("wire_oversize_reply").unwrap();
+    conn.set_max_msg_size(16);
+    let (mut strm, _) = Memory::accept(&lsnr).unwrap();
+    strm.write_all(&<P as Sndr<Message>>::encode(&flush_msg("sixteen bytes ok"))).unwrap();
+    let msg = conn.get_message_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(msg.get_content_str().unwrap(), "sixteen bytes ok");
+    strm.write_all(&claiming(17)).unwrap();
+    let text = protocol_error(&mut BufReader::new(&mut strm));
+    assert_eq!(text, "frame of 17 bytes exceeds maximum of 16");
+    assert_eq!(conn.next_state(), ConnectionState::Connected);
+    assert_eq!(conn.next_state(), ConnectionState::Disconnected);
+}
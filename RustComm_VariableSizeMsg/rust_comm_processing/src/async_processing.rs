@@ -0,0 +1,116 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::async_processing.rs               //
+//   - async send, recv, and process for CommProcessing    //
+/////////////////////////////////////////////////////////////
+/*
+   AsyncSndr, AsyncRcvr, and AsyncProcess for
+   CommProcessing<L>, built with the async feature.
+   Framing, flushing, and processing are those of the
+   blocking implementations in lib.rs, so messages written
+   by either are read by both.
+*/
+
+use super::*;
+use std::future::Future;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+impl<M,L,Z> AsyncSndr<M> for CommProcessing<L,Z>
+where 
+    M: Msg + Clone + Send + Sync + Default,
+    L: Logger + Debug + Copy + Clone + Default,
+    Z: Compression
+{
+    async fn async_send_message<W>(msg: &M, stream: &mut W) -> CommResult<()>
+    where W: AsyncWrite + Unpin + Send
+    {
+        L::write(&format!("\n  msg.len(): {}", msg.len()));
+        let wire = wire_bytes::<M,Z>(msg);
+        stream.write_all(&wire).await?;
+        if let Some(sum) = trailer(msg, &wire) {
+            stream.write_all(&sum).await?;
+        }
+        if is_flushed(msg.get_type()) {
+            L::write("\n  flushing stream");
+            let _ = stream.flush().await;
+        }
+        Ok(())
+    }
+}
+impl<M,L,Z> AsyncRcvr<M> for CommProcessing<L,Z>
+where 
+    M: Msg + Clone + Send + Sync + Default,
+    L: Logger + Debug + Copy + Clone + Default,
+    Z: Compression
+{
+    /*-- header then body, with the errors of read_message --*/
+    async fn async_recv_message_max<R>(stream: &mut R, max: usize) -> CommResult<M>
+    where R: AsyncRead + Unpin + Send
+    {
+        L::write("\n  attempting to receive msg in commProc");
+        let buf = &mut [0u8; HEADER_SIZE];
+        stream.read_exact(&mut buf[..PREFIX_SIZE]).await?;
+        let format = Format::of(&buf[..])?;
+        let hdrsz = format.header_size();
+        stream.read_exact(&mut buf[PREFIX_SIZE..hdrsz]).await?;
+        let header = format.parse(&buf[..hdrsz], max)?;
+        let mut bdy = vec![0u8;header.size];
+        stream.read_exact(&mut bdy).await.map_err(|e| body_error(e, header.size))?;
+        if header.is_checked() {
+            let sum = &mut [0u8; CHECKSUM_SIZE];
+            stream.read_exact(sum).await.map_err(|e| body_error(e, header.size))?;
+            verify(&buf[..hdrsz], &bdy, sum, hdrsz + header.size + CHECKSUM_SIZE)?;
+        }
+        header.assemble(&bdy, max)
+    }
+}
+impl<M,L,Z> AsyncProcess<M> for CommProcessing<L,Z>
+where 
+    M: Msg + Clone + Send + Sync + Default,
+    L: Logger + Debug + Copy + Clone + Default,
+    Z: Compression
+{
+    /*-- echo, as Process<M>, nothing to await --*/
+    fn async_process_message(
+        &mut self, msg: M, ctx: &mut ConnContext
+    ) -> impl Future<Output = Vec<M>> + Send {
+        std::future::ready(Process::process_message(self, msg, ctx))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    type P = CommProcessing<MuteLog>;
+
+    #[tokio::test]
+    async fn async_framing_matches_blocking() {
+        let mut msg = Message::create_msg_str_fit("both ways");
+        msg.set_msg_id(7);
+        let mut wire = Vec::new();
+        P::async_send_message(&msg, &mut wire).await.unwrap();
+        assert_eq!(wire, msg.get_ref().clone());
+
+        let back: Message = read_message(&mut &wire[..], DEFAULT_MAX_MSG_SIZE).unwrap();
+        assert_eq!(back.get_content_str().unwrap(), "both ways");
+        let back: Message = P::async_recv_message(&mut &wire[..]).await.unwrap();
+        assert_eq!(back.get_msg_id(), 7);
+
+        let rslt: CommResult<Message> =
+            P::async_recv_message(&mut &wire[..HEADER_SIZE + 2]).await;
+        assert!(matches!(rslt, Err(CommError::Framing(_))));
+
+        let mut garbage = wire.clone();
+        garbage[1] = b'X';
+        let rslt: CommResult<Message> = P::async_recv_message(&mut &garbage[..]).await;
+        assert!(matches!(rslt, Err(CommError::BadMagic([0xA5, b'X']))));
+
+        msg.set_flags(FLAG_CHECKSUM);
+        let mut wire = Vec::new();
+        P::async_send_message(&msg, &mut wire).await.unwrap();
+        assert_eq!(wire, <P as Sndr<Message>>::encode(&msg));
+        let back: Message = P::async_recv_message(&mut &wire[..]).await.unwrap();
+        assert_eq!(back.get_ref(), msg.get_ref());
+        *wire.last_mut().unwrap() ^= 1;
+        let rslt: CommResult<Message> = P::async_recv_message(&mut &wire[..]).await;
+        assert!(matches!(rslt, Err(CommError::ChecksumMismatch { .. })));
+    }
+}
@@ -0,0 +1,194 @@
+/////////////////////////////////////////////////////////////
+// rust_comm_processing::compression.rs                    //
+//   - compressed message bodies                           //
+/////////////////////////////////////////////////////////////
+/*
+   CommProcessing<L,Z> compresses the bodies of messages it
+   sends when Z says to:
+   - NoCompression, the default, never does
+   - Deflate<MIN> deflates bodies of MIN bytes or more,
+     unless that doesn't make them smaller, and Deflate
+     alone uses DEFAULT_DEFLATE_THRESHOLD
+
+   The threshold is part of each endpoint's P, so endpoints
+   in one process may compress differently.
+
+   A compressed body is sent with FLAG_COMPRESSED set, as
+   its original size, a big-endian u64, then the deflate
+   stream.  Every CommProcessing inflates such bodies when
+   receiving, whatever its Z, and clears the flag, so
+   get_content_bytes always returns the body as sent.  The
+   receiver's max applies to the inflated body too, so a
+   small frame can't inflate past it.
+*/
+
+use super::*;
+use std::borrow::Cow;
+
+/*---------------------------------------------------------
+  Compression - when a sender compresses
+*/
+pub trait Compression : Debug + Copy + Clone + Default + Send + Sync {
+    /*-- smallest body compressed, None for none --*/
+    fn threshold() -> Option<usize>;
+}
+#[derive(Debug, Copy, Clone, Default)]
+pub struct NoCompression;
+impl Compression for NoCompression {
+    fn threshold() -> Option<usize> {
+        None
+    }
+}
+
+/*-- smaller bodies rarely deflate enough to pay for it --*/
+pub const DEFAULT_DEFLATE_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Deflate<const MIN: usize = DEFAULT_DEFLATE_THRESHOLD>;
+impl<const MIN: usize> Compression for Deflate<MIN> {
+    fn threshold() -> Option<usize> {
+        Some(MIN)
+    }
+}
+
+const ORIGINAL_SIZE: usize = 8;
+const LEVEL: u8 = 6;
+
+/*---------------------------------------------------------
+  Header and body of msg as sent
+  - borrowed from msg unless its body is compressed
+*/
+pub(crate) fn wire_bytes<M: Msg, Z: Compression>(msg: &M) -> Cow<'_, [u8]> {
+    let size = msg.get_content_size();
+    let packed = match Z::threshold() {
+        Some(min) if size >= min => deflate(msg.get_content_bytes()),
+        _ => None,
+    };
+    match packed {
+        Some(bdy) => {
+            let mut bytes = msg.get_ref()[..HEADER_SIZE].to_vec();
+            bytes[FLAGS_OFFSET] |= FLAG_COMPRESSED;
+            bytes[CONTENT_SIZE_OFFSET..MSG_ID_OFFSET]
+                .copy_from_slice(&(bdy.len() as u64).to_be_bytes());
+            bytes.extend_from_slice(&bdy);
+            Cow::Owned(bytes)
+        }
+        /*-- the flag only ever describes bytes on the wire --*/
+        None if msg.get_flags() & FLAG_COMPRESSED != 0 => {
+            let mut bytes = msg.get_ref().clone();
+            bytes[FLAGS_OFFSET] &= !FLAG_COMPRESSED;
+            Cow::Owned(bytes)
+        }
+        None => Cow::Borrowed(&msg.get_ref()[..]),
+    }
+}
+/*-- compressed body, None if no smaller --*/
+fn deflate(bdy: &[u8]) -> Option<Vec<u8>> {
+    let mut packed = (bdy.len() as u64).to_be_bytes().to_vec();
+    packed.extend(miniz_oxide::deflate::compress_to_vec(bdy, LEVEL));
+    match packed.len() < bdy.len() {
+        true => Some(packed),
+        false => None,
+    }
+}
+/*-- body of a FLAG_COMPRESSED frame, never larger than it claims or max --*/
+pub(crate) fn inflate(packed: &[u8], max: usize) -> CommResult<Vec<u8>> {
+    if packed.len() < ORIGINAL_SIZE {
+        return Err(CommError::Framing("compressed body too short".to_string()));
+    }
+    let size = content_size(u64_at(packed, 0))?;
+    if size > max {
+        return Err(CommError::OversizeFrame { size, max });
+    }
+    let bdy = miniz_oxide::inflate::decompress_to_vec_with_limit(&packed[ORIGINAL_SIZE..], size)
+        .map_err(|e| CommError::Framing(format!("corrupt compressed body: {}", e)))?;
+    if bdy.len() != size {
+        return Err(CommError::Framing(format!(
+            "compressed body inflated to {} bytes, expected {}", bdy.len(), size
+        )));
+    }
+    Ok(bdy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    type Z = CommProcessing<MuteLog, Deflate<64>>;
+
+    fn text(n: usize) -> Message {
+        let line = "{\"level\":\"info\",\"msg\":\"request served\"}\n";
+        Message::create_msg_str_fit(&line.repeat(n / line.len() + 1)[..n])
+    }
+    #[test]
+    fn large_bodies_are_compressed_and_restored() {
+        let mut msg = text(4000);
+        msg.set_msg_id(3);
+        let wire = <Z as Sndr<Message>>::encode(&msg);
+        assert!(wire.len() < msg.len() / 4);
+        assert_eq!(wire[FLAGS_OFFSET] & FLAG_COMPRESSED, FLAG_COMPRESSED);
+
+        /*-- any CommProcessing reads it, by stream or decode --*/
+        let back: Message = CommProcessing::<MuteLog>::recv_message(&mut &wire[..]).unwrap();
+        assert_eq!(back.get_ref(), msg.get_ref());
+        let (back, used): (Message, usize) = Z::decode(&wire).unwrap().unwrap();
+        assert_eq!(used, wire.len());
+        assert_eq!(back.get_content_bytes(), msg.get_content_bytes());
+        assert_eq!(back.get_flags(), 0);
+    }
+    #[test]
+    fn small_or_incompressible_bodies_are_not() {
+        let msg = text(63);
+        assert_eq!(<Z as Sndr<Message>>::encode(&msg), msg.get_ref().clone());
+        let msg = text(64);
+        assert_eq!(<Z as Sndr<Message>>::encode(&msg)[FLAGS_OFFSET], FLAG_COMPRESSED);
+
+        /*-- Deflate alone waits for DEFAULT_DEFLATE_THRESHOLD --*/
+        type D = CommProcessing<MuteLog, Deflate>;
+        let msg = text(DEFAULT_DEFLATE_THRESHOLD - 1);
+        assert_eq!(<D as Sndr<Message>>::encode(&msg), msg.get_ref().clone());
+        let msg = text(DEFAULT_DEFLATE_THRESHOLD);
+        assert_eq!(<D as Sndr<Message>>::encode(&msg)[FLAGS_OFFSET], FLAG_COMPRESSED);
+        let mut x = 0x2545_f491_4f6c_dd1du64;
+        let noise: Vec<u8> = (0..1000).map(|_| {
+            x ^= x << 13; x ^= x >> 7; x ^= x << 17;
+            (x >> 24) as u8
+        }).collect();
+        let msg = Message::create_msg_bytes_fit(&noise);
+        assert_eq!(<Z as Sndr<Message>>::encode(&msg), msg.get_ref().clone());
+    }
+    #[test]
+    fn checksum_covers_compressed_frame() {
+        let mut msg = text(2000);
+        msg.set_flags(FLAG_CHECKSUM);
+        let mut wire = Vec::new();
+        Z::send_message(&msg, &mut wire).unwrap();
+        assert_eq!(wire, <Z as Sndr<Message>>::encode(&msg));
+        let back: Message = Z::recv_message(&mut &wire[..]).unwrap();
+        assert_eq!(back.get_content_bytes(), msg.get_content_bytes());
+        assert_eq!(back.get_flags(), FLAG_CHECKSUM);
+        wire[HEADER_SIZE + ORIGINAL_SIZE] ^= 0x40;
+        let rslt: CommResult<Message> = Z::recv_message(&mut &wire[..]);
+        assert!(matches!(rslt, Err(CommError::ChecksumMismatch { .. })));
+    }
+    #[test]
+    fn lying_original_size_is_a_framing_error() {
+        let wire = <Z as Sndr<Message>>::encode(&text(4000));
+        for size in [3999u64, 4001] {
+            let mut bad = wire.clone();
+            bad[HEADER_SIZE..HEADER_SIZE + ORIGINAL_SIZE].copy_from_slice(&size.to_be_bytes());
+            let rslt: CommResult<Message> = Z::recv_message(&mut &bad[..]);
+            assert!(matches!(rslt, Err(CommError::Framing(_))));
+        }
+    }
+    #[test]
+    fn inflating_past_max_is_oversize() {
+        let wire = <Z as Sndr<Message>>::encode(&text(4000));
+        assert!(wire.len() - HEADER_SIZE < 1000);
+        let rslt: CommResult<Message> = Z::recv_message_max(&mut &wire[..], 1000);
+        assert!(matches!(rslt, Err(CommError::OversizeFrame { size: 4000, max: 1000 })));
+        let mut bomb = wire.clone();
+        bomb[HEADER_SIZE..HEADER_SIZE + ORIGINAL_SIZE].copy_from_slice(&u64::MAX.to_be_bytes());
+        let rslt: CommResult<Option<(Message, usize)>> = Z::decode(&bomb);
+        assert!(matches!(rslt, Err(CommError::OversizeFrame { .. })));
+    }
+}
@@ -4,15 +4,31 @@
 // Jim Fawcett, https://JimFawcett.github.io, 19 Jul 2020  //
 /////////////////////////////////////////////////////////////
 /*
-   CommProcessing<L>:
+   CommProcessing<L,Z>:
    - defines send_message, recv_message, and process_message
    - each of these needs to be tailored to the specifics of
      the Message class
+   - sends the version 2 Message header, and reads it,
+     the version 1 header, or the legacy unversioned
+     header, see Format below
+   - messages with FLAG_CHECKSUM set are sent with a
+     CRC-32C of header and body, and checked when read,
+     see checksum below
+   - Z, NoCompression by default, says which message
+     bodies are sent compressed, see compression.rs
+   - with the async feature, also implements AsyncSndr,
+     AsyncRcvr, and AsyncProcess with the same framing,
+     see async_processing.rs
 */
 
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
+mod compression;
+pub use compression::*;
+#[cfg(feature = "async")]
+mod async_processing;
+
 /*-- RustComm facilities --*/
 use rust_traits::*;
 use rust_message::*;
@@ -21,115 +37,359 @@ use rust_comm_logger::*;
 
 /*-- std library facilities --*/
 use std::fmt::*;
-use std::net::{TcpStream};
 use std::io::prelude::*;
 use std::io::{BufReader, BufWriter, Write};
-use std::convert::{TryInto};
+use std::convert::{TryFrom, TryInto};
+
+use crc::{Crc, CRC_32_ISCSI};
 
 type M = Message;
 
 /*---------------------------------------------------------
-  CommProcessing<L> 
+  CommProcessing<L,Z> 
   - defines application specific processing for the
     appliczation's message type
   - L is a logger type the must implement the Logger trait
+  - Z is a Compression, applied to messages sent
 */
 #[derive(Debug, Copy, Clone, Default)]
-pub struct CommProcessing<L>
-where L: Logger + Debug + Copy + Clone + Default {
+pub struct CommProcessing<L, Z = NoCompression>
+where L: Logger + Debug + Copy + Clone + Default, Z: Compression {
     log: L,
+    compression: Z,
 }
-impl<L> CommProcessing<L>
-where L: Logger + Debug + Copy + Clone + Default
+impl<L,Z> CommProcessing<L,Z>
+where L: Logger + Debug + Copy + Clone + Default, Z: Compression
 {
-    pub fn new() -> CommProcessing<L> {
+    pub fn new() -> CommProcessing<L,Z> {
         CommProcessing {
             log: L::default(),
+            compression: Z::default(),
         }
     }
 }
-impl<M,L> Sndr<M> for CommProcessing<L>
+impl<M,L,Z> Sndr<M> for CommProcessing<L,Z>
 where 
     M: Msg + Clone + Send + Default,
-    L: Logger + Debug + Copy + Clone + Default
+    L: Logger + Debug + Copy + Clone + Default,
+    Z: Compression
 {
-    fn send_message(msg: &M, stream: &mut TcpStream) -> std::io::Result<()>
+    fn send_message<W: Write>(msg: &M, stream: &mut W) -> CommResult<()>
     {
         L::write(&format!("\n  msg.len(): {}", msg.len()));
-        stream.write(&msg.get_ref())?;
+        let wire = wire_bytes::<M,Z>(msg);
+        stream.write_all(&wire)?;
+        if let Some(sum) = trailer(msg, &wire) {
+            stream.write_all(&sum)?;
+        }
         Ok(())
     }
-    fn buf_send_message(msg: &M, stream: &mut BufWriter<TcpStream>) -> std::io::Result<()>
+    fn buf_send_message<W: Write>(msg: &M, stream: &mut BufWriter<W>) -> CommResult<()>
     {
         L::write(&format!("\n  msg.len(): {}", msg.len()));
-        stream.write(&msg.get_ref())?;
-        let msg_type = msg.get_type(); 
-        if msg_type == MessageType::FLUSH as u8 
-            || msg_type == MessageType::END as u8 
-            || msg_type == MessageType::QUIT as u8 
-        {
+        let wire = wire_bytes::<M,Z>(msg);
+        stream.write_all(&wire)?;
+        if let Some(sum) = trailer(msg, &wire) {
+            stream.write_all(&sum)?;
+        }
+        if is_flushed(msg.get_type()) {
             L::write("\n  flushing stream");
             let _ = stream.flush();
         }
         Ok(())
     }
+    fn encode(msg: &M) -> Vec<u8> {
+        let wire = wire_bytes::<M,Z>(msg);
+        let sum = trailer(msg, &wire);
+        let mut bytes = wire.into_owned();
+        if let Some(sum) = sum {
+            bytes.extend_from_slice(&sum);
+        }
+        bytes
+    }
+}
+/*---------------------------------------------------------
+  Frame checksums
+  - CRC-32C of the header and body as sent, appended
+    big-endian after the body
+  - a mismatch is reported only after reading the whole
+    frame, so the stream stays in step and the receiver
+    may go on to the next message
+*/
+const CASTAGNOLI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+pub fn checksum(bytes: &[u8]) -> u32 {
+    CASTAGNOLI.checksum(bytes)
 }
-impl<M,L> Rcvr<M> for CommProcessing<L>
+/*-- sent after wire, msg's header and body, if msg has FLAG_CHECKSUM --*/
+fn trailer<M: Msg>(msg: &M, wire: &[u8]) -> Option<[u8; CHECKSUM_SIZE]> {
+    if msg.get_flags() & FLAG_CHECKSUM == 0 {
+        return None;
+    }
+    Some(checksum(wire).to_be_bytes())
+}
+/*-- size is the whole frame, for ChecksumMismatch --*/
+fn verify(hdr: &[u8], bdy: &[u8], sum: &[u8], size: usize) -> CommResult<()> {
+    let mut digest = CASTAGNOLI.digest();
+    digest.update(hdr);
+    digest.update(bdy);
+    let actual = digest.finalize();
+    let expected = u32::from_be_bytes(sum.try_into().unwrap());
+    if actual != expected {
+        return Err(CommError::ChecksumMismatch { expected, actual, size });
+    }
+    Ok(())
+}
+/*-- types that are flushed as soon as they are written --*/
+pub fn is_flushed(msg_type: u8) -> bool {
+    msg_type == MessageType::FLUSH as u8 
+        || msg_type == MessageType::END as u8 
+        || msg_type == MessageType::QUIT as u8 
+        || msg_type == MessageType::FILE_BEGIN as u8 
+        || msg_type == MessageType::FILE_END as u8 
+        || msg_type == MessageType::FILE_ACK as u8 
+        || msg_type == MessageType::FILE_ERROR as u8 
+        || msg_type == MessageType::SHUTDOWN as u8 
+        || msg_type == MessageType::SUBSCRIBE as u8 
+        || msg_type == MessageType::UNSUBSCRIBE as u8 
+        || msg_type == MessageType::PUBLISH as u8 
+        || msg_type == MessageType::TOPIC_ERROR as u8 
+        || msg_type == MessageType::PING as u8 
+        || msg_type == MessageType::PONG as u8 
+        || msg_type == MessageType::PROTOCOL_ERROR as u8 
+}
+impl<M,L,Z> Rcvr<M> for CommProcessing<L,Z>
 where 
     M: Msg + Clone + Send + Default,
-    L: Logger + Debug + Copy + Clone + Default
+    L: Logger + Debug + Copy + Clone + Default,
+    Z: Compression
 {
-    /*-- reads message and enques in supplied BlockingQueue<M> --*/
-    fn recv_message(stream: &mut TcpStream) -> std::io::Result<M> 
+    /*-- reads message with body of at most max bytes --*/
+    fn recv_message_max<R: Read>(stream: &mut R, max: usize) -> CommResult<M> 
     {
         L::write("\n  attempting to receive msg in commProc");
-        let buf = &mut [0u8; HEADER_SIZE];
-        stream.read_exact(buf)?;
-        let msgtype = buf[0];
-        let sz_slice = &buf[1..HEADER_SIZE];
-        let mut dst = [0u8;8];
-        dst.clone_from_slice(sz_slice); // array from byte slice
-        let bdysz = usize::from_be_bytes(dst);   // usize from byte array
-
-        let mut bdy = vec![0u8;bdysz];
-        stream.read_exact(&mut bdy)?;        
-        let msg_size = TYPE_SIZE + CONTENT_SIZE + bdysz;
-        let mut msg = M::new(msg_size);
-        msg.set_type(msgtype);
-        msg.set_content_bytes(&bdy);
-        Ok(msg)
+        read_message(stream, max)
     }
     /*-- same as above but uses buffered reader --*/
-    fn buf_recv_message(stream: &mut BufReader<TcpStream>) -> std::io::Result<M> 
+    fn buf_recv_message_max<R: Read>(stream: &mut BufReader<R>, max: usize) -> CommResult<M> 
     {
         L::write("\n  attempting to receive msg in commProc");
-        let buf = &mut [0u8; HEADER_SIZE];
-        stream.read_exact(buf)?;
-        let msgtype = buf[0];
-        let sz_slice = &buf[1..HEADER_SIZE];
-        let mut dst = [0u8;8];
-        dst.clone_from_slice(sz_slice); // array from byte slice
-        let bdysz = usize::from_be_bytes(dst);   // usize from byte array
+        read_message(stream, max)
+    }
+    /*-- same framing as read_message, from bytes already read --*/
+    fn decode_max(buf: &[u8], max: usize) -> CommResult<Option<(M, usize)>>
+    {
+        if buf.len() < PREFIX_SIZE {
+            return Ok(None);
+        }
+        let format = Format::of(buf)?;
+        let hdrsz = format.header_size();
+        if buf.len() < hdrsz {
+            return Ok(None);
+        }
+        let header = format.parse(&buf[..hdrsz], max)?;
+        let end = hdrsz.checked_add(header.size).ok_or_else(|| {
+            CommError::Framing("content size overflows usize".to_string())
+        })?;
+        let size = end + header.trailer_size();
+        if buf.len() < size {
+            return Ok(None);
+        }
+        if header.is_checked() {
+            verify(&buf[..hdrsz], &buf[hdrsz..end], &buf[end..size], size)?;
+        }
+        Ok(Some((header.assemble(&buf[hdrsz..end], max)?, size)))
+    }
+}
+/*---------------------------------------------------------
+  Wire formats
+  - Current is the versioned Message header, see
+    rust_message
+  - V1 is the version 1 header, without a content type,
+    its bodies are RAW
+  - Legacy is the original unversioned header, type and
+    content size only, read with zero ids
+  - V1 and Legacy are still read so older peers can be
+    upgraded one at a time; messages are always sent as
+    Current
+  - the first PREFIX_SIZE bytes, magic and version, tell
+    them apart; a Legacy header starts with one of the
+    LEGACY_TYPES, anything else is BadMagic or
+    UnsupportedVersion, so stray text isn't read as a
+    Legacy header
+  - a content size over the receiver's max is
+    OversizeFrame, found before anything is allocated
+*/
+const PREFIX_SIZE: usize = MAGIC_SIZE + 1;
 
-        let mut bdy = vec![0u8;bdysz];
-        stream.read_exact(&mut bdy)?;        
-        let msg_size = TYPE_SIZE + CONTENT_SIZE + bdysz;
-        let mut msg = M::new(msg_size);
-        msg.set_type(msgtype);
-        msg.set_content_bytes(&bdy);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Current,
+    V1,
+    Legacy,
+}
+impl Format {
+    fn of(prefix: &[u8]) -> CommResult<Format> {
+        if prefix[..MAGIC_SIZE] == MAGIC {
+            match prefix[VERSION_OFFSET] {
+                VERSION => Ok(Format::Current),
+                1 => Ok(Format::V1),
+                version => Err(CommError::UnsupportedVersion(version)),
+            }
+        }
+        else if LEGACY_TYPES.contains(&prefix[0]) {
+            Ok(Format::Legacy)
+        }
+        else {
+            Err(CommError::BadMagic([prefix[0], prefix[1]]))
+        }
+    }
+    fn header_size(self) -> usize {
+        match self {
+            Format::Current => HEADER_SIZE,
+            Format::V1 => V1_HEADER_SIZE,
+            Format::Legacy => LEGACY_HEADER_SIZE,
+        }
+    }
+    /*-- fields of a whole header, rejecting oversize bodies --*/
+    fn parse(self, hdr: &[u8], max: usize) -> CommResult<Header> {
+        let header = self.fields(hdr)?;
+        if header.size > max {
+            return Err(CommError::OversizeFrame { size: header.size, max });
+        }
+        Ok(header)
+    }
+    fn fields(self, hdr: &[u8]) -> CommResult<Header> {
+        match self {
+            Format::Current => {
+                Ok(Header {
+                    flags: hdr[FLAGS_OFFSET],
+                    msg_type: hdr[TYPE_OFFSET],
+                    content_type: hdr[CONTENT_TYPE_OFFSET],
+                    size: content_size(u64_at(hdr, CONTENT_SIZE_OFFSET))?,
+                    msg_id: u64_at(hdr, MSG_ID_OFFSET),
+                    corr_id: u64_at(hdr, CORR_ID_OFFSET),
+                })
+            }
+            Format::V1 => {
+                let shift = CONTENT_TYPE_SIZE;  // fields after type move up
+                Ok(Header {
+                    flags: hdr[FLAGS_OFFSET],
+                    msg_type: hdr[TYPE_OFFSET],
+                    content_type: ContentType::RAW as u8,
+                    size: content_size(u64_at(hdr, CONTENT_SIZE_OFFSET - shift))?,
+                    msg_id: u64_at(hdr, MSG_ID_OFFSET - shift),
+                    corr_id: u64_at(hdr, CORR_ID_OFFSET - shift),
+                })
+            }
+            Format::Legacy => {
+                Ok(Header {
+                    flags: 0,
+                    msg_type: hdr[0],
+                    content_type: ContentType::RAW as u8,
+                    size: content_size(u64_at(hdr, TYPE_SIZE))?,
+                    msg_id: 0,
+                    corr_id: 0,
+                })
+            }
+        }
+    }
+}
+/*-- header fields, whichever format they arrived in --*/
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    flags: u8,
+    msg_type: u8,
+    content_type: u8,
+    size: usize,
+    msg_id: u64,
+    corr_id: u64,
+}
+impl Header {
+    fn is_checked(&self) -> bool {
+        self.flags & FLAG_CHECKSUM != 0
+    }
+    fn trailer_size(&self) -> usize {
+        match self.is_checked() {
+            true => CHECKSUM_SIZE,
+            false => 0,
+        }
+    }
+    /*-- message from header, keeping its ids, and body, inflated if compressed --*/
+    fn assemble<M: Msg>(&self, bdy: &[u8], max: usize) -> CommResult<M> {
+        let inflated;
+        let bdy = match self.flags & FLAG_COMPRESSED {
+            0 => bdy,
+            _ => {
+                inflated = inflate(bdy, max)?;
+                &inflated[..]
+            }
+        };
+        let mut msg = M::new(HEADER_SIZE + bdy.len());
+        msg.set_flags(self.flags & !FLAG_COMPRESSED);
+        msg.set_type(self.msg_type);
+        msg.set_content_type(self.content_type);
+        msg.set_content_bytes(bdy);
+        msg.set_msg_id(self.msg_id);
+        msg.set_correlation_id(self.corr_id);
         Ok(msg)
     }
 }
+fn u64_at(hdr: &[u8], offset: usize) -> u64 {
+    let mut dst = [0u8;8];
+    dst.clone_from_slice(&hdr[offset..offset + 8]); // array from byte slice
+    u64::from_be_bytes(dst)                         // u64 from byte array
+}
+fn content_size(sz: u64) -> CommResult<usize> {
+    usize::try_from(sz).map_err(|_| {
+        CommError::Framing("content size overflows usize".to_string())
+    })
+}
+/*---------------------------------------------------------
+  Read header then body from stream
+  - end of stream before the header is PeerClosed, a
+    clean close between messages
+  - end of stream within the body is a Framing error
+  - a bad prefix, version, or size is reported before
+    reading further, a checksum mismatch after reading the
+    whole frame
+*/
+fn read_message<M: Msg, R: Read>(stream: &mut R, max: usize) -> CommResult<M> {
+    let buf = &mut [0u8; HEADER_SIZE];
+    stream.read_exact(&mut buf[..PREFIX_SIZE])?;
+    let format = Format::of(&buf[..])?;
+    let hdrsz = format.header_size();
+    stream.read_exact(&mut buf[PREFIX_SIZE..hdrsz])?;
+    let header = format.parse(&buf[..hdrsz], max)?;
+    let mut bdy = vec![0u8;header.size];
+    stream.read_exact(&mut bdy).map_err(|e| body_error(e, header.size))?;
+    if header.is_checked() {
+        let sum = &mut [0u8; CHECKSUM_SIZE];
+        stream.read_exact(sum).map_err(|e| body_error(e, header.size))?;
+        verify(&buf[..hdrsz], &bdy, sum, hdrsz + header.size + CHECKSUM_SIZE)?;
+    }
+    header.assemble(&bdy, max)
+}
+fn body_error(e: std::io::Error, bdysz: usize) -> CommError {
+    if e.kind() == std::io::ErrorKind::UnexpectedEof {
+        CommError::Framing(format!("stream ended within {} byte body", bdysz))
+    }
+    else {
+        CommError::from(e)
+    }
+}
 /*---------------------------------------------------------
   Process<M> handles processing of each message on 
   Listener<P,L>
 */
-impl<M,L> Process<M> for CommProcessing<L>
+impl<M,L,Z> Process<M> for CommProcessing<L,Z>
 where 
     M: Msg + Clone + Send + Default,
-    L: Logger + Debug + Copy + Clone + Default
+    L: Logger + Debug + Copy + Clone + Default,
+    Z: Compression
 {
-    fn process_message(msg: &mut M) 
+    /*-- echo msg back as reply --*/
+    fn process_message(&mut self, mut msg: M, _ctx: &mut ConnContext) -> Vec<M>
     {
         L::write("\n--entered process_message--");
         let msg_type = msg.get_type();
@@ -139,19 +399,262 @@ where
         {
             msg.set_type(MessageType::REPLY as u8);
         }
+        vec![msg]
     }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
     #[test]
     fn construction() {
-        let msg = Message::new(64);
-        let _cp = CommProcessing::<MuteLog>::default();
-        let addr = "127.0.0.1:8080";
-        let _lstnr = std::net::TcpListener::bind(addr);
-        let mut stream = std::net::TcpStream::connect(addr).unwrap();
-        let _ = CommProcessing::<MuteLog>::send_message(&msg, &mut stream);
-        assert_eq!(2 + 2, 4);
+        type P = CommProcessing<MuteLog>;
+        let _cp = P::default();
+        let mut msg = Message::create_msg_str_fit("round trip");
+        msg.set_msg_id(9);
+        /*-- Sndr and Rcvr take any byte stream, no socket needed --*/
+        let mut wire = Vec::<u8>::new();
+        P::send_message(&msg, &mut wire).unwrap();
+        let mut buf_writer = BufWriter::new(Vec::<u8>::new());
+        P::buf_send_message(&msg, &mut buf_writer).unwrap();
+        wire.extend(buf_writer.into_inner().unwrap());
+        let mut reader = BufReader::new(&wire[..]);
+        for _ in 0..2 {
+            let back: Message = P::buf_recv_message(&mut reader).unwrap();
+            assert_eq!(back.get_content_str().unwrap(), "round trip");
+            assert_eq!(back.get_msg_id(), 9);
+        }
+        let end: CommResult<Message> = P::recv_message(&mut reader);
+        assert!(matches!(end, Err(CommError::PeerClosed)));
+    }
+    #[test]
+    fn decode_waits_for_whole_message() {
+        type P = CommProcessing<MuteLog>;
+        let mut msg = Message::create_msg_str_fit("in pieces");
+        msg.set_correlation_id(3);
+        let mut wire = P::encode(&msg);
+        wire.extend_from_slice(&P::encode(&msg));
+        let one = msg.len();
+        let rslt: CommResult<Option<(Message, usize)>> = P::decode(&wire[..one - 1]);
+        assert!(rslt.unwrap().is_none());
+        let (back, used): (Message, usize) = P::decode(&wire).unwrap().unwrap();
+        assert_eq!(used, one);
+        assert_eq!(back.get_content_str().unwrap(), "in pieces");
+        assert_eq!(back.get_correlation_id(), 3);
+    }
+    /*-- msg as the original Message framed it, type and usize size --*/
+    fn legacy_frame(msg: &Message) -> Vec<u8> {
+        let mut wire = vec![msg.get_type()];
+        wire.extend_from_slice(&msg.get_content_size().to_be_bytes());
+        wire.extend_from_slice(msg.get_content_bytes());
+        wire
+    }
+    #[test]
+    fn legacy_and_current_frames_are_read() {
+        type P = CommProcessing<MuteLog>;
+        let mut msg = Message::create_msg_str_fit("old peer");
+        msg.set_type(MessageType::FLUSH as u8);
+        let mut wire = legacy_frame(&msg);
+        assert_eq!(wire.len(), 9 + 8);
+        let mut current = msg.clone();
+        current.set_msg_id(4);
+        current.set_correlation_id(2);
+        wire.extend_from_slice(&P::encode(&current));
+        let mut reader = BufReader::new(&wire[..]);
+        let back: Message = P::buf_recv_message(&mut reader).unwrap();
+        assert_eq!(back.get_ref(), msg.get_ref());
+        let back: Message = P::buf_recv_message(&mut reader).unwrap();
+        assert_eq!(back.get_ref(), current.get_ref());
+        /*-- decode too, each as a whole header arrives --*/
+        let legacy = LEGACY_HEADER_SIZE + 8;
+        let rslt: CommResult<Option<(Message, usize)>> = P::decode(&wire[..legacy - 1]);
+        assert!(rslt.unwrap().is_none());
+        let (back, used): (Message, usize) = P::decode(&wire).unwrap().unwrap();
+        assert_eq!(used, legacy);
+        assert_eq!(back.get_ref(), msg.get_ref());
+        let (back, used): (Message, usize) = P::decode(&wire[legacy..]).unwrap().unwrap();
+        assert_eq!(used, current.len());
+        assert_eq!(back.get_ref(), current.get_ref());
+    }
+    /*-- msg as version 1 framed it, without a content type --*/
+    fn v1_frame(msg: &Message) -> Vec<u8> {
+        let mut wire = msg.get_ref().clone();
+        wire.remove(CONTENT_TYPE_OFFSET);
+        wire[VERSION_OFFSET] = 1;
+        wire
+    }
+    #[test]
+    fn version_1_frames_are_read_as_raw() {
+        type P = CommProcessing<MuteLog>;
+        let mut msg = Message::create_msg_str_fit("version 1 peer");
+        msg.set_type(MessageType::REPLY as u8);
+        msg.set_msg_id(6);
+        msg.set_correlation_id(5);
+        let mut wire = v1_frame(&msg);
+        assert_eq!(wire.len(), V1_HEADER_SIZE + 14);
+        let mut typed = msg.clone();
+        typed.set_content_type(ContentType::JSON as u8);
+        wire.extend_from_slice(&P::encode(&typed));
+        let mut reader = BufReader::new(&wire[..]);
+        let back: Message = P::buf_recv_message(&mut reader).unwrap();
+        assert_eq!(back.get_ref(), msg.get_ref());
+        assert_eq!(back.get_content_type(), ContentType::RAW as u8);
+        let back: Message = P::buf_recv_message(&mut reader).unwrap();
+        assert_eq!(back.get_content_type(), ContentType::JSON as u8);
+
+        let v1 = V1_HEADER_SIZE + 14;
+        let rslt: CommResult<Option<(Message, usize)>> = P::decode(&wire[..v1 - 1]);
+        assert!(rslt.unwrap().is_none());
+        let (back, used): (Message, usize) = P::decode(&wire).unwrap().unwrap();
+        assert_eq!(used, v1);
+        assert_eq!(back.get_ref(), msg.get_ref());
+    }
+    #[test]
+    fn bad_magic_and_version_are_rejected() {
+        type P = CommProcessing<MuteLog>;
+        let msg = Message::create_msg_str_fit("checked");
+        let rslt: CommResult<Message> = P::recv_message(&mut &b"\xffGET / HTTP/1.1"[..]);
+        assert!(matches!(rslt, Err(CommError::BadMagic([0xff, b'G']))));
+        let rslt: CommResult<Option<(Message, usize)>> = P::decode(b"GET / HTTP/1.1\r\n\r\n");
+        assert!(matches!(rslt, Err(CommError::BadMagic([b'G', b'E']))));
+        let rslt: CommResult<Message> = P::recv_message(&mut &b"\x03\0\0\0\0\0\0\0\0"[..]);
+        assert!(matches!(rslt, Err(CommError::BadMagic([3, 0]))));
+        let mut wire = P::encode(&msg);
+        wire[1] = 0;
+        let rslt: CommResult<Option<(Message, usize)>> = P::decode(&wire[..PREFIX_SIZE]);
+        assert!(matches!(rslt, Err(CommError::BadMagic([0xA5, 0]))));
+
+        let mut wire = P::encode(&msg);
+        wire[VERSION_OFFSET] = VERSION + 1;
+        let rslt: CommResult<Message> = P::recv_message(&mut &wire[..]);
+        match rslt {
+            Err(e @ CommError::UnsupportedVersion(_)) => {
+                assert_eq!(e.to_string(), format!("unsupported protocol version {}", VERSION + 1));
+            }
+            _ => panic!("expected UnsupportedVersion"),
+        }
+        let rslt: CommResult<Option<(Message, usize)>> = P::decode(&wire[..HEADER_SIZE]);
+        assert!(matches!(rslt, Err(CommError::UnsupportedVersion(_))));
+    }
+    #[test]
+    fn checksums_catch_corruption() {
+        type P = CommProcessing<MuteLog>;
+        let mut msg = Message::create_msg_str_fit("guarded");
+        msg.set_flags(FLAG_CHECKSUM);
+        let wire = P::encode(&msg);
+        assert_eq!(wire.len(), msg.len() + CHECKSUM_SIZE);
+        let mut sent = Vec::new();
+        P::send_message(&msg, &mut sent).unwrap();
+        assert_eq!(sent, wire);
+        let back: Message = P::recv_message(&mut &wire[..]).unwrap();
+        assert_eq!(back.get_ref(), msg.get_ref());
+
+        /*-- a flipped body bit is caught, after the whole frame is read --*/
+        let mut bad = wire.clone();
+        bad[HEADER_SIZE] ^= 0x10;
+        bad.extend_from_slice(&wire);
+        let mut reader = BufReader::new(&bad[..]);
+        let rslt: CommResult<Message> = P::buf_recv_message(&mut reader);
+        match rslt {
+            Err(CommError::ChecksumMismatch { size, .. }) => assert_eq!(size, wire.len()),
+            _ => panic!("expected ChecksumMismatch"),
+        }
+        let next: Message = P::buf_recv_message(&mut reader).unwrap();
+        assert_eq!(next.get_content_str().unwrap(), "guarded");
+        let rslt: CommResult<Option<(Message, usize)>> = P::decode(&bad);
+        assert!(matches!(rslt, Err(CommError::ChecksumMismatch { .. })));
+        let rslt: CommResult<Option<(Message, usize)>> = P::decode(&wire[..wire.len() - 1]);
+        assert!(rslt.unwrap().is_none());
+    }
+    #[test]
+    fn oversize_bodies_are_rejected_before_reading() {
+        type P = CommProcessing<MuteLog>;
+        let mut msg = Message::create_msg_str_fit("eleven byte");
+        msg.set_type(MessageType::FLUSH as u8);
+        let wire = P::encode(&msg);
+        let back: Message = P::recv_message_max(&mut &wire[..], 11).unwrap();
+        assert_eq!(back.get_ref(), msg.get_ref());
+        let rslt: CommResult<Message> = P::recv_message_max(&mut &wire[..], 10);
+        assert!(matches!(rslt, Err(CommError::OversizeFrame { size: 11, max: 10 })));
+
+        /*-- only the header is there, reading the body would be Framing --*/
+        let mut huge = wire[..HEADER_SIZE].to_vec();
+        huge[CONTENT_SIZE_OFFSET..MSG_ID_OFFSET].copy_from_slice(&(1u64 << 60).to_be_bytes());
+        let rslt: CommResult<Message> = P::recv_message(&mut &huge[..]);
+        match rslt {
+            Err(CommError::OversizeFrame { max, .. }) => assert_eq!(max, DEFAULT_MAX_MSG_SIZE),
+            _ => panic!("expected OversizeFrame"),
+        }
+        let rslt: CommResult<Option<(Message, usize)>> = P::decode(&huge);
+        assert!(matches!(rslt, Err(CommError::OversizeFrame { .. })));
+    }
+    /*-- header announcing size bytes of body, in either format --*/
+    fn header_claiming(size: u64, legacy: bool) -> Vec<u8> {
+        let msg = Message::create_msg_header_only();
+        let mut hdr = match legacy {
+            true => legacy_frame(&msg),
+            false => msg.get_ref().clone(),
+        };
+        let offset = if legacy { TYPE_SIZE } else { CONTENT_SIZE_OFFSET };
+        hdr[offset..offset + CONTENT_SIZE].copy_from_slice(&size.to_be_bytes());
+        hdr
+    }
+    proptest! {
+        #[test]
+        fn truncated_frames_are_incomplete(
+            body in proptest::collection::vec(any::<u8>(), 0..256),
+            flags in 0u8..4, cut in any::<proptest::sample::Index>()
+        ) {
+            type P = CommProcessing<MuteLog, Deflate<32>>;
+            let mut msg = Message::create_msg_bytes_fit(&body);
+            msg.set_flags(flags & FLAG_CHECKSUM);
+            let wire = P::encode(&msg);
+            let short = &wire[..cut.index(wire.len())];
+            let rslt: CommResult<Option<(Message, usize)>> = P::decode(short);
+            prop_assert!(rslt.unwrap().is_none());
+            let rslt: CommResult<Message> = P::recv_message(&mut &short[..]);
+            let incomplete = matches!(rslt, Err(CommError::PeerClosed) | Err(CommError::Framing(_)));
+            prop_assert!(incomplete);
+        }
+        #[test]
+        fn oversize_headers_are_rejected(
+            max in 0usize..1 << 20, excess in 1u64..u64::MAX >> 1, legacy in any::<bool>()
+        ) {
+            type P = CommProcessing<MuteLog>;
+            let size = max as u64 + excess;
+            let hdr = header_claiming(size, legacy);
+            let rslt: CommResult<Message> = P::recv_message_max(&mut &hdr[..], max);
+            let rejected = matches!(
+                rslt, Err(CommError::OversizeFrame { size: s, max: m }) if s as u64 == size && m == max
+            );
+            prop_assert!(rejected);
+            let rslt: CommResult<Option<(Message, usize)>> = P::decode_max(&hdr, max);
+            let rejected = matches!(rslt, Err(CommError::OversizeFrame { .. }));
+            prop_assert!(rejected);
+        }
+        #[test]
+        fn arbitrary_bytes_never_exceed_max(
+            bytes in proptest::collection::vec(any::<u8>(), 0..512), max in 0usize..256
+        ) {
+            type P = CommProcessing<MuteLog>;
+            let rslt: CommResult<Message> = P::recv_message_max(&mut &bytes[..], max);
+            if let Ok(msg) = rslt {
+                prop_assert!(msg.get_content_size() <= max);
+            }
+            let rslt: CommResult<Option<(Message, usize)>> = P::decode_max(&bytes, max);
+            if let Ok(Some((msg, used))) = rslt {
+                prop_assert!(msg.get_content_size() <= max && used <= bytes.len());
+            }
+        }
+    }
+    #[test]
+    fn process_echoes_reply() {
+        let mut cp = CommProcessing::<MuteLog>::new();
+        let mut ctx = ConnContext::default();
+        let msg = Message::create_msg_str_fit("echo me");
+        let replies = cp.process_message(msg, &mut ctx);
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].get_type(), MessageType::REPLY as u8);
+        assert_eq!(replies[0].get_content_str().unwrap(), "echo me");
     }
 }
@@ -12,7 +12,7 @@ fn main() {
         break_here(i == 3, f);
         let mut msg = String::from("step #");
         msg.push_str(&i.to_string());
-        print!("    {:?}\n",msg);
+        println!("    {:?}",msg);
     }
     print!("\n  finishing\n\n");
 }
\ No newline at end of file
@@ -8,7 +8,7 @@ use rust_traits::*;
 use rust_message::*;
 // use rust_message::{MessageType};
 
-const MESS_SIZE:usize = 32;
+const MESS_SIZE:usize = 48;
 
 fn main() {
     print!("\n  -- demo Message type --\n");
@@ -16,10 +16,10 @@ fn main() {
     print!("\n  -- demo writing directly to msg buffer --\n");
     let mut msg = Message::new(MESS_SIZE);
     print!("\n  msg len: {:?}",msg.get_ref().len());
-    for i in TYPE_SIZE + CONTENT_SIZE..MESS_SIZE {
+    for i in HEADER_SIZE..MESS_SIZE {
       msg.get_mut_ref()[i] = i as u8;
     }
-    msg.set_content_size(MESS_SIZE);
+    msg.set_content_size(MESS_SIZE - HEADER_SIZE);
     msg.show_message(8);
     println!();
 
@@ -31,9 +31,8 @@ fn main() {
     msg.show_message(8);
     let sz = msg.get_content_size();
     print!("\n  content size: {:?}",sz);
-    let rslt = msg.get_content_str();
-    if let Ok(_) = rslt {
-      print!("\n  contents: {:?}",rslt.unwrap());
+    if let Ok(s) = msg.get_content_str() {
+      print!("\n  contents: {:?}",s);
     }
     println!();
 
@@ -0,0 +1,155 @@
+/////////////////////////////////////////////////////////////
+// rust_message::codec.rs - serde values as message bodies //
+//                                                         //
+/////////////////////////////////////////////////////////////
+/*
+   A Codec encodes serde values as message bodies and
+   decodes them again.  Each is behind a cargo feature:
+   - Bincode, feature bincode, compact, for Rust peers
+   - Json, feature json, readable by anything
+   - MsgPack, feature msgpack, compact and self-describing
+
+   Messages made with from_value carry their codec's
+   ContentType in the header, so decode picks the codec the
+   sender used, as long as this build has its feature:
+
+     let msg = Message::from_value(MessageType::TEXT as u8, &point)?;
+     ...
+     let point: Point = msg.decode()?;
+
+   from_value uses DefaultCodec, the first of Bincode,
+   MsgPack, and Json that is enabled, and from_value_with
+   names the codec.  Failures are CommError::Codec.
+*/
+
+use super::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub trait Codec {
+    /*-- written in the header of messages this codec encodes --*/
+    const CONTENT_TYPE: ContentType;
+    fn encode<T: Serialize + ?Sized>(value: &T) -> CommResult<Vec<u8>>;
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> CommResult<T>;
+}
+fn codec_error<E: std::fmt::Display>(e: E) -> CommError {
+    CommError::Codec(e.to_string())
+}
+
+#[cfg(feature = "bincode")]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Bincode;
+#[cfg(feature = "bincode")]
+impl Codec for Bincode {
+    const CONTENT_TYPE: ContentType = ContentType::BINCODE;
+    fn encode<T: Serialize + ?Sized>(value: &T) -> CommResult<Vec<u8>> {
+        bincode::serialize(value).map_err(codec_error)
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> CommResult<T> {
+        bincode::deserialize(bytes).map_err(codec_error)
+    }
+}
+#[cfg(feature = "json")]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Json;
+#[cfg(feature = "json")]
+impl Codec for Json {
+    const CONTENT_TYPE: ContentType = ContentType::JSON;
+    fn encode<T: Serialize + ?Sized>(value: &T) -> CommResult<Vec<u8>> {
+        serde_json::to_vec(value).map_err(codec_error)
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> CommResult<T> {
+        serde_json::from_slice(bytes).map_err(codec_error)
+    }
+}
+/*-- structs are maps keyed by field name, as Json writes them --*/
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MsgPack;
+#[cfg(feature = "msgpack")]
+impl Codec for MsgPack {
+    const CONTENT_TYPE: ContentType = ContentType::MSGPACK;
+    fn encode<T: Serialize + ?Sized>(value: &T) -> CommResult<Vec<u8>> {
+        rmp_serde::to_vec_named(value).map_err(codec_error)
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> CommResult<T> {
+        rmp_serde::from_slice(bytes).map_err(codec_error)
+    }
+}
+
+#[cfg(feature = "bincode")]
+pub type DefaultCodec = Bincode;
+#[cfg(all(feature = "msgpack", not(feature = "bincode")))]
+pub type DefaultCodec = MsgPack;
+#[cfg(all(feature = "json", not(feature = "bincode"), not(feature = "msgpack")))]
+pub type DefaultCodec = Json;
+
+impl Message {
+    /*-- message of type mt with value as its body, encoded by DefaultCodec --*/
+    pub fn from_value<T: Serialize + ?Sized>(mt: u8, value: &T) -> CommResult<Message> {
+        Message::from_value_with::<DefaultCodec, T>(mt, value)
+    }
+    pub fn from_value_with<C: Codec, T: Serialize + ?Sized>(
+        mt: u8, value: &T
+    ) -> CommResult<Message> {
+        let mut msg = Message::create_msg_bytes_fit(&C::encode(value)?);
+        msg.set_type(mt);
+        msg.set_content_type(C::CONTENT_TYPE as u8);
+        Ok(msg)
+    }
+    /*-- body as a T, decoded by the codec its content type names --*/
+    pub fn decode<T: DeserializeOwned>(&self) -> CommResult<T> {
+        let bdy = self.get_content_bytes();
+        match self.get_content_type() {
+            #[cfg(feature = "bincode")]
+            ct if ct == ContentType::BINCODE as u8 => Bincode::decode(bdy),
+            #[cfg(feature = "json")]
+            ct if ct == ContentType::JSON as u8 => Json::decode(bdy),
+            #[cfg(feature = "msgpack")]
+            ct if ct == ContentType::MSGPACK as u8 => MsgPack::decode(bdy),
+            ct => Err(CommError::Codec(format!("no codec for content type {}", ct))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Reading {
+        sensor: String,
+        values: Vec<f64>,
+        ok: bool,
+    }
+    fn reading() -> Reading {
+        Reading { sensor: "sensors/3/temp".to_string(), values: vec![20.5, -3.25], ok: true }
+    }
+    fn round_trip<C: Codec>() {
+        let msg = Message::from_value_with::<C, _>(MessageType::FLUSH as u8, &reading()).unwrap();
+        assert_eq!(msg.get_type(), MessageType::FLUSH as u8);
+        assert_eq!(msg.get_content_type(), C::CONTENT_TYPE as u8);
+        assert_eq!(msg.decode::<Reading>().unwrap(), reading());
+    }
+    #[test]
+    fn each_codec_round_trips() {
+        #[cfg(feature = "bincode")]
+        round_trip::<Bincode>();
+        #[cfg(feature = "json")]
+        round_trip::<Json>();
+        #[cfg(feature = "msgpack")]
+        round_trip::<MsgPack>();
+        let msg = Message::from_value(MessageType::TEXT as u8, &reading()).unwrap();
+        assert_eq!(msg.get_content_type(), DefaultCodec::CONTENT_TYPE as u8);
+        assert_eq!(msg.decode::<Reading>().unwrap(), reading());
+    }
+    #[test]
+    fn undecodable_bodies_are_codec_errors() {
+        let raw = Message::create_msg_str_fit("{\"sensor\":\"x\"}");
+        let rslt = raw.decode::<Reading>();
+        assert!(matches!(rslt, Err(CommError::Codec(s)) if s == "no codec for content type 0"));
+        let msg = Message::from_value(MessageType::TEXT as u8, &42u8).unwrap();
+        assert!(matches!(msg.decode::<Reading>(), Err(CommError::Codec(_))));
+    }
+}
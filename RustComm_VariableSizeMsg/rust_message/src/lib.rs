@@ -5,14 +5,31 @@
 /////////////////////////////////////////////////////////////
 /*
    Message:
-   - fixed size header holding a MessageType attribute:
-     TEXT, BYTES, END, QUIT, REPLY
+   - fixed size header holding a magic prefix, protocol
+     version, flags, a MessageType attribute: TEXT, BYTES,
+     END, QUIT, REPLY, a ContentType, the content size, and
+     message and correlation ids
+   - file transfer types: FILE_BEGIN, FILE_CHUNK, FILE_END,
+     FILE_ACK, FILE_ERROR
+   - SHUTDOWN, sent by a Listener that is shutting down
+   - publish/subscribe types: SUBSCRIBE, UNSUBSCRIBE,
+     PUBLISH, TOPIC_ERROR
+   - heartbeat types: PING, answered with PONG
+   - PROTOCOL_ERROR, sent before closing a connection
+     whose peer sent a frame that can't be read
    - body holds utf-8 text or arbitrary byte sequence
    - stores contents in std::Vec<u8>
+   - with the bincode, json, or msgpack features, bodies
+     hold serde values, see codec.rs
 */
 
 #![allow(dead_code)]
 
+#[cfg(any(feature = "bincode", feature = "json", feature = "msgpack"))]
+mod codec;
+#[cfg(any(feature = "bincode", feature = "json", feature = "msgpack"))]
+pub use codec::*;
+
 // use std::fmt::*;
 use rust_traits::*;
 use std::str::Utf8Error;
@@ -22,11 +39,70 @@ use std::str::Utf8Error;
   - structure that wraps Vec<u8>, treated as byte array
   - set of public functions for manipulating Message state
 */
+/*---------------------------------------------------------
+  Header layout, version 2:
+    magic | version | flags | type | content type
+      | content size | message id | correlation id
+  - magic and version let receivers reject garbage and
+    peers speaking a newer protocol
+  - content size and ids are big-endian u64, the same
+    width on every platform
+  - ids are zero unless set, a reply's correlation id is
+    the message id of the request it answers
+  - flags are zero unless set, FLAG_CHECKSUM means a
+    CHECKSUM_SIZE byte checksum of header and body
+    follows the body, FLAG_COMPRESSED, set only on the
+    wire, that the body is compressed, see
+    rust_comm_processing
+  - content type, a ContentType, says how the body was
+    encoded, RAW unless set
+*/
+pub const MAGIC:[u8;2] = [0xA5, 0x4D];
+pub const VERSION:u8 = 2;
+pub const MAGIC_SIZE:usize = 2;
+pub const VERSION_OFFSET:usize = MAGIC_SIZE;
+pub const FLAGS_OFFSET:usize = VERSION_OFFSET + 1;
+pub const TYPE_OFFSET:usize = FLAGS_OFFSET + 1;
 pub const TYPE_SIZE:usize = 1;
-pub const CONTENT_SIZE:usize = 8;  // max 4096 - 32 - 1 = 4063
-pub const HEADER_SIZE:usize = TYPE_SIZE + CONTENT_SIZE;
+pub const CONTENT_TYPE_OFFSET:usize = TYPE_OFFSET + TYPE_SIZE;
+pub const CONTENT_TYPE_SIZE:usize = 1;
+pub const CONTENT_SIZE_OFFSET:usize = CONTENT_TYPE_OFFSET + CONTENT_TYPE_SIZE;
+pub const CONTENT_SIZE:usize = 8;
+pub const ID_SIZE:usize = 8;
+pub const MSG_ID_OFFSET:usize = CONTENT_SIZE_OFFSET + CONTENT_SIZE;
+pub const CORR_ID_OFFSET:usize = MSG_ID_OFFSET + ID_SIZE;
+pub const HEADER_SIZE:usize = CORR_ID_OFFSET + ID_SIZE;
+pub const FLAG_CHECKSUM:u8 = 0x01;
+pub const FLAG_COMPRESSED:u8 = 0x02;
+pub const CHECKSUM_SIZE:usize = 4;
+/*---------------------------------------------------------
+  Version 1 header, before content types:
+    magic | version | flags | type | content size
+      | message id | correlation id
+  - still readable, its bodies are RAW
+*/
+pub const V1_HEADER_SIZE:usize = HEADER_SIZE - CONTENT_TYPE_SIZE;
+/*---------------------------------------------------------
+  Legacy header, the original unversioned Message:
+    type | content size
+  - still readable, see rust_comm_processing, with zero
+    ids and flags
+  - its type is one of LEGACY_TYPES, the types the
+    original Message had, and MAGIC[0] isn't, so the first
+    byte tells them apart
+*/
+pub const LEGACY_HEADER_SIZE:usize = TYPE_SIZE + CONTENT_SIZE;
+pub const LEGACY_TYPES:[u8;6] = [
+    MessageType::DEFAULT as u8,
+    MessageType::TEXT as u8,
+    MessageType::REPLY as u8,
+    MessageType::END as u8,
+    MessageType::QUIT as u8,
+    MessageType::FLUSH as u8,
+];
 
 #[repr(u8)]
+#[allow(non_camel_case_types)]
 pub enum MessageType {
     DEFAULT = 0,
     TEXT = 1,
@@ -34,6 +110,35 @@ pub enum MessageType {
     END = 4,
     QUIT = 8,
     FLUSH = 16,
+    /*-- file transfer, see rust_comm::file_transfer --*/
+    FILE_BEGIN = 32,
+    FILE_CHUNK = 33,
+    FILE_END = 34,
+    FILE_ACK = 35,
+    FILE_ERROR = 36,
+    /*-- Listener is shutting down, see rust_comm::shutdown --*/
+    SHUTDOWN = 37,
+    /*-- publish/subscribe, see rust_comm::pubsub --*/
+    SUBSCRIBE = 38,
+    UNSUBSCRIBE = 39,
+    PUBLISH = 40,
+    TOPIC_ERROR = 41,
+    /*-- heartbeat, see rust_comm::heartbeat --*/
+    PING = 42,
+    PONG = 43,
+    /*-- unreadable frame, body is why, see rust_comm::frame_limit --*/
+    PROTOCOL_ERROR = 44,
+}
+/*-- how a message body was encoded --*/
+#[repr(u8)]
+#[allow(non_camel_case_types)]
+pub enum ContentType {
+    /*-- bytes or text the application framed itself --*/
+    RAW = 0,
+    /*-- serde values, see codec.rs --*/
+    BINCODE = 1,
+    JSON = 2,
+    MSGPACK = 3,
 }
 
  #[derive(Debug, Clone, Default)]
@@ -46,14 +151,17 @@ impl Msg for Message {
     */
     fn new(sz:usize) -> Self {
         assert!(sz >= HEADER_SIZE);
-        Self {
+        let mut msg = Self {
             br: vec![0; sz],
-        }
+        };
+        msg.set_prefix();
+        msg
     }
-    /*-- load existing heap array with zeros --*/
+    /*-- load existing heap array with zeros, keeping the prefix --*/
     fn init(&mut self) {
         let sz = self.len();
         self.br = vec![0;sz];
+        self.set_prefix();
     }
     /*-- return message length --*/
     fn len(&self) -> usize {
@@ -64,10 +172,22 @@ impl Msg for Message {
     }
     /*-- set message MsgType --*/
     fn set_type(&mut self, mt:u8) {
-        self.br[0] = mt as u8;
+        self.br[TYPE_OFFSET] = mt;
     }
     fn get_type(&self) -> u8 {
-        self.br[0]
+        self.br[TYPE_OFFSET]
+    }
+    fn set_flags(&mut self, flags:u8) {
+        self.br[FLAGS_OFFSET] = flags;
+    }
+    fn get_flags(&self) -> u8 {
+        self.br[FLAGS_OFFSET]
+    }
+    fn set_content_type(&mut self, ct:u8) {
+        self.br[CONTENT_TYPE_OFFSET] = ct;
+    }
+    fn get_content_type(&self) -> u8 {
+        self.br[CONTENT_TYPE_OFFSET]
     }
     /*-------------------------------------------
       Set message content from buff and set
@@ -99,13 +219,23 @@ impl Msg for Message {
     }
     /*-- set message content size --*/
     fn set_content_size(&mut self, sz:usize) {
-        self.set_field(TYPE_SIZE, &sz.to_be_bytes());
+        self.set_field(CONTENT_SIZE_OFFSET, &(sz as u64).to_be_bytes());
     }
     fn get_content_size(&self) -> usize {
-        let bytes = self.get_field(TYPE_SIZE, CONTENT_SIZE);
-        let mut dst = [0u8;8];
-        dst.clone_from_slice(bytes); // array from byte slice
-        usize::from_be_bytes(dst)    // usize from byte array
+        self.get_u64(CONTENT_SIZE_OFFSET) as usize
+    }
+    /*-- message and correlation ids --*/
+    fn set_msg_id(&mut self, id:u64) {
+        self.set_field(MSG_ID_OFFSET, &id.to_be_bytes());
+    }
+    fn get_msg_id(&self) -> u64 {
+        self.get_u64(MSG_ID_OFFSET)
+    }
+    fn set_correlation_id(&mut self, id:u64) {
+        self.set_field(CORR_ID_OFFSET, &id.to_be_bytes());
+    }
+    fn get_correlation_id(&self) -> u64 {
+        self.get_u64(CORR_ID_OFFSET)
     }
     fn get_bytes(&self) -> &[u8] {
         &self.br[..]
@@ -114,9 +244,7 @@ impl Msg for Message {
         &mut self.br[..]
     }
     fn set_bytes(&mut self, buff:&[u8]) {
-        for i in 0..buff.len() {
-            self.br[i] = buff[i];
-        }
+        self.br[..buff.len()].copy_from_slice(buff);
     }
     fn get_ref(&self) -> &Vec<u8> {
         &self.br
@@ -144,24 +272,64 @@ impl Msg for Message {
     }
     fn type_display(&self) -> String {
         let mut rtn:String = String::from("UNKNOWN");
-        if self.br[0] == MessageType::DEFAULT as u8 {
+        let mt = self.get_type();
+        if mt == MessageType::DEFAULT as u8 {
             rtn = String::from("DEFAULT");
         }
-        else if self.br[0] == MessageType::END as u8 {
+        else if mt == MessageType::END as u8 {
             rtn = String::from("END");
         }
-        else if self.br[0] == MessageType::QUIT as u8 {
+        else if mt == MessageType::QUIT as u8 {
             rtn = String::from("QUIT");
         }
-        else if self.br[0] == MessageType::REPLY as u8 {
+        else if mt == MessageType::REPLY as u8 {
             rtn = String::from("REPLY");
         }
-        else if self.br[0] == MessageType::TEXT as u8 {
+        else if mt == MessageType::TEXT as u8 {
             rtn = String::from("TEXT");
         }
-        else if self.br[0] == MessageType::FLUSH as u8 {
+        else if mt == MessageType::FLUSH as u8 {
             rtn = String::from("FLUSH");
         }
+        else if mt == MessageType::FILE_BEGIN as u8 {
+            rtn = String::from("FILE_BEGIN");
+        }
+        else if mt == MessageType::FILE_CHUNK as u8 {
+            rtn = String::from("FILE_CHUNK");
+        }
+        else if mt == MessageType::FILE_END as u8 {
+            rtn = String::from("FILE_END");
+        }
+        else if mt == MessageType::FILE_ACK as u8 {
+            rtn = String::from("FILE_ACK");
+        }
+        else if mt == MessageType::FILE_ERROR as u8 {
+            rtn = String::from("FILE_ERROR");
+        }
+        else if mt == MessageType::SHUTDOWN as u8 {
+            rtn = String::from("SHUTDOWN");
+        }
+        else if mt == MessageType::SUBSCRIBE as u8 {
+            rtn = String::from("SUBSCRIBE");
+        }
+        else if mt == MessageType::UNSUBSCRIBE as u8 {
+            rtn = String::from("UNSUBSCRIBE");
+        }
+        else if mt == MessageType::PUBLISH as u8 {
+            rtn = String::from("PUBLISH");
+        }
+        else if mt == MessageType::TOPIC_ERROR as u8 {
+            rtn = String::from("TOPIC_ERROR");
+        }
+        else if mt == MessageType::PING as u8 {
+            rtn = String::from("PING");
+        }
+        else if mt == MessageType::PONG as u8 {
+            rtn = String::from("PONG");
+        }
+        else if mt == MessageType::PROTOCOL_ERROR as u8 {
+            rtn = String::from("PROTOCOL_ERROR");
+        }
         rtn
     }
 }
@@ -194,6 +362,11 @@ impl Message {
         msg.set_content_size(0);
         msg
     }
+    /*-- magic and version, written by new and init --*/
+    fn set_prefix(&mut self) {
+        self.br[..MAGIC_SIZE].copy_from_slice(&MAGIC);
+        self.br[VERSION_OFFSET] = VERSION;
+    }
     pub fn set_field(&mut self, offset:usize, buff: &[u8]) {
         for (i, item) in buff.iter().enumerate() {
             if i + offset < self.br.len() {
@@ -204,6 +377,11 @@ impl Message {
     pub fn get_field(&self, offset:usize, size:usize) -> &[u8] {
         &self.br[offset..offset+size]
     }
+    fn get_u64(&self, offset:usize) -> u64 {
+        let mut dst = [0u8;8];
+        dst.clone_from_slice(self.get_field(offset, 8));
+        u64::from_be_bytes(dst)
+    }
     pub fn set_str(&mut self, offset:usize, s:&str) {
         let buff = Self::str_to_bytes(s);
         self.set_field(offset, buff);
@@ -222,7 +400,36 @@ impl Message {
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+    #[test]
+    fn ids_round_trip() {
+        let mut msg = Message::create_msg_str_fit("ids");
+        assert_eq!(msg.get_msg_id(), 0);
+        msg.set_msg_id(7);
+        msg.set_correlation_id(u64::MAX);
+        assert_eq!(msg.get_msg_id(), 7);
+        assert_eq!(msg.get_correlation_id(), u64::MAX);
+        assert_eq!(msg.get_content_str().unwrap(), "ids");
+    }
+    #[test]
+    fn header_is_versioned() {
+        let mut msg = Message::create_msg_str_fit("v1");
+        msg.set_type(MessageType::TEXT as u8);
+        msg.set_flags(0x80);
+        assert_eq!(msg.get_content_type(), ContentType::RAW as u8);
+        msg.set_content_type(ContentType::JSON as u8);
+        let bytes = msg.get_ref();
+        assert_eq!(bytes[..MAGIC_SIZE], MAGIC);
+        assert_eq!(bytes[VERSION_OFFSET], VERSION);
+        assert_eq!(bytes[FLAGS_OFFSET], 0x80);
+        assert_eq!(bytes[TYPE_OFFSET], MessageType::TEXT as u8);
+        assert_eq!(bytes[CONTENT_TYPE_OFFSET], ContentType::JSON as u8);
+        assert_eq!(bytes[CONTENT_SIZE_OFFSET..MSG_ID_OFFSET], 2u64.to_be_bytes());
+        assert_eq!(msg.type_display(), "TEXT");
+        msg.init();
+        assert_eq!(msg.get_ref()[..MAGIC_SIZE], MAGIC);
+        assert_eq!(msg.get_flags(), 0);
+    }
     #[test]
     fn construction() {
         // let mut msg = Message::new();
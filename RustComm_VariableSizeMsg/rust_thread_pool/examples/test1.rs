@@ -30,7 +30,7 @@ pub fn test_message_in_pool(
         let msg = bq.de_q();
         print!("\n  deQed {:<12} : {:?}", msg, id);
         thread::yield_now();
-        if !run.load(Ordering::Relaxed) && bq.len() == 0 {
+        if !run.load(Ordering::Relaxed) && bq.is_empty() {
             break;
         }
         // thread::yield_now();
@@ -79,7 +79,7 @@ fn test0() {
 
     /*-- main thread enqueues messages --*/
     for i in 0..5 {
-        let msg = format!("msg #{}", i.to_string());
+        let msg = format!("msg #{}", i);
         print!("\n  enqueued {:?} on main thread", msg);
         flush();
         share2.en_q(msg);
@@ -32,9 +32,11 @@ where M: Send + 'static
       - pass processing function that accepts
         ThreadPool blocking queue and stopping
         signal
+      - each thread gets its own clone of f, so f
+        may capture state, e.g., configuration
     */
     pub fn new<F>(nt:u8, f:F) -> ThreadPool<M> 
-    where F: FnOnce(&BlockingQueue<M>, &Arc<AtomicBool>) -> () + Send + 'static + Copy
+    where F: FnOnce(&BlockingQueue<M>, &Arc<AtomicBool>) + Send + 'static + Clone
     {
         let run_ref = Arc::new(AtomicBool::new(true));
         let siqm = Arc::new(BlockingQueue::<M>::new());
@@ -48,6 +50,7 @@ where M: Send + 'static
             */
             let siq = Arc::clone(&siqm);
             let run = Arc::clone(&run_ref);
+            let f = f.clone();
             let handle = std::thread::spawn( move || { 
                 f(&siq, &run);  // thread_pool_processing
             });
@@ -102,7 +105,7 @@ mod tests {
     use super::*;
     #[test]
     fn test_new() {
-        let test = |bq:&BlockingQueue<String>| { 
+        let test = |bq:&BlockingQueue<String>, _run:&Arc<AtomicBool>| { 
             let msg = bq.de_q();
             print!("\n  {:?}", msg);
         };
@@ -8,7 +8,7 @@ use rust_timer::{StopWatch, stop_watch, timer, date_time_stamp, date_stamp, time
 fn do_work(max:usize) -> u128 {
   let mut sw = StopWatch::new();
   sw.start();
-  let mut val:f64 = 3.1415927;
+  let mut val:f64 = std::f64::consts::PI;
   let delta:f64 = 0.05;
   for _i in 0..max {
     val = 1.0 + val/(1.0 + delta);
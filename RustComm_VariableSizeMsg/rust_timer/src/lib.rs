@@ -19,6 +19,11 @@ pub struct StopWatch {
     start: Instant,
     elapsed: Duration,
 }
+impl Default for StopWatch {
+    fn default() -> StopWatch {
+        StopWatch::new()
+    }
+}
 impl StopWatch {
     pub fn new() -> StopWatch {
         StopWatch {
@@ -67,24 +72,22 @@ pub fn stop_watch(millisec: u64) {
 #[derive(Debug, Clone, Copy)]
 pub struct Timer {
     start: Instant,
-    elapsed: Duration,
 }
 impl Timer {
-    pub fn new(time: u64) -> Timer {
+    /*-- time is given to start, kept here for existing callers --*/
+    pub fn new(_time: u64) -> Timer {
         Timer {
             start: Instant::now(),
-            elapsed: Duration::new(time, 0),
         }
     }
     pub fn start<F>(&mut self, time: u64, callback:F) -> JoinHandle<()>
         where F:FnOnce() + Send + 'static {
         self.start = Instant::now();
-        let handle = thread::spawn(move || {
+        thread::spawn(move || {
             let ttw = Duration::from_millis(time);
             thread::sleep(ttw);
             callback();
-        });
-        handle
+        })
     }
 }
 /*-----------------------------------------------
@@ -106,7 +109,7 @@ use chrono::{DateTime, Local, Datelike, Timelike};
 pub fn date_time_stamp() -> String {
     let now: DateTime<Local> = Local::now();
     /* format DateTime string using chrono formatting */
-    let mut now_str = format!("{}", now.to_rfc2822());
+    let mut now_str = now.to_rfc2822();
     /* remove trailing -0400 */
     now_str.truncate(now_str.len() - 6);
     now_str
@@ -0,0 +1,58 @@
+/////////////////////////////////////////////////////////////
+// async_traits.rs - async versions of the comm traits     //
+//                                                         //
+/////////////////////////////////////////////////////////////
+/*
+   Defines, with the async feature:
+   - AsyncSndr<M>
+   - AsyncRcvr<M>
+   - AsyncProcess<M>
+   used by rust_comm's AsyncConnector and AsyncListener.
+
+   They mirror Sndr, Rcvr, and Process, and implementations
+   must use the same framing, so async and blocking
+   endpoints can talk to each other.  Streams are any tokio
+   AsyncWrite or AsyncRead, usually a buffered half of a
+   tokio TcpStream.
+*/
+
+use crate::*;
+use std::future::Future;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+pub trait AsyncSndr<M> : Send
+where M: Msg + Clone + Send + Sync + Default,
+{
+    /*-- writes msg, flushing for the types buf_send_message flushes --*/
+    fn async_send_message<W>(msg: &M, stream: &mut W) -> impl Future<Output = CommResult<()>> + Send
+    where W: AsyncWrite + Unpin + Send;
+}
+/*-- rejects bodies larger than max before allocating, as Rcvr does --*/
+pub trait AsyncRcvr<M> : Send
+where M: Msg + Clone + Send + Sync + Default,
+{
+    fn async_recv_message_max<R>(
+        stream: &mut R, max: usize
+    ) -> impl Future<Output = CommResult<M>> + Send
+    where R: AsyncRead + Unpin + Send;
+
+    fn async_recv_message<R>(stream: &mut R) -> impl Future<Output = CommResult<M>> + Send
+    where R: AsyncRead + Unpin + Send
+    {
+        Self::async_recv_message_max(stream, DEFAULT_MAX_MSG_SIZE)
+    }
+}
+/*---------------------------------------------------------
+  AsyncProcess<M> handles each message received by an
+  AsyncListener, with the same per connection cloning as
+  Process<M>
+  - may await, e.g., another service, without holding a
+    thread
+*/
+pub trait AsyncProcess<M> : Send
+where M: Msg + Clone + Send + Sync + Default,
+{
+    fn async_process_message(
+        &mut self, msg: M, ctx: &mut ConnContext
+    ) -> impl Future<Output = Vec<M>> + Send;
+}
@@ -11,11 +11,23 @@
    - Sndr<M>
    - Rcvr<M>
    - Process<M>
+   and ConnContext, connection information for Process<M>,
+   including its ClientId,
+   and CommError, the error type of fallible operations.
+
+   The async feature adds AsyncSndr<M>, AsyncRcvr<M>, and
+   AsyncProcess<M>, see async_traits.rs.
 */
 
-use std::net::{TcpStream};
-use std::io::{BufReader, BufWriter, Result};
+#[cfg(feature = "async")]
+mod async_traits;
+#[cfg(feature = "async")]
+pub use async_traits::*;
+
+use std::net::SocketAddr;
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::str::Utf8Error;
+use std::fmt;
 // use rust_blocking_queue::*;
 
 // pub const MSG_SIZE:usize = 4096;
@@ -36,6 +48,10 @@ pub trait Msg : Send + std::fmt::Debug {
     fn is_empty(&self) -> bool;
     fn set_type(&mut self, mt:u8);
     fn get_type(&self) -> u8;
+    fn set_flags(&mut self, flags:u8);
+    fn get_flags(&self) -> u8;
+    fn set_content_type(&mut self, ct:u8);
+    fn get_content_type(&self) -> u8;
     fn set_content_bytes(&mut self, buff: &[u8]);
     fn get_content_bytes(&self) -> &[u8];
     fn set_content_str(&mut self, s: &str);
@@ -43,6 +59,10 @@ pub trait Msg : Send + std::fmt::Debug {
     fn show_message(&self, fold:usize);
     fn set_content_size(&mut self, sz:usize);
     fn get_content_size(&self) -> usize;
+    fn set_msg_id(&mut self, id:u64);
+    fn get_msg_id(&self) -> u64;
+    fn set_correlation_id(&mut self, id:u64);
+    fn get_correlation_id(&self) -> u64;
     fn set_bytes(&mut self, buff:&[u8]);
     fn get_bytes(&self) -> &[u8];
     fn get_mut_bytes(&mut self) -> &mut [u8];
@@ -50,27 +70,214 @@ pub trait Msg : Send + std::fmt::Debug {
     fn get_mut_ref(&mut self) -> &mut Vec<u8>;
     fn type_display(&self) -> String;
 }
+/*---------------------------------------------------------
+  CommError - why a rust_comm operation failed
+  - variants caused by an io::Error keep it as source
+*/
+#[derive(Debug)]
+pub enum CommError {
+    /*-- could not connect to remote address --*/
+    Connect(std::io::Error),
+    /*-- could not bind listening address --*/
+    Bind(std::io::Error),
+    /*-- received bytes don't form a valid message --*/
+    Framing(String),
+    /*-- message larger than the receiver accepts --*/
+    OversizeFrame { size: usize, max: usize },
+    /*-- frame doesn't start with a known header prefix --*/
+    BadMagic([u8; 2]),
+    /*-- frame header has a protocol version this build can't read --*/
+    UnsupportedVersion(u8),
+    /*-- frame's checksum doesn't match its contents, size is the whole frame --*/
+    ChecksumMismatch { expected: u32, actual: u32, size: usize },
+    /*-- peer closed the connection between messages --*/
+    PeerClosed,
+    /*-- operation did not complete in time --*/
+    Timeout,
+    /*-- endpoint is shutting down or shut down --*/
+    Shutdown,
+    /*-- TLS configuration or handshake failed --*/
+    Tls(String),
+    /*-- message body couldn't be serialized or deserialized --*/
+    Codec(String),
+    /*-- peer refused the request, text is its reason --*/
+    Refused(String),
+    /*-- any other io failure --*/
+    Io(std::io::Error),
+}
+pub type CommResult<T> = std::result::Result<T, CommError>;
+
+impl fmt::Display for CommError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommError::Connect(e) => write!(f, "connect failed: {}", e),
+            CommError::Bind(e) => write!(f, "bind failed: {}", e),
+            CommError::Framing(s) => write!(f, "framing error: {}", s),
+            CommError::OversizeFrame { size, max } => {
+                write!(f, "frame of {} bytes exceeds maximum of {}", size, max)
+            }
+            CommError::BadMagic(b) => {
+                write!(f, "bad magic {:02x}{:02x}, not a rust_comm frame", b[0], b[1])
+            }
+            CommError::UnsupportedVersion(v) => {
+                write!(f, "unsupported protocol version {}", v)
+            }
+            CommError::ChecksumMismatch { expected, actual, size } => write!(
+                f, "checksum mismatch in {} byte frame: expected {:08x}, got {:08x}",
+                size, expected, actual
+            ),
+            CommError::PeerClosed => write!(f, "peer closed connection"),
+            CommError::Timeout => write!(f, "timed out"),
+            CommError::Shutdown => write!(f, "shut down"),
+            CommError::Tls(s) => write!(f, "tls error: {}", s),
+            CommError::Codec(s) => write!(f, "codec error: {}", s),
+            CommError::Refused(s) => write!(f, "refused: {}", s),
+            CommError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+impl std::error::Error for CommError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CommError::Connect(e) | CommError::Bind(e) | CommError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+/*-- timeouts and closes have their own variants --*/
+impl From<std::io::Error> for CommError {
+    fn from(e: std::io::Error) -> CommError {
+        use std::io::ErrorKind::*;
+        match e.kind() {
+            WouldBlock | TimedOut => CommError::Timeout,
+            UnexpectedEof => CommError::PeerClosed,
+            _ => CommError::Io(e),
+        }
+    }
+}
+/*-- lets callers that return io::Result use ? on CommResult --*/
+impl From<CommError> for std::io::Error {
+    fn from(e: CommError) -> std::io::Error {
+        use std::io::ErrorKind::*;
+        match e {
+            CommError::Connect(e) | CommError::Bind(e) | CommError::Io(e) => e,
+            CommError::PeerClosed => std::io::Error::new(UnexpectedEof, e),
+            CommError::Timeout => std::io::Error::new(TimedOut, e),
+            CommError::Shutdown => std::io::Error::new(NotConnected, e),
+            CommError::Refused(_) => std::io::Error::other(e),
+            _ => std::io::Error::new(InvalidData, e),
+        }
+    }
+}
+/*---------------------------------------------------------
+  Sndr<M> and Rcvr<M> frame messages on any byte stream,
+  e.g., TcpStream or the halves of a rust_comm Transport
+  connection
+*/
 pub trait Sndr<M> : Send 
 where M: Msg + Clone + Send + Default,
 {
-    fn send_message(msg: &M, stream: &mut TcpStream) -> Result<()>;
-    fn buf_send_message(msg: &M, stream: &mut BufWriter<TcpStream>) -> Result<()>;
+    fn send_message<W: Write>(msg: &M, stream: &mut W) -> CommResult<()>;
+    fn buf_send_message<W: Write>(msg: &M, stream: &mut BufWriter<W>) -> CommResult<()>;
+    /*-- bytes send_message writes, for non-blocking writers --*/
+    fn encode(msg: &M) -> Vec<u8>;
 }
+/*---------------------------------------------------------
+  Largest message body receivers accept unless told
+  otherwise, see Rcvr
+*/
+pub const DEFAULT_MAX_MSG_SIZE: usize = 64 * 1024 * 1024;
+
+/*---------------------------------------------------------
+  Rcvr<M> implementations take a max, the largest body
+  they accept, and must reject a header announcing more
+  with OversizeFrame before allocating for the body, so a
+  hostile or corrupted header can't exhaust memory
+  - recv_message, buf_recv_message, and decode use
+    DEFAULT_MAX_MSG_SIZE
+*/
 pub trait Rcvr<M>: Send 
 where M: Msg + Clone + Send + Default,
 {
-    fn recv_message(stream: &mut TcpStream) -> Result<M>;
-    fn buf_recv_message(stream: &mut BufReader<TcpStream>) -> Result<M>;
+    fn recv_message_max<R: Read>(stream: &mut R, max: usize) -> CommResult<M>;
+    fn buf_recv_message_max<R: Read>(stream: &mut BufReader<R>, max: usize) -> CommResult<M>;
+    /*-----------------------------------------------------
+      For non-blocking readers: the message at the front
+      of buf and the number of bytes it used, or None if
+      buf doesn't yet hold a whole message
+    */
+    fn decode_max(buf: &[u8], max: usize) -> CommResult<Option<(M, usize)>>;
+
+    fn recv_message<R: Read>(stream: &mut R) -> CommResult<M> {
+        Self::recv_message_max(stream, DEFAULT_MAX_MSG_SIZE)
+    }
+    fn buf_recv_message<R: Read>(stream: &mut BufReader<R>) -> CommResult<M> {
+        Self::buf_recv_message_max(stream, DEFAULT_MAX_MSG_SIZE)
+    }
+    fn decode(buf: &[u8]) -> CommResult<Option<(M, usize)>> {
+        Self::decode_max(buf, DEFAULT_MAX_MSG_SIZE)
+    }
+}
+/*---------------------------------------------------------
+  ClientId - a Listener's name for one client connection,
+  not reused while the Listener runs
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClientId(pub u64);
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "client {}", self.0)
+    }
+}
+/*---------------------------------------------------------
+  ConnContext - per connection information handed to
+  Process<M>::process_message with each message
+*/
+#[derive(Debug, Clone, Default)]
+pub struct ConnContext {
+    /*-- address of connected peer, if known --*/
+    pub peer: Option<SocketAddr>,
+    /*-- messages received on this connection, including current --*/
+    pub msg_count: u64,
+    /*-- this connection's id, for pushing to it later, if it has one --*/
+    pub client: Option<ClientId>,
+}
+impl ConnContext {
+    pub fn new(peer: Option<SocketAddr>) -> ConnContext {
+        ConnContext {
+            peer,
+            msg_count: 0,
+            client: None,
+        }
+    }
 }
+/*---------------------------------------------------------
+  Process<M> handles each message received by a Listener
+  - each connection gets its own clone of the Listener's
+    processor, so state held in self is per connection;
+    share state across connections with Arc members
+  - returns replies, none, one, or many, which are sent
+    back in order
+*/
 pub trait Process<M> : Send 
 where M: Msg + Clone + Send + Default,
 {
-    fn process_message(m: &mut M);
+    fn process_message(&mut self, msg: M, ctx: &mut ConnContext) -> Vec<M>;
 }
 #[cfg(test)]
 mod tests {
+    use super::*;
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+    #[test]
+    fn comm_error_keeps_source() {
+        use std::error::Error;
+        let err = CommError::Connect(std::io::ErrorKind::ConnectionRefused.into());
+        assert!(err.source().is_some());
+        let eof: CommError = std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into();
+        assert!(matches!(eof, CommError::PeerClosed));
+    }
 }